@@ -1,13 +1,15 @@
 use eframe::{egui::{self, Key, Vec2}, App};
-use rodio::buffer::SamplesBuffer;
-use rodio::{OutputStream, OutputStreamHandle, Sink};
 use gilrs::Gilrs;
 use gilrs::ev::{Button, Axis};
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
 
 use emu::Gameboy;
 use emu::joypad::*;
+use emu::movie;
 
+use crate::audio::Audio;
 use crate::components::{control::Control, cpu::Cpu, display::Display, ppu::Ppu, cart::Cart};
+use crate::worker::{Command, Event, Worker};
 
 const BTN_A: Key 		= Key::Z;
 const BTN_B: Key 		= Key::X;
@@ -19,6 +21,8 @@ const DPAD_DOWN: Key 	= Key::ArrowDown;
 const DPAD_LEFT: Key 	= Key::ArrowLeft;
 const DPAD_RIGHT: Key 	= Key::ArrowRight;
 
+const REWIND: Key 		= Key::R;
+
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
 
@@ -32,14 +36,22 @@ enum DpadDir {
 }
 
 pub struct Debugger {
-	emu: Gameboy,
+	// the emulator runs on its own thread, so the UI and the sound don't hold each other up. It's dropped first, so the
+	// games are saved before the audio stops
+	worker: Worker,
+	audio: Audio,
 
-	_audio_stream: OutputStream,
-	stream_handle: OutputStreamHandle,
+	// what was last sent to the emulation thread, so only changes are sent
+	held: [u8; 2],
+	tilt: (f32, f32),
+	rewinding: bool,
 
 	gilrs: Gilrs,
+	rumble_effect: Option<Effect>,	// created the first time a game turns on the rumble motor
+	rumbling: bool,
 
 	display: Display,
+	link_display: Display,
 
 	control: Control,
 	cpu: Cpu,
@@ -59,26 +71,24 @@ impl Debugger {
 
 		let cart = vec![0u8; 32 * 1024];
 
-		let (stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-
-		let emu = Gameboy::new(cart, Box::new(move |buffer| {
-			while sink.len() > 2 {
-				std::thread::sleep(std::time::Duration::from_millis(1))
-			}
+		let audio = Audio::new();
 
-			sink.append(SamplesBuffer::new(2, 48000, buffer));
-		}));
+		let emu = Gameboy::new(cart, audio.callback()).expect("blank cart should always load");
 
 		Self {
-			emu: emu,
+			worker: Worker::spawn(emu),
+			audio,
 
-			_audio_stream: stream,
-			stream_handle: stream_handle,
+			held: [0; 2],
+			tilt: (0.0, 0.0),
+			rewinding: false,
 
 			gilrs: Gilrs::new().unwrap(),
+			rumble_effect: None,
+			rumbling: false,
 			
 			display: Display::new(cc),
+			link_display: Display::new(cc),
 
 			control: Control::new(),
 			cpu: Cpu::new(),
@@ -115,6 +125,43 @@ impl Debugger {
 		left_stick || btn_press
 	}
 
+	// forwards the cart's rumble motor to every gamepad with force feedback
+	fn set_rumble(&mut self, on: bool) {
+
+		if on == self.rumbling {
+			return;
+		}
+
+		self.rumbling = on;
+
+		if self.rumble_effect.is_none() {
+			let gamepads: Vec<_> = self.gilrs.gamepads()
+				.filter(|(_, gamepad)| gamepad.is_ff_supported())
+				.map(|(id, _)| id)
+				.collect();
+
+			if gamepads.is_empty() {
+				return;
+			}
+
+			// a short pulse on the strong motor, repeated until it's stopped
+			self.rumble_effect = EffectBuilder::new()
+				.add_effect(BaseEffect {
+					kind: BaseEffectType::Strong { magnitude: u16::MAX },
+					scheduling: Replay { play_for: Ticks::from_ms(50), ..Default::default() },
+					envelope: Default::default(),
+				})
+				.gamepads(&gamepads)
+				.finish(&mut self.gilrs)
+				.ok();
+		}
+
+		if let Some(effect) = &self.rumble_effect {
+			let _ = if on { effect.play() } else { effect.stop() };
+		}
+
+	}
+
 	fn is_keyboard_input_down(&mut self, key: Key, ctx: &egui::Context) -> bool {
 		ctx.input(|input| {
 			input.key_down(key)
@@ -135,15 +182,52 @@ impl Debugger {
 		let gp_dpad_down = self.is_gamepad_input_down(&Button::DPadDown, DpadDir::Down);
 		let kb_dpad_down = self.is_keyboard_input_down(DPAD_DOWN, ctx);
 
-		if gp_dpad_left || (kb_dpad_left && !kb_dpad_right) { self.emu.btn_down(GBInput::DPadLeft) } else { self.emu.btn_up(GBInput::DPadLeft) }
-		if gp_dpad_right || (kb_dpad_right && !kb_dpad_left) { self.emu.btn_down(GBInput::DPadRight) } else { self.emu.btn_up(GBInput::DPadRight) }
-		if gp_dpad_up || (kb_dpad_up && !kb_dpad_down) { self.emu.btn_down(GBInput::DPadUp) } else { self.emu.btn_up(GBInput::DPadUp) }
-		if gp_dpad_down || (kb_dpad_down && !kb_dpad_up) { self.emu.btn_down(GBInput::DPadDown) } else { self.emu.btn_up(GBInput::DPadDown) }
+		// (button, keyboard down, controller down)
+		let inputs = [
+			(GBInput::DPadLeft, kb_dpad_left && !kb_dpad_right, gp_dpad_left),
+			(GBInput::DPadRight, kb_dpad_right && !kb_dpad_left, gp_dpad_right),
+			(GBInput::DPadUp, kb_dpad_up && !kb_dpad_down, gp_dpad_up),
+			(GBInput::DPadDown, kb_dpad_down && !kb_dpad_up, gp_dpad_down),
+
+			(GBInput::BtnA, self.is_keyboard_input_down(BTN_A, ctx), self.is_gamepad_input_down(&Button::South, DpadDir::None)),
+			(GBInput::BtnB, self.is_keyboard_input_down(BTN_B, ctx), self.is_gamepad_input_down(&Button::East, DpadDir::None)),
+			(GBInput::BtnStart, self.is_keyboard_input_down(BTN_START, ctx), self.is_gamepad_input_down(&Button::Start, DpadDir::None)),
+			(GBInput::BtnSelect, self.is_keyboard_input_down(BTN_SELECT, ctx), self.is_gamepad_input_down(&Button::Select, DpadDir::None)),
+		];
+
+		let mut held = [0; 2];
+
+		for (btn, kb_down, gp_down) in inputs {
+			// with a second gameboy linked, the keyboard controls the first one and controllers control the second
+			let down = match self.control.linked {
+				true => [kb_down, gp_down],
+				false => [kb_down || gp_down, false],
+			};
+
+			for (held, down) in held.iter_mut().zip(down) {
+				if down {
+					*held |= movie::input_mask(btn);
+				}
+			}
+		}
 
-		if self.is_gamepad_input_down(&Button::South, DpadDir::None) || self.is_keyboard_input_down(BTN_A, ctx) { self.emu.btn_down(GBInput::BtnA) } else { self.emu.btn_up(GBInput::BtnA) }
-		if self.is_gamepad_input_down(&Button::East, DpadDir::None) || self.is_keyboard_input_down(BTN_B, ctx) { self.emu.btn_down(GBInput::BtnB) } else { self.emu.btn_up(GBInput::BtnB) }
-		if self.is_gamepad_input_down(&Button::Start, DpadDir::None) || self.is_keyboard_input_down(BTN_START, ctx) { self.emu.btn_down(GBInput::BtnStart) } else { self.emu.btn_up(GBInput::BtnStart) }
-		if self.is_gamepad_input_down(&Button::Select, DpadDir::None) || self.is_keyboard_input_down(BTN_SELECT, ctx) { self.emu.btn_down(GBInput::BtnSelect) } else { self.emu.btn_up(GBInput::BtnSelect) }
+		for (player, (held, sent)) in held.iter().zip(self.held).enumerate() {
+			if *held != sent {
+				self.worker.send(Command::Input { player, held: *held });
+			}
+		}
+
+		self.held = held;
+
+		// the right stick tilts carts with an accelerometer. Pushing it up tilts the top of the gameboy away
+		let (tilt_x, tilt_y) = self.gilrs.gamepads()
+			.find_map(|(_, g)| Some((g.axis_data(Axis::RightStickX)?.value(), g.axis_data(Axis::RightStickY)?.value())))
+			.unwrap_or((0.0, 0.0));
+
+		if (tilt_x, -tilt_y) != self.tilt {
+			self.tilt = (tilt_x, -tilt_y);
+			self.worker.send(Command::Tilt(tilt_x, -tilt_y));
+		}
 
 		ctx.input(|input| {
 			if input.key_pressed(Key::Escape) {
@@ -160,6 +244,9 @@ impl App for Debugger {
 		
 		self.handle_input(ctx);
 
+		// linked gameboys are shown side by side
+		let screens = if self.control.linked { 2.0 } else { 1.0 };
+
 		if self.just_changed_mode {
 			self.just_changed_mode = false;
 
@@ -167,25 +254,27 @@ impl App for Debugger {
 				ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(Vec2::new(1280.0, 720.0)));
 			} else {
 				ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(
-					Vec2::new(SCREEN_WIDTH as f32 * self.window_scale, SCREEN_HEIGHT as f32 * self.window_scale))
+					Vec2::new(SCREEN_WIDTH as f32 * self.window_scale * screens, SCREEN_HEIGHT as f32 * self.window_scale))
 				);
 			}
 		}
 
-		if !self.control.paused {
+		let rewinding = self.is_keyboard_input_down(REWIND, ctx) || self.is_gamepad_input_down(&Button::LeftTrigger, DpadDir::None);
 
-			'update:
-			while !self.emu.tick() {
-				for i in self.control.breakpoints.iter() {
-					if self.emu.cpu.pc == *i {
-						self.control.paused = true;
-						break 'update;
-					}
-				}
-			}
+		if rewinding != self.rewinding {
+			self.rewinding = rewinding;
+			self.worker.send(Command::Rewind(rewinding));
+		}
 
+		for event in self.worker.poll(self.debug_mode) {
+			match event {
+				Event::Breakpoint => self.control.paused = true,
+				Event::MovieRecorded(movie) => self.control.save_movie(&movie),
+			}
 		}
 
+		self.set_rumble(!self.control.paused && self.worker.snapshot.rumble);
+
 		if self.debug_mode {
 			egui::SidePanel::left("left_pannel").show(ctx, |ui| {
 				
@@ -193,19 +282,22 @@ impl App for Debugger {
 				
 				ui.separator();
 
-				self.control.show(ctx, ui, &mut self.emu, &mut self.stream_handle);
-				
-				ui.separator();
-				
-				self.cpu.show(ctx, ui, &mut self.emu);
-				
-				ui.separator();
-				
-				self.ppu.show(ctx, ui, &mut self.emu);
+				self.control.show(ctx, ui, &self.worker, &self.audio);
 
-				ui.separator();
+				// the debug info arrives with the first snapshot after the panels open
+				if let Some(debug) = &self.worker.snapshot.debug {
+					ui.separator();
+
+					self.cpu.show(ctx, ui, &debug.cpu);
+
+					ui.separator();
 
-				self.cart.show(ctx, ui, &mut self.emu);
+					self.ppu.show(ctx, ui, &debug.ppu);
+
+					ui.separator();
+
+					self.cart.show(ctx, ui, &debug.header);
+				}
 
 			});
 			
@@ -215,7 +307,9 @@ impl App for Debugger {
 				
 				ui.separator();
 				
-				self.ppu.vram_viewer(ctx, ui, &mut self.emu);
+				if let Some(debug) = &self.worker.snapshot.debug {
+					self.ppu.vram_viewer(ctx, ui, &debug.tile_data);
+				}
 				
 			});
 
@@ -224,7 +318,7 @@ impl App for Debugger {
 			egui::TopBottomPanel::top("top_bar").show(ctx, |ui| {
 				ui.horizontal(|ui| {
 					
-					self.control.show_start_speed(ui, &mut self.emu);
+					self.control.show_start_speed(ui, &self.worker);
 					
 					if ui.button(format!("Scale: {}x", self.window_scale)).clicked() {
 						self.window_scale = match self.window_scale as u32 {
@@ -235,13 +329,17 @@ impl App for Debugger {
 						};
 						
 						ctx.send_viewport_cmd(
-							egui::ViewportCommand::InnerSize(Vec2::new(SCREEN_WIDTH as f32 * self.window_scale, SCREEN_HEIGHT as f32 * self.window_scale))
+							egui::ViewportCommand::InnerSize(Vec2::new(SCREEN_WIDTH as f32 * self.window_scale * screens, SCREEN_HEIGHT as f32 * self.window_scale))
 						);
 						
 						
 					}
 
-					self.control.show_select_rom(ui, &mut self.emu, &mut self.stream_handle);
+					self.control.show_select_rom(ui, &self.worker, &self.audio);
+
+					if self.control.show_link(ui, &self.worker) {
+						self.just_changed_mode = true;
+					}
 
 					if ui.button("Help").clicked() {
 						self.show_help = !self.show_help;
@@ -260,6 +358,7 @@ impl App for Debugger {
 							ui.label("Arrow keys: Dpad");
 							ui.label("Enter: Start");
 							ui.label("Backspace: Select");
+							ui.label("R: Rewind (hold)");
 
 							ui.separator();
 
@@ -270,6 +369,11 @@ impl App for Debugger {
 							ui.label("Left stick / Dpad: Dpad");
 							ui.label("Start: Start");
 							ui.label("Select / Share: Select");
+							ui.label("Left trigger: Rewind (hold)");
+
+							ui.separator();
+
+							ui.label("When a second ROM is linked, the keyboard controls the left screen and controllers control the right one.");
 
 						});
 					}
@@ -283,16 +387,27 @@ impl App for Debugger {
 		}
 
 		egui::CentralPanel::default().show(ctx, |ui| {
-			self.display.show(ctx, ui, &mut self.emu, self.control.scale, self.debug_mode)
+			let snapshot = &self.worker.snapshot;
+
+			match &snapshot.link_screen {
+				Some(link_screen) if self.debug_mode => {
+					ui.horizontal(|ui| {
+						self.display.show(ctx, ui, &snapshot.screen, self.control.scale, true);
+						self.link_display.show(ctx, ui, link_screen, self.control.scale, true);
+					});
+				},
+				Some(link_screen) => {
+					let rect = ui.ctx().available_rect();
+					let (left, right) = rect.split_left_right_at_fraction(0.5);
+
+					self.display.paint_at(ui, &snapshot.screen, left);
+					self.link_display.paint_at(ui, link_screen, right);
+				},
+				None => self.display.show(ctx, ui, &snapshot.screen, self.control.scale, self.debug_mode),
+			}
 		});
 
 		ctx.request_repaint();
 		
 	}
-}
-
-impl Drop for Debugger {
-	fn drop(&mut self) {
-		self.control.save_sram(&self.emu);
-	}
 }
\ No newline at end of file
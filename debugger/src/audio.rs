@@ -0,0 +1,108 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::time::Duration;
+
+use rodio::{OutputStream, Source};
+
+use emu::apu::{AudioCallback, SAMPLE_RATE};
+
+// buffers the emulator can get ahead of the speakers by, about 20ms
+const QUEUED_BUFFERS: usize = 2;
+
+// the speakers, fed with sample buffers from the emulation thread
+pub struct Audio {
+	_stream: OutputStream,
+	samples: SyncSender<Vec<f32>>,
+}
+
+impl Audio {
+
+	pub fn new() -> Self {
+
+		let (stream, stream_handle) = OutputStream::try_default().unwrap();
+		let (samples, receiver) = mpsc::sync_channel(QUEUED_BUFFERS);
+
+		stream_handle.play_raw(SampleQueue::new(receiver)).expect("unable to play audio");
+
+		Self {
+			_stream: stream,
+			samples,
+		}
+	}
+
+	// the callback blocks while the queue is full, which keeps the emulator running at the speed of the sound
+	pub fn callback(&self) -> AudioCallback {
+		let samples = self.samples.clone();
+
+		Box::new(move |buffer| {
+			let _ = samples.send(buffer.to_vec());
+		})
+	}
+
+}
+
+// plays silence whenever the emulator falls behind (or is paused), rather than waiting for it
+struct SampleQueue {
+	receiver: Receiver<Vec<f32>>,
+	buffer: Vec<f32>,
+	pos: usize,
+	right: bool,	// whether the next sample is for the right channel
+}
+
+impl SampleQueue {
+	fn new(receiver: Receiver<Vec<f32>>) -> Self {
+		Self {
+			receiver,
+			buffer: Vec::new(),
+			pos: 0,
+			right: false,
+		}
+	}
+}
+
+impl Iterator for SampleQueue {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+
+		// a new buffer can only start on a left sample, or the channels would swap
+		if self.pos >= self.buffer.len() && !self.right {
+			match self.receiver.try_recv() {
+				Ok(buffer) => {
+					self.buffer = buffer;
+					self.pos = 0;
+				},
+				Err(TryRecvError::Empty) => {},
+				Err(TryRecvError::Disconnected) => return None,
+			}
+		}
+
+		self.right = !self.right;
+
+		match self.buffer.get(self.pos) {
+			Some(sample) => {
+				self.pos += 1;
+				Some(*sample)
+			},
+			None => Some(0.0),
+		}
+
+	}
+}
+
+impl Source for SampleQueue {
+	fn current_frame_len(&self) -> Option<usize> {
+		None
+	}
+
+	fn channels(&self) -> u16 {
+		2
+	}
+
+	fn sample_rate(&self) -> u32 {
+		SAMPLE_RATE as u32
+	}
+
+	fn total_duration(&self) -> Option<Duration> {
+		None
+	}
+}
\ No newline at end of file
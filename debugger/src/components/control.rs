@@ -2,10 +2,15 @@ use std::fs;
 use eframe::egui::*;
 use native_dialog::FileDialog;
 
-use emu::Gameboy;
-use rodio::{buffer::SamplesBuffer, OutputStreamHandle, Sink};
+use emu::{Gameboy, Mode, Model};
+use emu::movie::{Movie, MovieStart};
+use emu::ppu::Renderer;
 
-pub const CPU_CLOCK: usize = 4194304;
+use crate::audio::Audio;
+use crate::worker::{Command, Worker};
+
+// how far back holding the rewind key can go
+const REWIND_SECONDS: usize = 30;
 
 pub struct Control {
 	pub paused: bool,
@@ -19,10 +24,16 @@ pub struct Control {
 	pub rom_index: usize,
 
 	pub enable_bootrom: bool,
+	model_override: Option<Model>,	// None picks the model from the cart header
+	renderer: Renderer,
 
 	pub breakpoints: Vec<u16>,
 	breakpoints_window_open: bool,
 	breakpoint_str: String,
+
+	// whether a second gameboy is connected over a link cable
+	pub linked: bool,
+	link_rom_path: String,
 }
 
 impl Control {
@@ -56,20 +67,25 @@ impl Control {
 			rom_index: 0,
 
 			enable_bootrom: false,
+			model_override: None,
+			renderer: Renderer::Scanline,
 
 			breakpoints: Vec::new(),
 			breakpoints_window_open: false,
 			breakpoint_str: String::new(),
+
+			linked: false,
+			link_rom_path: String::new(),
 		}
 	}
 
-	pub fn show(&mut self, ctx: &Context, ui: &mut Ui, emu: &mut Gameboy, stream_handle: &mut OutputStreamHandle) {
+	pub fn show(&mut self, ctx: &Context, ui: &mut Ui, worker: &Worker, audio: &Audio) {
 
 		ui.strong("Control");
 		
 		ui.horizontal(|ui| {
 			
-			self.show_start_speed(ui, emu);
+			self.show_start_speed(ui, worker);
 
 			if ui.button(format!("Scale: {}x", self.scale)).clicked() {
 				self.scale = match self.scale {
@@ -81,13 +97,11 @@ impl Control {
 			}
 
 			if ui.button("Step").clicked() {
-				for _ in 0..self.speed {
-					emu.tick();
-				}
+				worker.send(Command::Step);
 			}
 
 			if ui.button("Run scanline").clicked() {
-				emu.run_scanline();
+				worker.send(Command::RunScanline);
 			}
 
 			
@@ -99,21 +113,54 @@ impl Control {
 
 				self.rom_path = self.rom_list[self.rom_index].to_string();
 
-				self.save_sram(emu);
-				self.reset_emu(emu, stream_handle, self.enable_bootrom);
+				self.reset_emu(worker, audio, self.enable_bootrom);
 
 				self.old_rom_path = self.rom_path.clone();
 
 			}
 
-			self.show_select_rom(ui, emu, stream_handle);
+			self.show_select_rom(ui, worker, audio);
 
 			ui.checkbox(&mut self.enable_bootrom, "Enable Bootrom");
 
+			// takes effect the next time a rom is loaded
+			ComboBox::from_label("Hardware")
+				.selected_text(self.model_override.map_or("Auto", Model::name))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut self.model_override, None, "Auto");
+
+					for model in Model::ALL {
+						ui.selectable_value(&mut self.model_override, Some(model), model.name());
+					}
+				});
+
+			// switching renderers on the fly makes it easy to compare them
+			let renderer_changed = ComboBox::from_label("Renderer")
+				.selected_text(format!("{:?}", self.renderer))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut self.renderer, Renderer::Scanline, "Scanline").changed()
+						| ui.selectable_value(&mut self.renderer, Renderer::Fifo, "Fifo").changed()
+				})
+				.inner
+				.unwrap_or(false);
+
+			if renderer_changed {
+				worker.send(Command::Renderer(self.renderer));
+			}
+
+		});
+
+		// the other gameboy isn't part of the movie, so linked runs can't be played back
+		ui.add_enabled_ui(!self.linked, |ui| {
+			ui.horizontal(|ui| {
+				self.show_movie(ui, worker, audio);
+			});
 		});
 
 		ui.horizontal(|ui| {
 
+			self.show_link(ui, worker);
+
 			if ui.button("breakpoints").clicked() {
 				self.breakpoints_window_open = !self.breakpoints_window_open;
 			}
@@ -138,6 +185,7 @@ impl Control {
 
 					if let Some(i) = removed_breakpoint {
 						self.breakpoints.remove(i);
+						worker.send(Command::Breakpoints(self.breakpoints.clone()));
 					}
 
 					ui.horizontal(|ui| {
@@ -146,6 +194,7 @@ impl Control {
 							
 							if let Ok(breakpoint) = u16::from_str_radix(&self.breakpoint_str, 16) {
 								self.breakpoints.push(breakpoint);
+								worker.send(Command::Breakpoints(self.breakpoints.clone()));
 							} else {
 								eprintln!("[ERROR] Unable to parse breakpoint {}", self.breakpoint_str);
 							}
@@ -162,7 +211,7 @@ impl Control {
 
 	}
 
-	pub fn show_select_rom(&mut self, ui: &mut Ui, emu: &mut Gameboy, stream_handle: &mut OutputStreamHandle) {
+	pub fn show_select_rom(&mut self, ui: &mut Ui, worker: &Worker, audio: &Audio) {
 		if ui.button("Select ROM").clicked() {
 
 			let rom_path = FileDialog::new()
@@ -174,8 +223,7 @@ impl Control {
 			if let Some(rom) = rom_path {
 				self.rom_path = rom.to_str().unwrap().to_string();
 
-				self.save_sram(emu);
-				self.reset_emu(emu, stream_handle, self.enable_bootrom);
+				self.reset_emu(worker, audio, self.enable_bootrom);
 
 				self.old_rom_path = self.rom_path.clone();
 			}
@@ -183,9 +231,146 @@ impl Control {
 		}
 	}
 
-	pub fn show_start_speed(&mut self, ui: &mut Ui, emu: &mut Gameboy) {
+	pub fn show_movie(&mut self, ui: &mut Ui, worker: &Worker, audio: &Audio) {
+		if let Some(status) = worker.snapshot.movie {
+
+			// a recording comes back from the emulation thread to be saved
+			if ui.button("Stop movie").clicked() {
+				worker.send(Command::StopMovie);
+			}
+
+			let progress = match status.playing {
+				true => format!("Playing: frame {}/{}", status.frame, status.length),
+				false => format!("Recording: frame {}", status.frame),
+			};
+
+			ui.label(format!("{}, {} lag frames", progress, status.lag_frames));
+
+			if status.lagged {
+				ui.colored_label(Color32::RED, "Lag");
+			}
+
+			return;
+
+		}
+
+		if ui.button("Record movie").clicked() {
+			worker.send(Command::RecordMovie { from_power_on: false });
+		}
+
+		// the save file isn't part of the movie, so it's left out to make sure the game starts the same way on playback. The
+		// movie's game isn't saved either, so it can't overwrite the real save file
+		if ui.button("Record from power-on").clicked() {
+			self.start_emu(worker, audio, Some(worker.snapshot.model), self.enable_bootrom, false);
+
+			worker.send(Command::RecordMovie { from_power_on: true });
+		}
+
+		if ui.button("Play movie").clicked() {
+
+			let movie_path = FileDialog::new()
+				.set_location(std::env::current_dir().unwrap().as_path())
+				.add_filter("GB Movie", &["gbm"])
+				.show_open_single_file()
+				.unwrap();
+
+			if let Some(path) = movie_path {
+				let movie = match fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| Movie::from_bytes(&bytes).map_err(|e| e.to_string())) {
+					Ok(movie) => movie,
+					Err(e) => {
+						eprintln!("[ERROR] failed to open movie. Error: {}", e);
+						return;
+					}
+				};
+
+				if let MovieStart::PowerOn { bootrom, .. } = movie.start {
+					self.start_emu(worker, audio, Some(movie.model), bootrom, false);
+				}
+
+				worker.send(Command::PlayMovie(movie));
+			}
+
+		}
+	}
+
+	pub fn save_movie(&self, movie: &Movie) {
+		let movie_path = FileDialog::new()
+			.set_location(std::env::current_dir().unwrap().as_path())
+			.add_filter("GB Movie", &["gbm"])
+			.show_save_single_file()
+			.unwrap();
+
+		if let Some(path) = movie_path {
+			if let Err(e) = fs::write(&path, movie.to_bytes()) {
+				eprintln!("[ERROR] failed to save movie. Error: {}", e);
+			}
+		}
+	}
+
+	// returns true if a second ROM was linked or unlinked
+	pub fn show_link(&mut self, ui: &mut Ui, worker: &Worker) -> bool {
+		if self.linked {
+
+			if ui.button("Unlink").clicked() {
+				worker.send(Command::Link(None));
+				self.linked = false;
+
+				return true;
+			}
+
+		} else if ui.button("Link second ROM").clicked() {
+
+			let rom_path = FileDialog::new()
+				.set_location(std::env::current_dir().unwrap().as_path())
+				.add_filter("GB Rom", &["gb", "gbc"])
+				.show_open_single_file()
+				.unwrap();
+
+			if let Some(rom) = rom_path {
+				self.link_rom_path = rom.to_str().unwrap().to_string();
+
+				match fs::read(&self.link_rom_path) {
+					Ok(rom) => {
+						// only the first gameboy is audible
+						let mut link_emu = match Gameboy::new(rom, Box::new(|_| {})) {
+							Ok(link_emu) => link_emu,
+							Err(e) => {
+								eprintln!("[ERROR] failed to load rom. Error: {}", e);
+								return false;
+							}
+						};
+
+						link_emu.set_renderer(self.renderer);
+
+						if link_emu.bus().cart.is_battery_backed() {
+							if let Ok(sram) = fs::read(self.link_sram_path()) {
+								load_sram_file(&mut link_emu, sram, &self.link_sram_path());
+							}
+						}
+
+						worker.send(Command::Link(Some((Box::new(link_emu), self.link_sram_path()))));
+						self.linked = true;
+
+						return true;
+					},
+					Err(e) => eprintln!("[ERROR] failed to open rom. Error: {:?}", e),
+				}
+			}
+
+		}
+
+		false
+	}
+
+	// the second gameboy keeps its own save so the same game can be linked with itself (e.g. for trading)
+	fn link_sram_path(&self) -> String {
+		format!("{}.link.sav", self.link_rom_path)
+	}
+
+	pub fn show_start_speed(&mut self, ui: &mut Ui, worker: &Worker) {
 		if ui.button(if self.paused == true { "Start" } else { "Stop" }).clicked() {
 			self.paused = !self.paused;
+			worker.send(Command::Pause(self.paused));
 		}
 
 		if ui.button(format!("Speed: {}x", self.speed)).clicked() {
@@ -196,28 +381,22 @@ impl Control {
 				_ => 1
 			};
 
-			emu.bus.borrow_mut().apu.cpu_clock = CPU_CLOCK * self.speed as usize;
+			worker.send(Command::Speed(self.speed));
 		}
 	}
 
-	pub fn save_sram(&self, emu: &Gameboy) {
-		if emu.bus.borrow().cart.is_battery_backed() {
-			let save_path = format!("{}.sav", self.old_rom_path);
-			let sram = emu.bus.borrow().cart.dump_sram();
-
-			fs::write(save_path, &sram).expect("Oh no! Your progress couldn't be saved.");
-
-		}
+	fn save_path(&self) -> String {
+		format!("{}.sav", self.rom_path)
 	}
 
 	pub fn load_sram(&self, emu: &mut Gameboy) {
-		if emu.bus.borrow().cart.is_battery_backed() {
+		if emu.bus().cart.is_battery_backed() {
 
-			let save_path = format!("{}.sav", self.rom_path);
-			let sram_res = fs::read(save_path);
+			let save_path = self.save_path();
+			let sram_res = fs::read(&save_path);
 
 			if let Ok(sram) = sram_res {
-				emu.bus.borrow_mut().cart.load_sram(sram);
+				load_sram_file(emu, sram, &save_path);
 			} else {
 				println!("no save file present for {}", self.rom_path);
 			}
@@ -225,27 +404,54 @@ impl Control {
 		}
 	}
 
-	fn reset_emu(&mut self, emu: &mut Gameboy, stream_handle: &mut OutputStreamHandle, enable_bootrom: bool) {
+	fn reset_emu(&mut self, worker: &Worker, audio: &Audio, enable_bootrom: bool) {
+		self.start_emu(worker, audio, None, enable_bootrom, true);
+	}
+
+	// starts the rom from power-on. Without a model, the one picked in the "Hardware" dropdown or the cart header is used.
+	// The running gameboy saves its game before it's replaced. Without the save file, the new one's game is never saved
+	fn start_emu(&mut self, worker: &Worker, audio: &Audio, model: Option<Model>, enable_bootrom: bool, use_sram: bool) {
 
 		let rom_open = fs::read(self.rom_path.clone());
 
 		if let Ok(rom) = rom_open {
 
-			let sink = Sink::try_new(&stream_handle).unwrap();
+			let model = model.or(self.model_override).unwrap_or(Model::detect(&rom));
 
-			*emu = Gameboy::new(rom, Box::new(move |buffer| {
-				while sink.len() > 2 {
-					std::thread::sleep(std::time::Duration::from_millis(1))
+			// a bad ROM leaves whatever was running before alone
+			let mut emu = match Gameboy::with_model(rom, audio.callback(), model) {
+				Ok(new_emu) => new_emu,
+				Err(e) => {
+					eprintln!("[ERROR] failed to load rom. Error: {}", e);
+					self.rom_path = self.old_rom_path.clone();
+					return;
 				}
+			};
+
+			for warning in emu.header().warnings() {
+				eprintln!("[WARNING] {}: {}", self.rom_path, warning);
+			}
 
-				sink.append(SamplesBuffer::new(2, 48000, buffer));
-			}));
+			emu.set_renderer(self.renderer);
 
-			if let Ok(bootrom) = fs::read("roms/bootrom.gb") {
-				emu.load_bootrom(bootrom, enable_bootrom);
+			let bootrom_path = match model.mode() {
+				Mode::Dmg => "roms/bootrom.gb",
+				Mode::Cgb => "roms/cgb_bootrom.gb",
+			};
+
+			if let Ok(bootrom) = fs::read(bootrom_path) {
+				if let Err(e) = emu.load_bootrom(bootrom, enable_bootrom) {
+					eprintln!("[ERROR] failed to load {}. Error: {}", bootrom_path, e);
+				}
 			}
 
-			self.load_sram(emu);
+			if use_sram {
+				self.load_sram(&mut emu);
+			}
+
+			emu.set_rewind_length(REWIND_SECONDS);
+
+			worker.send(Command::Load { emu: Box::new(emu), save_path: use_sram.then(|| self.save_path()) });
 
 		} else {
 			eprintln!("[ERROR] failed to open rom. Error: {:?}", rom_open.unwrap_err());
@@ -253,4 +459,17 @@ impl Control {
 
 	}
 
+}
+
+// a save that doesn't fit the cart would be overwritten by the next save, so it's kept to one side instead
+fn load_sram_file(emu: &mut Gameboy, sram: Vec<u8>, save_path: &str) {
+	if let Err(e) = emu.bus_mut().cart.load_sram(sram) {
+		let backup_path = format!("{}.bak", save_path);
+
+		eprintln!("[ERROR] failed to load {}, moving it to {}. Error: {}", save_path, backup_path, e);
+
+		if let Err(e) = fs::rename(save_path, &backup_path) {
+			eprintln!("[ERROR] failed to move {}. Error: {}", save_path, e);
+		}
+	}
 }
\ No newline at end of file
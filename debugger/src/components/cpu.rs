@@ -1,6 +1,6 @@
 use eframe::egui::*;
 
-use emu::{cpu, Gameboy};
+use crate::worker::CpuState;
 
 pub struct Cpu;
 
@@ -10,49 +10,51 @@ impl Cpu {
 		Cpu {}
 	}
 
-	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, emu: &mut Gameboy) {
+	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, cpu: &CpuState) {
 
 		ui.strong("CPU");
 
 		ui.horizontal(|ui| {
 
-			ui.monospace(format!("AF: 0x{:04X}", emu.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::AF)));
-			ui.monospace(format!("BC: 0x{:04X}", emu.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::BC)));
-			ui.monospace(format!("DE: 0x{:04X}", emu.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::DE)));
-			ui.monospace(format!("HL: 0x{:04X}", emu.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::HL)));
+			ui.monospace(format!("AF: 0x{:04X}", cpu.af));
+			ui.monospace(format!("BC: 0x{:04X}", cpu.bc));
+			ui.monospace(format!("DE: 0x{:04X}", cpu.de));
+			ui.monospace(format!("HL: 0x{:04X}", cpu.hl));
 
 		});
 
 		ui.horizontal(|ui| {
 
-			ui.monospace(format!("SP: 0x{:04X}", emu.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::SP)));
-			ui.monospace(format!("PC: 0x{:04X}", emu.cpu.pc));
+			ui.monospace(format!("SP: 0x{:04X}", cpu.sp));
+			ui.monospace(format!("PC: 0x{:04X}", cpu.pc));
 
-			ui.monospace(format!("[HL]: 0x{:X}", emu.cpu.get_8bit_reg(emu::cpu::registers::Register8Bit::HL)));
+			ui.monospace(format!("[HL]: 0x{:X}", cpu.deref_hl));
 
-			ui.monospace(format!("Cycles: {}", emu.cycles));
+			ui.monospace(format!("Cycles: {}", cpu.cycles));
 
 		});
 
 		ui.horizontal(|ui| {
 
-			ui.monospace(format!("IME: {}", emu.cpu.ime));
-			ui.monospace(format!("HALT: {}", emu.cpu.halted));
+			ui.monospace(format!("IME: {}", cpu.ime));
+			ui.monospace(format!("HALT: {}", cpu.halted));
 
 		});
 
 		ui.horizontal(|ui| {
 
+			let [z, n, h, c] = cpu.flags;
+
 			ui.monospace(format!("Flags: {}{}{}{}", 
-				if emu.cpu.registers.get_flag(cpu::registers::Flag::Z) { "Z" } else { "_" },
-				if emu.cpu.registers.get_flag(cpu::registers::Flag::N) { "N" } else { "_" },
-				if emu.cpu.registers.get_flag(cpu::registers::Flag::H) { "H" } else { "_" },
-				if emu.cpu.registers.get_flag(cpu::registers::Flag::C) { "C" } else { "_" },
+				if z { "Z" } else { "_" },
+				if n { "N" } else { "_" },
+				if h { "H" } else { "_" },
+				if c { "C" } else { "_" },
 			))
 
 		});
 
-		ui.monospace(format!("Executed Instruction: {}", emu.cpu.last_instruction));
+		ui.monospace(format!("Executed Instruction: {}", cpu.last_instruction));
 
 	}
 
@@ -1,6 +1,8 @@
 use eframe::{egui::*, CreationContext};
 
-use emu::{Gameboy, ppu};
+use emu::ppu;
+
+use crate::worker::Screen;
 
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
@@ -24,27 +26,9 @@ impl Display {
 		}
 	}
 
-	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, emu: &mut Gameboy, scale: usize, debugger: bool) {
-
-		let mut display_buf = vec![Color32::default(); SCREEN_WIDTH * SCREEN_HEIGHT];
-
-		for (i, pixel) in emu.bus.borrow().ppu.get_frame().iter().enumerate() {
-
-			match pixel {
-				ppu::GBColour::White => display_buf[i] = Color32::from_rgb(0xFF, 0xFF, 0xFF),
-				ppu::GBColour::LightGrey => display_buf[i] = Color32::from_rgb(0xAA, 0xAA, 0xAA),
-				ppu::GBColour::DarkGrey => display_buf[i] = Color32::from_rgb(0x55, 0x55, 0x55),
-				ppu::GBColour::Black => display_buf[i] = Color32::from_rgb(0x0, 0x0, 0x0)
-			}
-
-		}
-
-		let colour_image = ColorImage {
-			size: [SCREEN_WIDTH, SCREEN_HEIGHT],
-			pixels: display_buf,
-		};
+	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, screen: &Screen, scale: usize, debugger: bool) {
 
-		self.screen_tex.set(colour_image, TextureOptions::NEAREST);
+		self.update_texture(screen);
 
 		let image = Image::new(&self.screen_tex);
         let image = image.maintain_aspect_ratio(true).fit_to_exact_size(vec2((SCREEN_WIDTH * scale) as f32, (SCREEN_WIDTH * scale) as f32));
@@ -58,4 +42,30 @@ impl Display {
 
 	}
 
+	// draws the screen into part of the window, used to show linked gameboys side by side
+	pub fn paint_at(&mut self, ui: &mut Ui, screen: &Screen, rect: Rect) {
+
+		self.update_texture(screen);
+
+		Image::new(&self.screen_tex).maintain_aspect_ratio(true).paint_at(ui, rect);
+
+	}
+
+	fn update_texture(&mut self, screen: &Screen) {
+
+		let display_buf = screen.pixels.iter().map(|pixel| {
+			let [r, g, b] = ppu::rgb555_to_rgb888(*pixel);
+
+			Color32::from_rgb(r, g, b)
+		}).collect();
+
+		let colour_image = ColorImage {
+			size: screen.size,
+			pixels: display_buf,
+		};
+
+		self.screen_tex.set(colour_image, TextureOptions::NEAREST);
+
+	}
+
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 use eframe::egui::*;
 
-use emu::{ppu, Gameboy};
+use emu::ppu;
+
+use crate::worker::PpuState;
 
 pub struct Ppu;
 
@@ -10,30 +12,26 @@ impl Ppu {
 		Ppu {}
 	}
 
-	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, emu: &mut Gameboy) {
+	pub fn show(&mut self, _ctx: &Context, ui: &mut Ui, ppu: &PpuState) {
 
 		ui.strong("PPU");
 
-		ui.monospace(format!("PPU Mode: {:?}", emu.bus.borrow().ppu.rendering_mode));
+		ui.monospace(format!("PPU Mode: {:?}", ppu.rendering_mode));
 
-		ui.monospace(format!("Line dots: {}", emu.bus.borrow().ppu.line_dots));
+		ui.monospace(format!("Line dots: {}", ppu.line_dots));
 
 		ui.horizontal(|ui| {
-			ui.monospace(format!("LY: {}", emu.bus.borrow().read_byte(0xFF44)));
-			ui.monospace(format!("LYC: {}", emu.bus.borrow().read_byte(0xFF45)));
+			ui.monospace(format!("LY: {}", ppu.ly));
+			ui.monospace(format!("LYC: {}", ppu.lyc));
 		});
 
-		ui.monospace(format!("STAT: {:#010b}", emu.bus.borrow().read_byte(0xFF41)));
-		ui.monospace(format!("LCDC: {:#010b}", emu.bus.borrow().read_byte(0xFF40)));
-		ui.monospace(format!("BGP:  {:#010b}", emu.bus.borrow().read_byte(0xFF47)));
+		ui.monospace(format!("STAT: {:#010b}", ppu.stat));
+		ui.monospace(format!("LCDC: {:#010b}", ppu.lcdc));
+		ui.monospace(format!("BGP:  {:#010b}", ppu.bgp));
 
 	}
 
-	pub fn vram_viewer(&mut self, ctx: &Context, ui: &mut Ui, emu: &mut Gameboy) {
-
-		emu.bus.borrow_mut().ppu.draw_tile_data();
-
-		let tile_data_buf = emu.bus.borrow().ppu.tile_data_buf.clone();
+	pub fn vram_viewer(&mut self, ctx: &Context, ui: &mut Ui, tile_data_buf: &[[u16; 8 * 8]]) {
 
 		let mut vram_viewer_buf: Vec<Color32> = vec![Color32::GREEN; 16 * 8 * 24 * 8];
 		
@@ -47,12 +45,9 @@ impl Ppu {
 						let global_x = x + 8 * col;
 						let global_y = y + 8 * row;
 
-						match tile[i] {
-							ppu::GBColour::White => vram_viewer_buf[global_x + 192 * global_y] = Color32::from_rgb(255, 255, 255),
-							ppu::GBColour::LightGrey => vram_viewer_buf[global_x + 192 * global_y] = Color32::from_rgb(128, 128, 128),
-							ppu::GBColour::DarkGrey => vram_viewer_buf[global_x + 192 * global_y] = Color32::from_rgb(64, 64, 64),
-							ppu::GBColour::Black => vram_viewer_buf[global_x + 192 * global_y] = Color32::from_rgb(0, 0, 0),
-						}
+						let [r, g, b] = ppu::rgb555_to_rgb888(tile[i]);
+
+						vram_viewer_buf[global_x + 192 * global_y] = Color32::from_rgb(r, g, b);
 					}
 				}
 			}
@@ -3,7 +3,9 @@ use eframe::{egui::{Vec2, ViewportBuilder}, run_native, NativeOptions};
 use app::Debugger;
 
 mod app;
+mod audio;
 mod components;
+mod worker;
 
 fn main() {
 
@@ -14,7 +16,7 @@ fn main() {
 
 	let native_options = NativeOptions {
 		viewport: viewport,
-		vsync: false,
+		vsync: true,
 		..Default::default()
 	};
 
@@ -0,0 +1,492 @@
+use std::fs;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use emu::{Gameboy, Model, RunResult, FRAME_CYCLES};
+use emu::cart::CartHeader;
+use emu::cpu::registers::{Flag, Register16Bit};
+use emu::joypad::GBInput;
+use emu::movie::{self, Movie, MovieStatus};
+use emu::ppu::{Renderer, RenderingMode};
+use emu::serial::DisconnectedPort;
+use emu::sgb::{SGB_WIDTH, SGB_HEIGHT};
+
+pub const CPU_CLOCK: usize = 4194304;
+
+// how long a frame lasts on hardware. Rewinding makes no sound for the audio queue to hold it back, so it's paced by this instead
+const FRAME_TIME: Duration = Duration::from_nanos(FRAME_CYCLES * 1_000_000_000 / CPU_CLOCK as u64);
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+// sent from the UI to the emulation thread
+pub enum Command {
+	Pause(bool),
+	Step,
+	RunScanline,
+	Speed(u8),
+	Breakpoints(Vec<u16>),
+	Renderer(Renderer),
+	Input { player: usize, held: u8 },	// buttons held as movie::input_mask bits. Player 1 is the linked gameboy
+	Tilt(f32, f32),
+	Rewind(bool),
+	Load { emu: Box<Gameboy>, save_path: Option<String> },	// replaces the running gameboy, saving the old one's game first
+	Link(Option<(Box<Gameboy>, String)>),
+	RecordMovie { from_power_on: bool },
+	PlayMovie(Movie),
+	StopMovie,
+	Snapshot { debug: bool },
+	Quit,
+}
+
+// things the UI has to act on
+pub enum Event {
+	Breakpoint,	// the emulator stopped itself
+	MovieRecorded(Movie),
+}
+
+enum Update {
+	Snapshot(Box<Snapshot>),
+	Event(Event),
+}
+
+pub struct Screen {
+	pub pixels: Vec<u16>,
+	pub size: [usize; 2],
+}
+
+// everything the UI shows about the emulator, copied out after a frame
+pub struct Snapshot {
+	pub screen: Screen,
+	pub link_screen: Option<Screen>,
+	pub model: Model,
+	pub movie: Option<MovieStatus>,
+	pub rumble: bool,	// whether the motor ran at any point since the last snapshot
+	pub debug: Option<DebugInfo>,
+}
+
+// only filled in when the debugger panels are open
+pub struct DebugInfo {
+	pub cpu: CpuState,
+	pub ppu: PpuState,
+	pub header: CartHeader,
+	pub tile_data: Vec<[u16; 8 * 8]>,
+}
+
+pub struct CpuState {
+	pub af: u16,
+	pub bc: u16,
+	pub de: u16,
+	pub hl: u16,
+	pub sp: u16,
+	pub pc: u16,
+	pub deref_hl: u8,
+	pub flags: [bool; 4],	// Z, N, H, C
+	pub ime: bool,
+	pub halted: bool,
+	pub cycles: u64,
+	pub last_instruction: String,
+}
+
+pub struct PpuState {
+	pub rendering_mode: RenderingMode,
+	pub line_dots: i32,
+	pub ly: u8,
+	pub lyc: u8,
+	pub stat: u8,
+	pub lcdc: u8,
+	pub bgp: u8,
+}
+
+// the UI's end of the emulation thread
+pub struct Worker {
+	commands: Sender<Command>,
+	updates: Receiver<Update>,
+	thread: Option<JoinHandle<()>>,
+
+	pub snapshot: Snapshot,	// the latest one received
+	waiting: bool,			// a snapshot has been asked for but hasn't arrived yet
+}
+
+impl Worker {
+
+	pub fn spawn(emu: Gameboy) -> Self {
+
+		let (commands, command_receiver) = mpsc::channel();
+		let (update_sender, updates) = mpsc::channel();
+
+		let mut emulation = Emulation {
+			emu: Box::new(emu),
+			save_path: None,
+			link_emu: None,
+			link_save_path: String::new(),
+
+			// the UI starts paused too
+			paused: true,
+			speed: 1,
+			breakpoints: Vec::new(),
+			held: [0; 2],
+			tilt: (0.0, 0.0),
+			rewinding: false,
+			rumbled: false,
+
+			snapshot_request: None,
+			updates: update_sender,
+		};
+
+		let snapshot = emulation.snapshot(false);
+
+		let thread = thread::Builder::new()
+			.name("emulation".to_string())
+			.spawn(move || emulation.run(command_receiver))
+			.expect("unable to start the emulation thread");
+
+		Self {
+			commands,
+			updates,
+			thread: Some(thread),
+
+			snapshot,
+			waiting: false,
+		}
+	}
+
+	pub fn send(&self, command: Command) {
+		// the thread only stops once it's told to quit, so this can't fail
+		let _ = self.commands.send(command);
+	}
+
+	// takes whatever the emulation thread has sent since the last call, and asks for another snapshot once the last one has
+	// arrived. Only one is asked for at a time, so the UI falling behind doesn't leave a backlog of frames
+	pub fn poll(&mut self, debug: bool) -> Vec<Event> {
+
+		let mut events = Vec::new();
+
+		while let Ok(update) = self.updates.try_recv() {
+			match update {
+				Update::Snapshot(snapshot) => {
+					self.snapshot = *snapshot;
+					self.waiting = false;
+				},
+				Update::Event(event) => events.push(event),
+			}
+		}
+
+		if !self.waiting {
+			self.send(Command::Snapshot { debug });
+			self.waiting = true;
+		}
+
+		events
+
+	}
+
+}
+
+// the games are saved once the thread finishes
+impl Drop for Worker {
+	fn drop(&mut self) {
+		self.send(Command::Quit);
+
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+// the emulation thread's state
+struct Emulation {
+	emu: Box<Gameboy>,
+	save_path: Option<String>,	// movie sessions have no save file, so they can't overwrite the real one
+
+	// second gameboy connected over a link cable
+	link_emu: Option<Box<Gameboy>>,
+	link_save_path: String,
+
+	paused: bool,
+	speed: u8,
+	breakpoints: Vec<u16>,
+	held: [u8; 2],
+	tilt: (f32, f32),
+	rewinding: bool,
+	rumbled: bool,
+
+	snapshot_request: Option<bool>,	// whether the debug info was asked for
+	updates: Sender<Update>,
+}
+
+impl Emulation {
+
+	fn run(mut self, commands: Receiver<Command>) {
+
+		'run: loop {
+
+			// nothing runs while paused, so there's nothing to do until the UI asks for something
+			if self.paused {
+				let Ok(command) = commands.recv() else { break 'run };
+
+				if !self.handle(command) {
+					break 'run;
+				}
+			}
+
+			loop {
+				match commands.try_recv() {
+					Ok(command) => if !self.handle(command) {
+						break 'run;
+					},
+					Err(TryRecvError::Empty) => break,
+					Err(TryRecvError::Disconnected) => break 'run,
+				}
+			}
+
+			if !self.paused {
+				self.run_frame();
+			}
+
+			if let Some(debug) = self.snapshot_request.take() {
+				let snapshot = self.snapshot(debug);
+				let _ = self.updates.send(Update::Snapshot(Box::new(snapshot)));
+			}
+
+		}
+
+		if let Some(save_path) = &self.save_path {
+			save_sram(&self.emu, save_path);
+		}
+
+		if let Some(link_emu) = &self.link_emu {
+			save_sram(link_emu, &self.link_save_path);
+		}
+
+	}
+
+	// returns false when the thread should stop
+	fn handle(&mut self, command: Command) -> bool {
+
+		match command {
+			Command::Pause(paused) => self.paused = paused,
+			Command::Step => {
+				for _ in 0..self.speed {
+					self.emu.tick();
+				}
+			},
+			Command::RunScanline => self.emu.run_scanline(),
+			Command::Speed(speed) => {
+				self.speed = speed;
+				self.emu.bus_mut().apu.cpu_clock = CPU_CLOCK * speed as usize;
+			},
+			Command::Breakpoints(breakpoints) => {
+				self.emu.set_breakpoints(breakpoints.clone());
+				self.breakpoints = breakpoints;
+			},
+			Command::Renderer(renderer) => {
+				self.emu.set_renderer(renderer);
+
+				if let Some(link_emu) = &mut self.link_emu {
+					link_emu.set_renderer(renderer);
+				}
+			},
+			Command::Input { player, held } => {
+				match (player, &mut self.link_emu) {
+					(0, _) => set_buttons(&mut self.emu, self.held[0], held),
+					(_, Some(link_emu)) => set_buttons(link_emu, self.held[1], held),
+					_ => {},
+				}
+
+				self.held[player] = held;
+			},
+			Command::Tilt(x, y) => {
+				self.tilt = (x, y);
+				self.emu.set_accelerometer(x, y);
+			},
+			Command::Rewind(rewinding) => self.rewinding = rewinding,
+			Command::Load { emu, save_path } => {
+				if let Some(save_path) = &self.save_path {
+					save_sram(&self.emu, save_path);
+				}
+
+				self.emu = emu;
+				self.save_path = save_path;
+
+				self.emu.bus_mut().apu.cpu_clock = CPU_CLOCK * self.speed as usize;
+				self.emu.set_breakpoints(self.breakpoints.clone());
+
+				// buttons held (and the tilt) carry over to the new gameboy
+				set_buttons(&mut self.emu, 0, self.held[0]);
+				self.emu.set_accelerometer(self.tilt.0, self.tilt.1);
+
+				if let Some(link_emu) = &mut self.link_emu {
+					self.emu.link_with(link_emu);
+				}
+			},
+			Command::Link(link) => {
+				if let Some(link_emu) = &self.link_emu {
+					save_sram(link_emu, &self.link_save_path);
+				}
+
+				match link {
+					Some((mut link_emu, save_path)) => {
+						set_buttons(&mut link_emu, 0, self.held[1]);
+						self.emu.link_with(&mut link_emu);
+
+						self.link_emu = Some(link_emu);
+						self.link_save_path = save_path;
+					},
+					None => {
+						self.link_emu = None;
+						self.emu.set_link_port(Box::new(DisconnectedPort));
+					},
+				}
+			},
+			Command::RecordMovie { from_power_on } => {
+				if let Err(e) = self.emu.record_movie(from_power_on) {
+					eprintln!("[ERROR] failed to start recording. Error: {}", e);
+				}
+			},
+			Command::PlayMovie(movie) => {
+				if let Err(e) = self.emu.play_movie(movie) {
+					eprintln!("[ERROR] failed to play movie. Error: {}", e);
+				}
+			},
+			Command::StopMovie => {
+				let recording = self.emu.movie_status().is_some_and(|status| !status.playing);
+
+				match self.emu.stop_movie() {
+					Some(movie) if recording => {
+						let _ = self.updates.send(Update::Event(Event::MovieRecorded(movie)));
+					},
+					_ => {},
+				}
+			},
+			Command::Snapshot { debug } => self.snapshot_request = Some(debug),
+			Command::Quit => return false,
+		}
+
+		true
+
+	}
+
+	fn run_frame(&mut self) {
+
+		// a linked gameboy can't be rewound without the other one, and rewinding a movie would desync it
+		if self.rewinding && self.link_emu.is_none() && self.emu.movie_status().is_none() {
+			let start = Instant::now();
+
+			// a frame back for every frame forward, so it rewinds at normal speed
+			self.emu.rewind_step();
+
+			thread::sleep(FRAME_TIME.saturating_sub(start.elapsed()));
+			return;
+		}
+
+		// the audio callback waits while its queue is full, so the emulator can't get far ahead of the sound. When sped up, the
+		// APU thinks the CPU is faster and makes fewer samples per frame, so more frames run in the same time
+		let result = match &mut self.link_emu {
+			// gamepads control the second gameboy when two are linked
+			Some(link_emu) => {
+				let (result, link_result) = self.emu.run_frame_linked(link_emu);
+
+				RunResult { rumble: link_result.rumble, ..result }
+			},
+			None => self.emu.run_frame(),
+		};
+
+		// games pulse the motor to vary its strength, so it counts as on if it was on at any point
+		self.rumbled |= result.rumble;
+
+		if result.breakpoint.is_some() {
+			self.paused = true;
+
+			let _ = self.updates.send(Update::Event(Event::Breakpoint));
+		}
+
+	}
+
+	fn snapshot(&mut self, debug: bool) -> Snapshot {
+		Snapshot {
+			screen: screen(&self.emu),
+			link_screen: self.link_emu.as_deref().map(screen),
+			model: self.emu.model(),
+			movie: self.emu.movie_status(),
+			rumble: std::mem::take(&mut self.rumbled),
+			debug: debug.then(|| self.debug_info()),
+		}
+	}
+
+	fn debug_info(&mut self) -> DebugInfo {
+
+		let emu = &mut self.emu;
+
+		emu.bus_mut().ppu.draw_tile_data();
+
+		let registers = &emu.cpu.registers;
+
+		let cpu = CpuState {
+			af: registers.get_16bit_reg(Register16Bit::AF),
+			bc: registers.get_16bit_reg(Register16Bit::BC),
+			de: registers.get_16bit_reg(Register16Bit::DE),
+			hl: registers.get_16bit_reg(Register16Bit::HL),
+			sp: registers.get_16bit_reg(Register16Bit::SP),
+			pc: emu.cpu.pc,
+			// a peek, since reading through the CPU would tick the rest of the system
+			deref_hl: emu.bus().read_byte(registers.get_16bit_reg(Register16Bit::HL)),
+			flags: [Flag::Z, Flag::N, Flag::H, Flag::C].map(|flag| registers.get_flag(flag)),
+			ime: emu.cpu.ime,
+			halted: emu.cpu.halted,
+			cycles: emu.cycles,
+			last_instruction: emu.cpu.last_instruction.clone(),
+		};
+
+		let bus = emu.bus();
+
+		let ppu = PpuState {
+			rendering_mode: bus.ppu.rendering_mode,
+			line_dots: bus.ppu.line_dots,
+			ly: bus.read_byte(0xFF44),
+			lyc: bus.read_byte(0xFF45),
+			stat: bus.read_byte(0xFF41),
+			lcdc: bus.read_byte(0xFF40),
+			bgp: bus.read_byte(0xFF47),
+		};
+
+		DebugInfo {
+			cpu,
+			ppu,
+			header: emu.header().clone(),
+			tile_data: bus.ppu.tile_data_buf.clone(),
+		}
+
+	}
+
+}
+
+// SGB games are shown with their border
+fn screen(emu: &Gameboy) -> Screen {
+	match emu.sgb_frame() {
+		Some(frame) => Screen { pixels: frame.to_vec(), size: [SGB_WIDTH, SGB_HEIGHT] },
+		None => Screen { pixels: emu.bus().ppu.get_frame().to_vec(), size: [SCREEN_WIDTH, SCREEN_HEIGHT] },
+	}
+}
+
+// presses and releases whatever changed between two sets of held buttons
+fn set_buttons(emu: &mut Gameboy, old: u8, new: u8) {
+	for btn in GBInput::ALL {
+		let mask = movie::input_mask(btn);
+
+		match (old & mask != 0, new & mask != 0) {
+			(false, true) => emu.btn_down(btn),
+			(true, false) => emu.btn_up(btn),
+			_ => {},
+		}
+	}
+}
+
+fn save_sram(emu: &Gameboy, save_path: &str) {
+	if emu.bus().cart.is_battery_backed() {
+		let sram = emu.bus().cart.dump_sram();
+
+		fs::write(save_path, &sram).expect("Oh no! Your progress couldn't be saved.");
+	}
+}
\ No newline at end of file
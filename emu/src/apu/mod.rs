@@ -6,6 +6,8 @@ use square::{SquareChannel1, SquareChannel2};
 use wave::WaveChannel;
 use noise::NoiseChannel;
 
+use crate::savestate::{StateReader, StateWriter, StateError};
+
 // Sample rate of 48Khz
 pub const SAMPLE_RATE: usize = 48000;
 
@@ -15,6 +17,9 @@ pub const BUFFER_SIZE: usize = 1024;
 // The rate at which the CPU is ticked.
 pub const CPU_CLOCK: usize = 4194304;
 
+// Called with interleaved stereo samples whenever the buffer fills up.
+pub type AudioCallback = Box<dyn Fn(&[f32]) + Send>;
+
 pub struct APU {
 
 	enabled: bool,
@@ -30,8 +35,9 @@ pub struct APU {
 
 	buffer: Box<[f32; BUFFER_SIZE]>,
 	pub buffer_pos: usize,
+	samples: usize,		// stereo samples generated since the last call to take_samples()
 
-	callback: Box<dyn Fn(&[f32])>,
+	callback: AudioCallback,
 
 	pub cpu_clock: usize,
 
@@ -47,7 +53,7 @@ pub struct APU {
 
 impl APU {
 
-	pub fn new(callback: Box<dyn Fn(&[f32])>) -> Self {
+	pub fn new(callback: AudioCallback) -> Self {
 		Self {
 			enabled: false,
 
@@ -60,6 +66,7 @@ impl APU {
 
 			buffer: Box::new([0.0; BUFFER_SIZE]),
 			buffer_pos: 0,
+			samples: 0,
 
 			callback: callback,
 
@@ -75,6 +82,20 @@ impl APU {
 		}
 	}
 
+	// the registers as the bootrom leaves them. The DMG bootroms finish with a note on channel 1, which has faded out by
+	// the time the cart starts but leaves the channel on
+	pub(crate) fn after_boot(&mut self, played_jingle: bool) {
+		self.write_byte(0xFF26, 0x80);
+
+		self.write_byte(0xFF10, 0x80);
+		self.write_byte(0xFF11, 0xBF);
+		self.write_byte(0xFF12, 0xF3);
+		self.write_byte(0xFF24, 0x77);
+		self.write_byte(0xFF25, 0xF3);
+
+		self.channel_1.enabled = played_jingle;
+	}
+
 	pub fn tick(&mut self, cycles: u64) -> bool {
 
 		let mut buffer_full = false;
@@ -173,6 +194,7 @@ impl APU {
 				}) / 4.0);
 
 				self.buffer_pos += 2;
+				self.samples += 1;
 			}
 
 			if self.buffer_pos >= BUFFER_SIZE {
@@ -188,6 +210,10 @@ impl APU {
 		buffer_full
 	}
 
+	pub fn take_samples(&mut self) -> usize {
+		std::mem::take(&mut self.samples)
+	}
+
 	pub fn read_byte(&self, addr: u16) -> u8 {
 		match addr {
 			// NR52: Audio Master Control
@@ -261,4 +287,60 @@ impl APU {
 		}
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+
+		state.write_u8(self.nr51);
+		state.write_u8(self.left_volume);
+		state.write_u8(self.right_volume);
+
+		state.write_bool(self.vin_left);
+		state.write_bool(self.vin_right);
+
+		for sample in self.buffer.iter() {
+			state.write_f32(*sample);
+		}
+		state.write_u32(self.buffer_pos as u32);
+
+		state.write_u32(self.sample_clock);
+		state.write_u8(self.frame_sequencer_pos);
+
+		self.channel_1.save_state(state);
+		self.channel_2.save_state(state);
+		self.channel_3.save_state(state);
+		self.channel_4.save_state(state);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+
+		self.nr51 = state.read_u8()?;
+		self.left_volume = state.read_u8()? & 0x7;
+		self.right_volume = state.read_u8()? & 0x7;
+
+		self.vin_left = state.read_bool()?;
+		self.vin_right = state.read_bool()?;
+
+		for sample in self.buffer.iter_mut() {
+			*sample = state.read_f32()?;
+		}
+
+		self.buffer_pos = state.read_u32()? as usize;
+
+		// samples are written in stereo pairs
+		if self.buffer_pos >= BUFFER_SIZE || self.buffer_pos & 1 != 0 {
+			return Err(StateError::InvalidValue("audio buffer position"));
+		}
+
+		self.sample_clock = state.read_u32()?;
+		self.frame_sequencer_pos = state.read_u8()? % 8;
+
+		self.channel_1.load_state(state)?;
+		self.channel_2.load_state(state)?;
+		self.channel_3.load_state(state)?;
+		self.channel_4.load_state(state)?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
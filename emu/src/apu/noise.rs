@@ -1,3 +1,5 @@
+use crate::savestate::{StateReader, StateWriter, StateError};
+
 #[derive(Default)]
 pub struct NoiseChannel {
 
@@ -102,7 +104,7 @@ impl NoiseChannel {
 			// NR43: frequency & randomness
 			0xFF22 => self.nr43,
 			// NR44: control register
-			0xFF23 => (self.length_enabled as u8) << 5,
+			0xFF23 => ((self.length_enabled as u8) << 6) | 0b1011_1111,
 
 			_ => unreachable!()
 		}
@@ -152,4 +154,44 @@ impl NoiseChannel {
 		}
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+
+		state.write_u16(self.lfsr);
+		state.write_u8(self.nr43);
+
+		state.write_u16(self.frequency_timer);
+
+		state.write_u8(self.initial_volume);
+		state.write_u8(self.current_volume);
+		state.write_bool(self.inc_volume);
+		state.write_u8(self.envelope_period);
+		state.write_u8(self.envelope_timer);
+
+		state.write_u16(self.length_timer);
+		state.write_bool(self.length_enabled);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+
+		self.lfsr = state.read_u16()?;
+		self.nr43 = state.read_u8()?;
+
+		self.frequency_timer = state.read_u16()?;
+
+		self.initial_volume = state.read_u8()?;
+		self.current_volume = state.read_u8()?;
+		self.inc_volume = state.read_bool()?;
+		self.envelope_period = state.read_u8()?;
+		self.envelope_timer = state.read_u8()?;
+
+		self.length_timer = state.read_u16()?;
+		self.length_enabled = state.read_bool()?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
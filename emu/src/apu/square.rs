@@ -1,3 +1,5 @@
+use crate::savestate::{StateReader, StateWriter, StateError};
+
 // table of all wave duty values
 const WAVE_DUTY: [[u8; 8]; 4] = [
     [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
@@ -222,6 +224,62 @@ impl SquareChannel1 {
 		}
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+
+		state.write_u8(self.duty_pattern as u8);
+		state.write_u8(self.wave_position as u8);
+
+		state.write_u16(self.frequency_timer);
+		state.write_u16(self.frequency);
+
+		state.write_u8(self.initial_volume);
+		state.write_u8(self.current_volume);
+		state.write_bool(self.inc_volume);
+		state.write_u8(self.envelope_period);
+		state.write_u8(self.envelope_timer);
+
+		state.write_u16(self.length_timer);
+		state.write_bool(self.length_enabled);
+
+		state.write_bool(self.sweep_enabled);
+		state.write_bool(self.dec_freq);
+		state.write_u8(self.sweep_period);
+		state.write_u8(self.sweep_timer);
+		state.write_u8(self.sweep_amount);
+		state.write_u16(self.old_freq);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+
+		self.duty_pattern = (state.read_u8()? & 0b11) as usize;
+		self.wave_position = (state.read_u8()? % 8) as usize;
+
+		self.frequency_timer = state.read_u16()?;
+		self.frequency = state.read_u16()?;
+
+		self.initial_volume = state.read_u8()?;
+		self.current_volume = state.read_u8()?;
+		self.inc_volume = state.read_bool()?;
+		self.envelope_period = state.read_u8()?;
+		self.envelope_timer = state.read_u8()?;
+
+		self.length_timer = state.read_u16()?;
+		self.length_enabled = state.read_bool()?;
+
+		self.sweep_enabled = state.read_bool()?;
+		self.dec_freq = state.read_bool()?;
+		self.sweep_period = state.read_u8()?;
+		self.sweep_timer = state.read_u8()?;
+		self.sweep_amount = state.read_u8()?;
+		self.old_freq = state.read_u16()?;
+
+		Ok(())
+	}
+
 }
 
 #[derive(Default)]
@@ -374,4 +432,46 @@ impl SquareChannel2 {
 		}
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+
+		state.write_u8(self.duty_pattern as u8);
+		state.write_u8(self.wave_position as u8);
+
+		state.write_u16(self.frequency_timer);
+		state.write_u16(self.frequency);
+
+		state.write_u8(self.initial_volume);
+		state.write_u8(self.current_volume);
+		state.write_bool(self.inc_volume);
+		state.write_u8(self.envelope_period);
+		state.write_u8(self.envelope_timer);
+
+		state.write_u16(self.length_timer);
+		state.write_bool(self.length_enabled);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+
+		self.duty_pattern = (state.read_u8()? & 0b11) as usize;
+		self.wave_position = (state.read_u8()? % 8) as usize;
+
+		self.frequency_timer = state.read_u16()?;
+		self.frequency = state.read_u16()?;
+
+		self.initial_volume = state.read_u8()?;
+		self.current_volume = state.read_u8()?;
+		self.inc_volume = state.read_bool()?;
+		self.envelope_period = state.read_u8()?;
+		self.envelope_timer = state.read_u8()?;
+
+		self.length_timer = state.read_u16()?;
+		self.length_enabled = state.read_bool()?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
@@ -1,3 +1,5 @@
+use crate::savestate::{StateReader, StateWriter, StateError};
+
 #[derive(Default)]
 pub struct WaveChannel {
 	pub enabled: bool,
@@ -131,4 +133,40 @@ impl WaveChannel {
 		}
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+
+		state.write_bytes(&self.wave_ram);
+		state.write_u8(self.wave_position as u8);
+
+		state.write_u8(self.output_level);
+		state.write_u8(self.volume_shift);
+
+		state.write_u16(self.frequency_timer);
+		state.write_u16(self.frequency);
+
+		state.write_u16(self.length_timer);
+		state.write_bool(self.length_enabled);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+
+		state.read_bytes(&mut self.wave_ram)?;
+		self.wave_position = (state.read_u8()? % 32) as usize;
+
+		self.output_level = state.read_u8()? & 0b11;
+		self.volume_shift = state.read_u8()?;
+
+		self.frequency_timer = state.read_u16()?;
+		self.frequency = state.read_u16()?;
+
+		self.length_timer = state.read_u16()?;
+		self.length_enabled = state.read_bool()?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
@@ -1,14 +1,15 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-
-use crate::cart::create_cart;
+use crate::cart::{create_cart, CartError};
+use crate::{Mode, Model};
+use crate::sgb::Sgb;
 
 use super::timer::Timer;
-use super::interrupt::Interrupt;
-use super::ppu::PPU;
-use super::apu::APU;
-use super::joypad::Joypad;
+use super::interrupt::{Interrupt, InterruptFlag};
+use super::ppu::{PPU, RenderingMode};
+use super::apu::{APU, AudioCallback};
+use super::joypad::{Joypad, GBInput};
+use super::serial::Serial;
 use super::cart::MBC;
+use super::savestate::{StateReader, StateWriter, StateError};
 
 // possible off-by-one error
 const ROM_BANK1_START: 		u16	= 0x0;
@@ -46,21 +47,46 @@ pub struct Bus {
 
 	pub cart: Box<dyn MBC>,
 	
-	pub intf: Rc<RefCell<Interrupt>>,
+	pub intf: Interrupt,
 	pub timer: Timer,
 	pub ppu: PPU,
 	pub apu: APU,
 	pub joypad: Joypad,
+	pub sgb: Option<Sgb>,	// only for games that support it
+	pub serial: Serial,
 	
+	// OAM DMA (0xFF46)
 	dma_src: u8,
+	dma_start_delay: u8,	// M-cycles until a newly requested transfer starts
+	dma_active: bool,		// OAM is being copied to, so the CPU can't use it
+	dma_base: u16,			// source address of the running transfer
+	dma_index: u8,			// next byte to copy
+	dma_byte: u8,			// last byte copied, which the CPU sees if it reads from the same bus
+
+	pub cgb: bool,			// CGB features are enabled (a CGB running a CGB game)
+	pub double_speed: bool,	// KEY1 bit 7
+	speed_switch_armed: bool,	// KEY1 bit 0: the next STOP switches speed
+
+	wram_bank: u8,			// 0xFF70: SVBK. Selects the WRAM bank at 0xD000-0xDFFF (CGB only)
+
+	// CGB VRAM DMA (0xFF51-0xFF55)
+	hdma_src: u16,
+	hdma_dst: u16,
+	hdma_len: u8,			// 16 byte blocks left to copy minus one, with bit 7 set while inactive
+	hdma_active: bool,		// copying one block per HBlank
+	cpu_stall: u64,			// T-states the CPU is held up for once its current access is done
+
+	audio_buffer_full: bool,	// the APU filled its buffer since the last call to take_audio_buffer_full()
 	
 	pub rom: [u8; 0x8000],
-	wram: [u8; 0x8000],
+	wram: [u8; 0x8000],		// 8 banks of 4k; DMG only uses the first two
 	hram: [u8; 0x7F],
 
 	pub bootrom: Vec<u8>,
 	pub bootrom_loaded: bool,
 
+	flat_memory: bool,		// every address is plain RAM, for running CPU tests
+
 	/*
 	rom_bank1: 		[u8; ROM_BANK1_END],							// fixed ROM bank from the cart
 	rom_bank2: 		[u8; ROM_BANK2_END - ROM_BANK2_START],			// swappable ROM bank from the cart
@@ -78,23 +104,56 @@ pub struct Bus {
 
 impl Bus {
 
-	pub fn new(rom: Vec<u8>, audio_callback: Box<dyn Fn(&[f32])>) -> Self {
+	pub fn new(rom: Vec<u8>, audio_callback: AudioCallback, model: Model) -> Result<Self, CartError> {
+
+		let mode = model.mode();
+
+		// a CGB only enables its new features for games that support them, and runs anything else in compatibility mode
+		let cgb_game = rom.get(0x143).is_some_and(|flag| flag & 0x80 != 0);
+		let cgb = mode == Mode::Cgb && cgb_game;
+		let compat = mode == Mode::Cgb && !cgb_game;
 
-		let intf = Rc::new(RefCell::new(Interrupt::default()));
+		// the SGB only listens to games with the SGB flag, and a licensee code that says to check it
+		let sgb_model = matches!(model, Model::Sgb | Model::Sgb2);
+		let sgb_game = rom.get(0x146) == Some(&0x03) && rom.get(0x14B) == Some(&0x33);
+		let sgb = sgb_model && sgb_game;
 
-		Bus {
+		let timer = Timer::new(model, &rom);
 
-			cart: create_cart(rom),
+		let mut bus = Bus {
+
+			cart: create_cart(rom)?,
 
 			memory: [0xFF; 64 * 1024],
 
-			intf: Rc::clone(&intf),
-			timer: Timer::new(Rc::clone(&intf)),
-			ppu: PPU::new(Rc::clone(&intf)),
+			intf: Interrupt::default(),
+			timer,
+			ppu: PPU::new(model, cgb, compat),
 			apu: APU::new(audio_callback),
-			joypad: Joypad::new(Rc::clone(&intf)),
+			joypad: Joypad::new(),
+			sgb: sgb.then(Sgb::new),
+			serial: Serial::new(cgb),
 
 			dma_src: 0,
+			dma_start_delay: 0,
+			dma_active: false,
+			dma_base: 0,
+			dma_index: 0,
+			dma_byte: 0xFF,
+
+			cgb,
+			double_speed: false,
+			speed_switch_armed: false,
+
+			wram_bank: 1,
+
+			hdma_src: 0,
+			hdma_dst: 0,
+			hdma_len: 0xFF,
+			hdma_active: false,
+			cpu_stall: 0,
+
+			audio_buffer_full: false,
 
 			rom: [0xFF; 0x8000],
 			wram: [0xFF; 0x8000],
@@ -102,34 +161,60 @@ impl Bus {
 
 			bootrom: Vec::new(),
 			bootrom_loaded: false,
-		}
+
+			flat_memory: false,
+		};
+
+		// what each model's bootrom leaves in the IO registers. The SGB's finishes with neither joypad row selected, having
+		// been talking to the SNES, and plays its jingle on the SNES rather than channel 1
+		bus.joypad.write(if sgb_model { 0x30 } else { 0x00 });
+		bus.apu.after_boot(!sgb_model);
+
+		// the bootrom waits for a VBlank without ever clearing IF
+		bus.intf.raise(InterruptFlag::VBlank);
+
+		Ok(bus)
 
 	}
 
 	pub fn read_byte(&self, addr: u16) -> u8 {
 
+		if self.flat_memory {
+			return self.memory[addr as usize];
+		}
+
 		return match addr {
 
 			0x0				..= 0xFF if self.bootrom_loaded => self.bootrom[addr as usize],
+			// the CGB bootrom is larger, and leaves a gap for the cart header
+			0x200			..= 0x8FF if self.bootrom_loaded && self.bootrom.len() > addr as usize => self.bootrom[addr as usize],
 
 			ROM_BANK1_START	..=	ROM_BANK2_END => self.cart.read(addr),
 
 			EXT_RAM_START	..= EXT_RAM_END => self.cart.read(addr),
 
-			WRAM_START		..=	WRAM_END => self.wram[(addr - WRAM_START) as usize],
+			WRAM_START		..=	WRAM_END => self.wram[self.wram_addr(addr)],
 
 			/* PPU addresses */
 			0x8000			..= 0x9FFF => self.ppu.read(addr),
 			0xFE00			..=	0xFE9F => self.ppu.read(addr),
 
-			0xFF00			=> self.joypad.read(),
+			0xFF00			=> self.joypad.read_player(self.sgb.as_ref().map_or(0, Sgb::player)),
+			0xFF01			|	0xFF02 => self.serial.read(addr),
 
 			0xFF46			=> self.dma_src,
 
 			0xFF40 			..= 0xFF4B => self.ppu.read(addr),
+			0xFF4F			|	0xFF68..=0xFF6B => self.ppu.read(addr),
+
+			/* CGB registers */
+			0xFF4D			=> if self.cgb { 0x7E | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8 } else { 0xFF },
+			0xFF55			=> if self.cgb { self.hdma_len } else { 0xFF },	// bit 7 is set while no HBlank DMA is running
+			0xFF51			..= 0xFF54 => 0xFF,
+			0xFF70			=> if self.cgb { 0xF8 | self.wram_bank } else { 0xFF },
 			
 			0xFF04			..= 0xFF07 => self.timer.read(addr),
-			0xFF0F			|	0xFFFF => self.intf.borrow().read(addr),
+			0xFF0F			|	0xFFFF => self.intf.read(addr),
 
 			0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.apu.read_byte(addr),
 
@@ -141,8 +226,10 @@ impl Bus {
 
 	pub fn write_byte(&mut self, addr: u16, write: u8) {
 
-		//self.memory[addr as usize] = write;
-		//return;
+		if self.flat_memory {
+			self.memory[addr as usize] = write;
+			return;
+		}
 
 		match addr {
 
@@ -150,22 +237,39 @@ impl Bus {
 
 			EXT_RAM_START	..= EXT_RAM_END => self.cart.write(addr, write),
 
-			WRAM_START		..=	WRAM_END => self.wram[(addr - WRAM_START) as usize] = write,
+			WRAM_START		..=	WRAM_END => self.wram[self.wram_addr(addr)] = write,
 
 			/* PPU addresses */
 			0x8000			..= 0x9FFF => self.ppu.write(addr, write),
 			0xFE00			..=	0xFE9F => self.ppu.write(addr, write),
 
-			0xFF00			=> self.joypad.write(write),
+			0xFF00			=> {
+				self.joypad.write(write);
+
+				if let Some(sgb) = self.sgb.as_mut() {
+					sgb.write_joypad(write);
+				}
+			},
+			0xFF01			|	0xFF02 => self.serial.write(addr, write),
 
 			0xFF46 => self.dma_transfer(write),
 
 			0xFF40			..= 0xFF4B => self.ppu.write(addr, write),
+			0xFF4F			|	0xFF68..=0xFF6B => self.ppu.write(addr, write),
+
+			/* CGB registers */
+			0xFF4D			if self.cgb => self.speed_switch_armed = write & 1 != 0,
+			0xFF51			if self.cgb => self.hdma_src = (self.hdma_src & 0xFF) | (write as u16) << 8,
+			0xFF52			if self.cgb => self.hdma_src = (self.hdma_src & 0xFF00) | (write & 0xF0) as u16,
+			0xFF53			if self.cgb => self.hdma_dst = (self.hdma_dst & 0xFF) | ((write & 0x1F) as u16) << 8,
+			0xFF54			if self.cgb => self.hdma_dst = (self.hdma_dst & 0xFF00) | (write & 0xF0) as u16,
+			0xFF55			if self.cgb => self.start_hdma(write),
+			0xFF70			if self.cgb => self.wram_bank = if write & 0x7 == 0 { 1 } else { write & 0x7 },
 
 			0xFF50 if self.bootrom_loaded => self.bootrom_loaded = false,
 
 			0xFF04			..= 0xFF07 => self.timer.write(addr, write),
-			0xFF0F			|	0xFFFF => self.intf.borrow_mut().write(addr, write),
+			0xFF0F			|	0xFFFF => self.intf.write(addr, write),
 
 			0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.apu.write_byte(addr, write),
 
@@ -174,6 +278,36 @@ impl Bus {
 			_ => self.memory[addr as usize] = write,
 		}
 
+		// a write to TIMA or TAC can overflow the timer, which raises its interrupt straight away
+		self.raise_interrupts();
+
+	}
+
+	// a read by the CPU. While OAM DMA is running the CPU can't reach OAM, and anything on the same bus as the DMA source returns the byte being copied instead
+	pub fn cpu_read(&self, addr: u16) -> u8 {
+		if self.dma_active {
+			if (0xFE00..=0xFEFF).contains(&addr) {
+				return 0xFF;
+			}
+
+			if self.dma_conflict(addr) {
+				return self.dma_byte;
+			}
+		}
+
+		self.read_byte(addr)
+	}
+
+	// a write by the CPU. Writes that would conflict with OAM DMA are lost
+	pub fn cpu_write(&mut self, addr: u16, write: u8) {
+		if self.dma_active && ((0xFE00..=0xFEFF).contains(&addr) || self.dma_conflict(addr)) {
+			return;
+		}
+
+		// only the CPU's own writes hold it up, not anything poked in through write_byte
+		self.cpu_stall = 0;
+
+		self.write_byte(addr, write)
 	}
 
 	pub fn read_register(&self, register: MemRegister) -> u8 {
@@ -184,16 +318,285 @@ impl Bus {
 		self.write_byte(register as u16, write)
 	}
 
-	pub fn dma_transfer(&mut self, src: u8) {
+	// advances everything except the CPU. The CPU calls this once per M-cycle so memory accesses line up with the rest of the system
+	pub fn tick(&mut self, cycles: u64) {
+
+		// in double speed mode the CPU, timer and serial port run twice as fast, while everything else keeps its normal speed
+		let slow_cycles = if self.double_speed { cycles / 2 } else { cycles };
+
+		self.timer.tick(cycles);
+
+		// OAM DMA copies a byte every M-cycle, so it speeds up with the CPU
+		for _ in 0..cycles / 4 {
+			self.dma_cycle();
+		}
+
+		self.audio_buffer_full |= self.apu.tick(slow_cycles);
+
+		let was_hblank = self.ppu.rendering_mode == RenderingMode::HBlank;
+		let was_vblank = self.ppu.rendering_mode == RenderingMode::VBlank;
+		self.ppu.tick(slow_cycles);
+
+		if !was_hblank && self.ppu.rendering_mode == RenderingMode::HBlank {
+			self.hblank_dma();
+		}
+
+		// the frame is finished once VBlank ends
+		if let Some(sgb) = self.sgb.as_mut() {
+			if was_vblank && self.ppu.rendering_mode != RenderingMode::VBlank {
+				sgb.frame_done(&self.ppu.get_frame());
+			}
+		}
+
+		self.serial.tick(cycles);
+		self.cart.tick(slow_cycles);
+
+		self.raise_interrupts();
+
+	}
 
+	// moves the interrupts the timer, PPU and serial port have raised into IF
+	fn raise_interrupts(&mut self) {
+		self.intf.raise_flags(self.timer.take_interrupts() | self.ppu.take_interrupts() | self.serial.take_interrupts());
+	}
+
+	pub fn btn_down(&mut self, input: GBInput) {
+		self.joypad.btn_down(input);
+		self.intf.raise(InterruptFlag::Joypad);
+	}
+
+	pub fn btn_up(&mut self, input: GBInput) {
+		self.joypad.btn_up(input);
+	}
+
+	pub fn take_audio_buffer_full(&mut self) -> bool {
+		std::mem::take(&mut self.audio_buffer_full)
+	}
+
+	// requests an OAM DMA transfer. It starts after a setup M-cycle, during which any transfer already running carries on
+	pub fn dma_transfer(&mut self, src: u8) {
 		self.dma_src = src;
+		self.dma_start_delay = 2;
+	}
+
+	pub fn dma_running(&self) -> bool {
+		self.dma_active
+	}
+
+	fn dma_cycle(&mut self) {
+
+		if self.dma_start_delay > 0 {
+			self.dma_start_delay -= 1;
+
+			if self.dma_start_delay == 0 {
+				self.dma_active = true;
+				self.dma_index = 0;
 
-		let addr: u16 = (src as u16) << 8;	// addr is src * 100
+				let base = (self.dma_src as u16) << 8;	// addr is src * 100
+				// there's nothing to copy from at 0xE000 and above, so those sources read from work RAM instead like echo RAM does
+				self.dma_base = if base >= 0xE000 { base - 0x2000 } else { base };
+			}
+		}
+
+		if !self.dma_active {
+			return;
+		}
+
+		if self.dma_index == 0xA0 {
+			self.dma_active = false;
+			return;
+		}
+
+		self.dma_byte = self.read_byte(self.dma_base + self.dma_index as u16);
+		self.ppu.write(0xFE00 + self.dma_index as u16, self.dma_byte);
+
+		self.dma_index += 1;
+
+	}
+
+	// true if the CPU accessing addr would fight the running OAM DMA for the same bus
+	fn dma_conflict(&self, addr: u16) -> bool {
+		let bus = self.bus_of(addr);
+
+		bus.is_some() && bus == self.bus_of(self.dma_base)
+	}
+
+	// the external bus connects the cart and work RAM, except on the CGB where work RAM has its own. IO and HRAM are internal to the CPU
+	fn bus_of(&self, addr: u16) -> Option<u8> {
+		match addr {
+			0x0000..=0x7FFF | 0xA000..=0xBFFF => Some(0),
+			0x8000..=0x9FFF => Some(1),
+			0xC000..=0xFDFF => Some(if self.cgb { 2 } else { 0 }),
+			_ => None,
+		}
+	}
+
+	// 0xC000-0xCFFF is always bank 0, 0xD000-0xDFFF is bank 1-7 depending on SVBK
+	fn wram_addr(&self, addr: u16) -> usize {
+		match addr {
+			0xC000..=0xCFFF => (addr - 0xC000) as usize,
+			_ => self.wram_bank as usize * 0x1000 + (addr - 0xD000) as usize,
+		}
+	}
+
+	// called by STOP. Returns true if the CPU changed speed
+	pub fn switch_speed(&mut self) -> bool {
+		if !self.cgb || !self.speed_switch_armed {
+			return false;
+		}
+
+		self.double_speed = !self.double_speed;
+		self.speed_switch_armed = false;
+
+		true
+	}
+
+	fn start_hdma(&mut self, write: u8) {
+
+		// writing with bit 7 cleared during an HBlank DMA stops it
+		if self.hdma_active && write & 0x80 == 0 {
+			self.hdma_active = false;
+			self.hdma_len |= 0x80;
+
+			return;
+		}
+
+		self.hdma_len = write & 0x7F;
+
+		match write & 0x80 != 0 {
+			// HBlank DMA: 16 bytes are copied at the start of every HBlank
+			true => self.hdma_active = true,
+			// general purpose DMA: everything is copied at once, and the CPU is halted for the 8 M-cycles (16 in double
+			// speed) each block would take
+			false => {
+				for _ in 0..=self.hdma_len {
+					self.hdma_copy_block();
 
-		for i in 0x0..0xA0 {
-			self.write_byte(0xFE00 + i, self.read_byte(addr + i))
+					self.cpu_stall += if self.double_speed { 64 } else { 32 };
+				}
+
+				self.hdma_len = 0xFF;
+			}
+		}
+
+	}
+
+	pub(crate) fn take_cpu_stall(&mut self) -> u64 {
+		std::mem::take(&mut self.cpu_stall)
+	}
+
+	// called when the PPU enters HBlank
+	pub fn hblank_dma(&mut self) {
+
+		if !self.hdma_active {
+			return;
 		}
 
+		self.hdma_copy_block();
+
+		if self.hdma_len == 0 {
+			self.hdma_active = false;
+			self.hdma_len = 0xFF;
+		} else {
+			self.hdma_len -= 1;
+		}
+
+	}
+
+	fn hdma_copy_block(&mut self) {
+		for i in 0..16 {
+			let byte = self.read_byte(self.hdma_src.wrapping_add(i));
+			self.ppu.write(0x8000 | (self.hdma_dst.wrapping_add(i) & 0x1FFF), byte);
+		}
+
+		self.hdma_src = self.hdma_src.wrapping_add(16);
+		self.hdma_dst = self.hdma_dst.wrapping_add(16) & 0x1FF0;
+	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&self.memory);
+		state.write_bytes(&self.wram);
+		state.write_bytes(&self.hram);
+
+		state.write_u8(self.dma_src);
+		state.write_u8(self.dma_start_delay);
+		state.write_bool(self.dma_active);
+		state.write_u16(self.dma_base);
+		state.write_u8(self.dma_index);
+		state.write_u8(self.dma_byte);
+		state.write_bool(self.bootrom_loaded);
+
+		state.write_bool(self.double_speed);
+		state.write_bool(self.speed_switch_armed);
+		state.write_u8(self.wram_bank);
+		state.write_u16(self.hdma_src);
+		state.write_u16(self.hdma_dst);
+		state.write_u8(self.hdma_len);
+		state.write_bool(self.hdma_active);
+
+		self.intf.save_state(state);
+		self.timer.save_state(state);
+		self.ppu.save_state(state);
+		self.apu.save_state(state);
+		self.joypad.save_state(state);
+		self.serial.save_state(state);
+
+		if let Some(sgb) = &self.sgb {
+			sgb.save_state(state);
+		}
+
+		self.cart.save_state(state);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		state.read_bytes(&mut self.memory)?;
+		state.read_bytes(&mut self.wram)?;
+		state.read_bytes(&mut self.hram)?;
+
+		self.dma_src = state.read_u8()?;
+		self.dma_start_delay = state.read_u8()?;
+		self.dma_active = state.read_bool()?;
+		self.dma_base = state.read_u16()?;
+		self.dma_index = state.read_u8()?;
+		self.dma_byte = state.read_u8()?;
+		// the bootrom itself isn't part of the state, so it can only be resumed if one is loaded
+		self.bootrom_loaded = state.read_bool()? && self.bootrom.len() >= 0x100;
+
+		self.double_speed = state.read_bool()?;
+		self.speed_switch_armed = state.read_bool()?;
+		self.wram_bank = state.read_u8()?;
+		self.hdma_src = state.read_u16()?;
+		self.hdma_dst = state.read_u16()?;
+		self.hdma_len = state.read_u8()?;
+		self.hdma_active = state.read_bool()?;
+
+		if !(1..=7).contains(&self.wram_bank) {
+			return Err(StateError::InvalidValue("WRAM bank"));
+		}
+
+		if self.dma_start_delay > 2 || self.dma_index > 0xA0 || self.dma_base > 0xDF00 {
+			return Err(StateError::InvalidValue("OAM DMA"));
+		}
+
+		self.intf.load_state(state)?;
+		self.timer.load_state(state)?;
+		self.ppu.load_state(state)?;
+		self.apu.load_state(state)?;
+		self.joypad.load_state(state)?;
+		self.serial.load_state(state)?;
+
+		if let Some(sgb) = self.sgb.as_mut() {
+			sgb.load_state(state)?;
+		}
+
+		self.cart.load_state(state)?;
+
+		Ok(())
+	}
+
+	// CPU tests put code and data anywhere in the address space, so they need it all to behave like RAM
+	pub fn set_flat_memory(&mut self, flat: bool) {
+		self.flat_memory = flat;
 	}
 
 	pub fn clear_test_mem(&mut self) {
@@ -0,0 +1,236 @@
+use super::{MBC, check_sram, load_fixed_ram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+const RAM_SIZE: usize = 0x20000;
+const REGISTER_COUNT: usize = 0x36;
+
+// captured photos are written to the start of RAM bank 0 as tiles, after the first 0x100 bytes
+const IMAGE_ADDR: usize = 0x100;
+
+// the Game Boy Camera: an MBC with 128KiB of RAM and an M64282FP image sensor. The sensor's own edge enhancement and
+// gain aren't emulated, the image goes straight through the dither matrix the game sets up
+pub struct Camera {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+
+	rom_bank: u8,
+	ram_bank: u8,
+	rom_banks: usize,
+
+	ram_enabled: bool,
+	registers_mapped: bool,	// 0xA000-0xBFFF is the sensor's registers instead of RAM
+
+	registers: [u8; REGISTER_COUNT],
+	capture_cycles: u64,	// T-states until the capture in progress finishes
+
+	image: Vec<u8>,	// what the sensor sees, 8-bit greyscale where 0 is black
+}
+
+impl Camera {
+
+	pub fn new(rom: Vec<u8>) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+
+		Self {
+			rom,
+			ram: vec![0; RAM_SIZE],
+
+			rom_bank: 1,
+			ram_bank: 0,
+			rom_banks,
+
+			ram_enabled: false,
+			registers_mapped: false,
+
+			registers: [0; REGISTER_COUNT],
+			capture_cycles: 0,
+
+			// as if the lens were covered
+			image: vec![0; CAMERA_WIDTH * CAMERA_HEIGHT],
+		}
+
+	}
+
+	fn capturing(&self) -> bool {
+		self.capture_cycles != 0
+	}
+
+	fn start_capture(&mut self) {
+
+		// bit 7 of register 1 turns off the sensor's negative image output, which saves some time
+		let negative = self.registers[1] & 0x80 == 0;
+		let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as u64;
+
+		self.capture_cycles = 4 * (32446 + if negative { 512 } else { 0 } + 16 * exposure);
+
+	}
+
+	// each pixel is compared against the 3 thresholds for its place in a 4x4 matrix, picking one of 4 shades
+	fn finish_capture(&mut self) {
+
+		let matrix = &self.registers[0x6..REGISTER_COUNT];
+
+		for y in 0..CAMERA_HEIGHT {
+			for x in 0..CAMERA_WIDTH {
+
+				let pixel = self.image[y * CAMERA_WIDTH + x];
+				let thresholds = &matrix[((y % 4) * 4 + x % 4) * 3..][..3];
+
+				let colour = match thresholds.iter().position(|threshold| pixel < *threshold) {
+					Some(level) => 3 - level as u8,
+					None => 0,
+				};
+
+				let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+				let addr = IMAGE_ADDR + tile * 16 + (y % 8) * 2;
+				let bit = 0x80 >> (x % 8);
+
+				for (plane, mask) in [(0, 1), (1, 2)] {
+					if colour & mask != 0 {
+						self.ram[addr + plane] |= bit;
+					} else {
+						self.ram[addr + plane] &= !bit;
+					}
+				}
+
+			}
+		}
+
+		self.registers[0] &= !1;
+
+	}
+
+}
+
+impl MBC for Camera {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom bank x
+			0x4000	..= 0x7FFF	=> {
+				let bank = self.rom_bank as usize % self.rom_banks;
+
+				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
+			},
+			// only the first register can be read, and only its bottom 3 bits
+			0xA000	..= 0xBFFF	if self.registers_mapped => match addr & 0x7F {
+				0 => self.registers[0] & 0x7,
+				_ => 0x00,
+			},
+			// ram can be read whether it's enabled or not
+			0xA000	..= 0xBFFF	=> self.ram[addr as usize - 0xA000 + self.ram_bank as usize * 0x2000],
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// ram enable register
+			0		..= 0x1FFF	=> self.ram_enabled = write & 0xF == 0xA,
+			// rom bank register
+			0x2000	..= 0x3FFF	=> self.rom_bank = write & 0x3F,
+			// ram bank register. Bit 4 maps the registers
+			0x4000	..= 0x5FFF	=> {
+				self.registers_mapped = write & 0x10 != 0;
+
+				if !self.registers_mapped {
+					self.ram_bank = write & 0xF;
+				}
+			},
+			0xA000	..= 0xBFFF	if self.registers_mapped => {
+				match addr as usize & 0x7F {
+					0 => {
+						let start = write & 1 != 0 && !self.capturing();
+
+						self.registers[0] = write & 0x7;
+
+						if start {
+							self.start_capture();
+						}
+					},
+					reg @ 1 .. REGISTER_COUNT => self.registers[reg] = write,
+					_ => {},
+				}
+			},
+			// ram write
+			0xA000	..= 0xBFFF	if self.ram_enabled => {
+				self.ram[addr as usize - 0xA000 + self.ram_bank as usize * 0x2000] = write;
+			},
+			_ => {}
+		}
+
+	}
+
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	fn tick(&mut self, cycles: u64) {
+
+		if !self.capturing() {
+			return;
+		}
+
+		self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+
+		if !self.capturing() {
+			self.finish_capture();
+		}
+
+	}
+
+	// anything that isn't the right size is ignored
+	fn set_camera_image(&mut self, image: &[u8]) {
+		if image.len() == CAMERA_WIDTH * CAMERA_HEIGHT {
+			self.image.copy_from_slice(image);
+		}
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		self.ram.clone()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.len())?;
+
+		self.ram = sram;
+
+		Ok(())
+	}
+
+	// the sensor image isn't saved; it comes from outside
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.ram);
+
+		state.write_u8(self.rom_bank);
+		state.write_u8(self.ram_bank);
+		state.write_bool(self.ram_enabled);
+		state.write_bool(self.registers_mapped);
+
+		state.write_bytes(&self.registers);
+		state.write_u64(self.capture_cycles);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_fixed_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u8()? & 0x3F;
+		self.ram_bank = state.read_u8()? & 0xF;
+		self.ram_enabled = state.read_bool()?;
+		self.registers_mapped = state.read_bool()?;
+
+		state.read_bytes(&mut self.registers)?;
+		self.capture_cycles = state.read_u64()?;
+
+		Ok(())
+	}
+}
\ No newline at end of file
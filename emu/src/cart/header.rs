@@ -0,0 +1,235 @@
+// the logo every licensed cart has at 0x104. The bootrom refuses to start a game if it doesn't match
+pub(crate) const NINTENDO_LOGO: [u8; 48] = [
+	0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+	0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+	0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// the header ends at 0x14F, so anything shorter can't be a cart
+pub const HEADER_END: usize = 0x150;
+
+#[derive(Debug, PartialEq)]
+pub enum CartError {
+	TooShort(usize),
+	InvalidRomSize(u8),
+	InvalidRamSize(u8),
+	UnsupportedType(u8),
+}
+
+impl std::fmt::Display for CartError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::TooShort(len) => write!(f, "ROM is too short to contain a cartridge header ({} bytes)", len),
+			Self::InvalidRomSize(code) => write!(f, "cartridge header has an invalid ROM size (0x{:02X})", code),
+			Self::InvalidRamSize(code) => write!(f, "cartridge header has an invalid RAM size (0x{:02X})", code),
+			Self::UnsupportedType(cart_type) => write!(f, "unsupported cartridge type 0x{:02X} ({})", cart_type, cart_type_name(*cart_type)),
+		}
+	}
+}
+
+impl std::error::Error for CartError {}
+
+// a header the bootrom wouldn't boot, or that suggests a bad dump. The game is loaded anyway, since without the bootrom
+// nothing checks, and plenty of homebrew never fills these in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderWarning {
+	BadLogo,
+	BadHeaderChecksum,
+	BadGlobalChecksum,
+}
+
+impl std::fmt::Display for HeaderWarning {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::BadLogo => write!(f, "the Nintendo logo in the cartridge header doesn't match, so the bootrom would lock up"),
+			Self::BadHeaderChecksum => write!(f, "the cartridge header checksum doesn't match, so the bootrom would lock up"),
+			Self::BadGlobalChecksum => write!(f, "the ROM checksum doesn't match, so the dump may be bad"),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CgbSupport {
+	None,
+	Enhanced,	// works on both, with extra features on a CGB
+	Exclusive,	// only runs on a CGB
+}
+
+// who published the game. Older carts have a one byte code, and 0x33 means the newer two character code is used instead
+#[derive(Clone, PartialEq, Debug)]
+pub enum Licensee {
+	Old(u8),
+	New(String),
+}
+
+impl std::fmt::Display for Licensee {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Old(code) => write!(f, "0x{:02X}", code),
+			Self::New(code) => write!(f, "\"{}\"", code),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+	Japan,
+	Overseas,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CartHeader {
+	pub title: String,
+	pub manufacturer: Option<String>,	// only on later carts, which shortened the title to make room for it
+	pub cgb_support: CgbSupport,
+	pub sgb_support: bool,
+	pub licensee: Licensee,
+	pub cart_type: u8,
+	pub rom_size: usize,	// in bytes
+	pub ram_size: usize,	// in bytes. MBC2 and MBC7 have RAM built in, which isn't counted here
+	pub region: Region,
+	pub version: u8,
+
+	pub header_checksum: u8,
+	pub global_checksum: u16,
+
+	// whether the checksums and logo match. Only the header checksum and logo are checked by the bootrom
+	pub header_checksum_valid: bool,
+	pub global_checksum_valid: bool,
+	pub logo_valid: bool,
+}
+
+impl CartHeader {
+
+	pub fn parse(rom: &[u8]) -> Result<Self, CartError> {
+
+		if rom.len() < HEADER_END {
+			return Err(CartError::TooShort(rom.len()));
+		}
+
+		let cgb_support = match rom[0x143] {
+			0xC0 => CgbSupport::Exclusive,
+			flag if flag & 0x80 != 0 => CgbSupport::Enhanced,
+			_ => CgbSupport::None,
+		};
+
+		let licensee = match rom[0x14B] {
+			0x33 => Licensee::New(ascii(&rom[0x144..0x146])),
+			code => Licensee::Old(code),
+		};
+
+		// the manufacturer code replaced the end of the title on carts made after the CGB came out
+		let manufacturer_code = &rom[0x13F..0x143];
+		let has_manufacturer = cgb_support != CgbSupport::None
+			&& manufacturer_code.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+
+		let title_end = match (has_manufacturer, cgb_support) {
+			(true, _) => 0x13F,
+			(false, CgbSupport::None) => 0x144,
+			(false, _) => 0x143,
+		};
+
+		let rom_size = match rom[0x148] {
+			code @ 0..=8 => 0x8000 << code,
+			code => return Err(CartError::InvalidRomSize(code)),
+		};
+
+		let ram_size = match rom[0x149] {
+			0 | 1 	=> 0,
+			2 		=> 8 * 1024,
+			3 		=> 32 * 1024,
+			4 		=> 128 * 1024,
+			5 		=> 64 * 1024,
+			code 	=> return Err(CartError::InvalidRamSize(code)),
+		};
+
+		let header_checksum = rom[0x14D];
+		let global_checksum = u16::from_be_bytes([rom[0x14E], rom[0x14F]]);
+
+		let computed_header = rom[0x134..=0x14C].iter().fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+		let computed_global = rom.iter().enumerate()
+			.filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+			.fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+
+		Ok(Self {
+			title: ascii(&rom[0x134..title_end]),
+			manufacturer: if has_manufacturer { Some(ascii(manufacturer_code)) } else { None },
+			cgb_support,
+			sgb_support: rom[0x146] == 0x03,
+			licensee,
+			cart_type: rom[0x147],
+			rom_size,
+			ram_size,
+			region: if rom[0x14A] == 0 { Region::Japan } else { Region::Overseas },
+			version: rom[0x14C],
+
+			header_checksum,
+			global_checksum,
+
+			header_checksum_valid: computed_header == header_checksum,
+			global_checksum_valid: computed_global == global_checksum,
+			logo_valid: rom[0x104..0x134] == NINTENDO_LOGO,
+		})
+
+	}
+
+	pub fn warnings(&self) -> Vec<HeaderWarning> {
+		[
+			(!self.logo_valid, HeaderWarning::BadLogo),
+			(!self.header_checksum_valid, HeaderWarning::BadHeaderChecksum),
+			(!self.global_checksum_valid, HeaderWarning::BadGlobalChecksum),
+		].into_iter().filter_map(|(bad, warning)| bad.then_some(warning)).collect()
+	}
+
+	pub fn rom_banks(&self) -> usize {
+		self.rom_size / 0x4000
+	}
+
+	pub fn cart_type_name(&self) -> &'static str {
+		cart_type_name(self.cart_type)
+	}
+
+}
+
+// text in the header is padded with zeroes. Anything that isn't printable is dropped
+fn ascii(bytes: &[u8]) -> String {
+	bytes.iter()
+		.take_while(|c| **c != 0)
+		.filter(|c| c.is_ascii_graphic() || **c == b' ')
+		.map(|c| *c as char)
+		.collect()
+}
+
+pub fn cart_type_name(cart_type: u8) -> &'static str {
+	match cart_type {
+		0x0 => "ROM ONLY",
+		0x1 => "MBC1",
+		0x2 => "MBC1+RAM",
+		0x3 => "MBC1+RAM+BATTERY",
+		0x5 => "MBC2",
+		0x6 => "MBC2+BATTERY",
+		0x8 => "ROM+RAM",
+		0x9 => "ROM+RAM+BATTERY",
+		0xB => "MMM01",
+		0xC => "MMM01+RAM",
+		0xD => "MMM01+RAM+BATTERY",
+		0xF => "MBC3+TIMER+BATTERY",
+		0x10 => "MBC3+TIMER+RAM+BATTERY",
+		0x11 => "MBC3",
+		0x12 => "MBC3+RAM",
+		0x13 => "MBC3+RAM+BATTERY",
+		0x19 => "MBC5",
+		0x1A => "MBC5+RAM",
+		0x1B => "MBC5+RAM+BATTERY",
+		0x1C => "MBC5+RUMBLE",
+		0x1D => "MBC5+RUMBLE+RAM",
+		0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+		0x20 => "MBC6",
+		0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+		0xFC => "POCKET CAMERA",
+		0xFD => "BANDAI TAMA5",
+		0xFE => "HuC3",
+		0xFF => "HuC1+RAM+BATTERY",
+		_ => "UNKNOWN",
+	}
+}
\ No newline at end of file
@@ -0,0 +1,123 @@
+use super::{MBC, check_sram, load_fixed_ram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// Hudson's MBC1 lookalike with an infrared port. There's nothing to talk to over IR, so it never sees any light
+pub struct HuC1 {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+
+	rom_bank: u8,
+	ram_bank: u8,
+
+	rom_banks: usize,
+	ram_banks: usize,
+
+	ir_mode: bool,	// 0xA000-0xBFFF is the IR port instead of RAM
+}
+
+impl HuC1 {
+
+	pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+
+		Self {
+			rom,
+			ram: vec![0; ram_size],
+
+			rom_bank: 1,
+			ram_bank: 0,
+
+			rom_banks,
+			ram_banks: ram_size >> 13,
+
+			ir_mode: false,
+		}
+
+	}
+
+}
+
+impl MBC for HuC1 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom bank x
+			0x4000	..= 0x7FFF	=> {
+				let bank = self.rom_bank as usize % self.rom_banks;
+
+				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
+			},
+			// IR receiver: 0xC1 when light is seen, 0xC0 when it isn't
+			0xA000	..= 0xBFFF	if self.ir_mode => 0xC0,
+			// ram bank x. There's no RAM enable, RAM is mapped whenever IR isn't
+			0xA000	..= 0xBFFF	if self.ram_banks != 0 => {
+				let bank = self.ram_bank as usize % self.ram_banks;
+
+				self.ram[addr as usize - 0xA000 + (bank * 0x2000)]
+			},
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// IR / ram select
+			0		..= 0x1FFF	=> self.ir_mode = write & 0xF == 0xE,
+			// rom bank register
+			0x2000	..= 0x3FFF	=> self.rom_bank = write & 0x3F,
+			// ram bank register
+			0x4000	..= 0x5FFF	=> self.ram_bank = write & 0x3,
+			// the IR LED; nobody's watching
+			0xA000	..= 0xBFFF	if self.ir_mode => {},
+			// ram write
+			0xA000	..= 0xBFFF	if self.ram_banks != 0 => {
+				let bank = self.ram_bank as usize % self.ram_banks;
+
+				self.ram[addr as usize - 0xA000 + (bank * 0x2000)] = write;
+			},
+			_ => {}
+		}
+
+	}
+
+	// HuC1 carts always have a battery
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		self.ram.clone()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.len())?;
+
+		self.ram = sram;
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.ram);
+
+		state.write_u8(self.rom_bank);
+		state.write_u8(self.ram_bank);
+		state.write_bool(self.ir_mode);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_fixed_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u8()? & 0x3F;
+		self.ram_bank = state.read_u8()? & 0x3;
+		self.ir_mode = state.read_bool()?;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,274 @@
+use super::{MBC, check_sram, load_fixed_ram, unix_time, CYCLES_PER_SECOND};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// save files store the clock after the RAM: minutes and days as 16-bit values, followed by a 64-bit UNIX timestamp
+const RTC_TRAILER_LEN: usize = 12;
+
+const MINUTES_PER_DAY: u16 = 1440;
+
+// RTC commands, written to the high nibble in mode 0xB
+const CMD_READ: u8 = 0x1;		// reads the nibble at the address into the response, then increments the address
+const CMD_WRITE: u8 = 0x3;		// writes the argument to the address, then increments it
+const CMD_ADDR_LOW: u8 = 0x4;
+const CMD_ADDR_HIGH: u8 = 0x5;
+const CMD_EXTENDED: u8 = 0x6;	// 0: copy the clock into 0x00-0x06, 1: set the clock from 0x00-0x06
+
+// Hudson's mapper with an RTC and an IR port. The RTC counts minutes and days, and is driven through a little
+// nibble-wide command interface with its own 256 nibbles of memory
+pub struct HuC3 {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+
+	rom_bank: u8,
+	ram_bank: u8,
+
+	rom_banks: usize,
+	ram_banks: usize,
+
+	mode: u8,	// what 0xA000-0xBFFF is mapped to
+
+	minutes: u16,	// minutes into the current day
+	days: u16,		// 12 bits
+	rtc_cycles: u64,	// T-states since the last RTC second
+	rtc_seconds: u8,
+
+	rtc_memory: [u8; 256],	// one nibble per byte
+	rtc_address: u8,
+	rtc_response: u8,
+}
+
+impl HuC3 {
+
+	pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+
+		Self {
+			rom,
+			ram: vec![0; ram_size],
+
+			rom_bank: 1,
+			ram_bank: 0,
+
+			rom_banks,
+			ram_banks: ram_size >> 13,
+
+			mode: 0,
+
+			minutes: 0,
+			days: 0,
+			rtc_cycles: 0,
+			rtc_seconds: 0,
+
+			rtc_memory: [0; 256],
+			rtc_address: 0,
+			rtc_response: 0,
+		}
+
+	}
+
+	fn ram_addr(&self, addr: u16) -> Option<usize> {
+		if self.ram_banks == 0 {
+			return None;
+		}
+
+		Some(addr as usize - 0xA000 + (self.ram_bank as usize % self.ram_banks) * 0x2000)
+	}
+
+	fn advance_minutes(&mut self, minutes: u64) {
+		let total = self.minutes as u64 + minutes;
+
+		self.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+		self.days = ((self.days as u64 + total / MINUTES_PER_DAY as u64) & 0xFFF) as u16;
+	}
+
+	fn rtc_command(&mut self, write: u8) {
+
+		let command = (write >> 4) & 0x7;
+		let argument = write & 0xF;
+
+		match command {
+			CMD_READ => {
+				self.rtc_response = self.rtc_memory[self.rtc_address as usize];
+				self.rtc_address = self.rtc_address.wrapping_add(1);
+			},
+			CMD_WRITE => {
+				self.rtc_memory[self.rtc_address as usize] = argument;
+				self.rtc_address = self.rtc_address.wrapping_add(1);
+			},
+			CMD_ADDR_LOW => self.rtc_address = (self.rtc_address & 0xF0) | argument,
+			CMD_ADDR_HIGH => self.rtc_address = (self.rtc_address & 0x0F) | argument << 4,
+			CMD_EXTENDED => match argument {
+				// minutes then days, 12 bits each, least significant nibble first
+				0x0 => {
+					for i in 0..3 {
+						self.rtc_memory[i] = (self.minutes >> (i * 4)) as u8 & 0xF;
+						self.rtc_memory[3 + i] = (self.days >> (i * 4)) as u8 & 0xF;
+					}
+				},
+				0x1 => {
+					let nibbles = |start: usize| (0..3).fold(0, |value, i| value | (self.rtc_memory[start + i] as u16) << (i * 4));
+
+					self.minutes = nibbles(0) % MINUTES_PER_DAY;
+					self.days = nibbles(3);
+				},
+				// alarm and tone generator commands; there's no speaker to drive
+				_ => {},
+			},
+			_ => {},
+		}
+
+		self.rtc_response = command << 4 | if command == CMD_READ { self.rtc_response } else { 0 };
+
+	}
+
+}
+
+impl MBC for HuC3 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom bank x
+			0x4000	..= 0x7FFF	=> {
+				let bank = self.rom_bank as usize % self.rom_banks;
+
+				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
+			},
+			0xA000	..= 0xBFFF	=> match self.mode {
+				// ram, read only in mode 0
+				0x0 | 0xA => self.ram_addr(addr).map_or(0xFF, |addr| self.ram[addr]),
+				// the result of the last RTC command
+				0xC => self.rtc_response,
+				// semaphore: bit 0 is set once the RTC is ready for another command, which is always
+				0xD => 0xFF,
+				// IR receiver, which never sees any light
+				0xE => 0xC0,
+				_ => 0xFF,
+			},
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// ram / rtc / IR select
+			0		..= 0x1FFF	=> self.mode = write & 0xF,
+			// rom bank register
+			0x2000	..= 0x3FFF	=> self.rom_bank = write & 0x7F,
+			// ram bank register
+			0x4000	..= 0x5FFF	=> self.ram_bank = write & 0x3,
+			0xA000	..= 0xBFFF	=> match self.mode {
+				0xA => {
+					if let Some(addr) = self.ram_addr(addr) {
+						self.ram[addr] = write;
+					}
+				},
+				0xB => self.rtc_command(write),
+				_ => {},
+			},
+			_ => {}
+		}
+
+	}
+
+	// HuC3 carts always have a battery for the RTC
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	fn tick(&mut self, cycles: u64) {
+
+		self.rtc_cycles += cycles;
+
+		while self.rtc_cycles >= CYCLES_PER_SECOND {
+			self.rtc_cycles -= CYCLES_PER_SECOND;
+			self.rtc_seconds += 1;
+
+			if self.rtc_seconds == 60 {
+				self.rtc_seconds = 0;
+				self.advance_minutes(1);
+			}
+		}
+
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		let mut sram = self.ram.clone();
+
+		sram.extend_from_slice(&self.minutes.to_le_bytes());
+		sram.extend_from_slice(&self.days.to_le_bytes());
+		sram.extend_from_slice(&unix_time().to_le_bytes());
+
+		sram
+	}
+
+	// saves without the clock (e.g. from other emulators) are just the RAM
+	fn load_sram(&mut self, mut sram: Vec<u8>) -> Result<(), Error> {
+
+		if sram.len() == self.ram.len() + RTC_TRAILER_LEN {
+
+			let trailer = sram.split_off(self.ram.len());
+
+			self.minutes = u16::from_le_bytes([trailer[0], trailer[1]]) % MINUTES_PER_DAY;
+			self.days = u16::from_le_bytes([trailer[2], trailer[3]]) & 0xFFF;
+
+			let timestamp = u64::from_le_bytes(trailer[4..12].try_into().unwrap());
+
+			// catch up on the time that passed while the game wasn't running
+			self.advance_minutes(unix_time().saturating_sub(timestamp) / 60);
+
+		} else {
+			check_sram(&sram, self.ram.len())?;
+		}
+
+		self.ram = sram;
+
+		Ok(())
+
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.ram);
+
+		state.write_u8(self.rom_bank);
+		state.write_u8(self.ram_bank);
+		state.write_u8(self.mode);
+
+		state.write_u16(self.minutes);
+		state.write_u16(self.days);
+		state.write_u64(self.rtc_cycles);
+		state.write_u8(self.rtc_seconds);
+
+		state.write_bytes(&self.rtc_memory);
+		state.write_u8(self.rtc_address);
+		state.write_u8(self.rtc_response);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_fixed_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u8()? & 0x7F;
+		self.ram_bank = state.read_u8()? & 0x3;
+		self.mode = state.read_u8()? & 0xF;
+
+		self.minutes = state.read_u16()?;
+		self.days = state.read_u16()? & 0xFFF;
+		self.rtc_cycles = state.read_u64()?;
+		self.rtc_seconds = state.read_u8()?;
+
+		state.read_bytes(&mut self.rtc_memory)?;
+		self.rtc_address = state.read_u8()?;
+		self.rtc_response = state.read_u8()?;
+
+		if self.minutes >= MINUTES_PER_DAY || self.rtc_seconds >= 60 || self.rtc_memory.iter().any(|nibble| *nibble > 0xF) {
+			return Err(StateError::InvalidValue("HuC3 RTC"));
+		}
+
+		Ok(())
+	}
+}
\ No newline at end of file
@@ -1,16 +1,30 @@
-#![allow(unused_variables)]
-use super::MBC;
+use super::{MBC, save_ram, load_ram, check_sram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
 
+// no mapper at all. ROM+RAM carts wire RAM straight to 0xA000-0xBFFF, with no enable or banking
 pub struct MBC0 {
-	rom: Vec<u8>
+	rom: Vec<u8>,
+	ram: Option<Vec<u8>>,
+
+	has_battery: bool,
 }
 
 impl MBC0 {
 
-	pub fn new(rom: Vec<u8>) -> Self {
-		
+	pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+
+		let has_ram = matches!(rom[0x147], 0x8 | 0x9);
+		let has_battery = rom[0x147] == 0x9;
+
+		// carts that don't say how much RAM they have get the whole window
+		let ram = if has_ram { Some(vec![0; if ram_size == 0 { 0x2000 } else { ram_size }]) } else { None };
+
 		Self {
-			rom: rom
+			rom,
+			ram,
+
+			has_battery,
 		}
 
 	}
@@ -21,24 +35,52 @@ impl MBC for MBC0 {
 
 	fn read(&self, addr: u16) -> u8 {
 
-		if addr <= 0x7FFF {
-			self.rom[addr as usize]
-		} else {
-			0xFF
+		match addr {
+			0		..= 0x7FFF	=> self.rom[addr as usize],
+			// anything bigger than the window is unreachable, anything smaller is mirrored
+			0xA000	..= 0xBFFF	=> match self.ram {
+				Some(ref ram) => ram[(addr as usize - 0xA000) % ram.len()],
+				None => 0xFF,
+			},
+			_ => 0xFF
 		}
 
 	}
 
-	fn write(&mut self, addr: u16, write: u8) {}
+	fn write(&mut self, addr: u16, write: u8) {
+
+		if let (0xA000 ..= 0xBFFF, Some(ram)) = (addr, self.ram.as_mut()) {
+			let len = ram.len();
+
+			ram[(addr as usize - 0xA000) % len] = write;
+		}
+
+	}
 
 	fn is_battery_backed(&self) -> bool {
-		false
+		self.has_battery
 	}
 
 	fn dump_sram(&self) -> Vec<u8> {
-		Vec::new()
+		self.ram.clone().unwrap_or_default()
 	}
 
-	fn load_sram(&mut self, sram: Vec<u8>) {}
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.as_ref().map_or(0, |ram| ram.len()))?;
+
+		if self.ram.is_some() {
+			self.ram = Some(sram);
+		}
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		save_ram(state, &self.ram);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_ram(state, &mut self.ram)
+	}
 
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
-use super::MBC;
+use super::{MBC, save_ram, load_ram, check_sram};
+use super::header::NINTENDO_LOGO;
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
 
 pub struct MBC1 {
 	rom: Vec<u8>,
@@ -13,6 +16,9 @@ pub struct MBC1 {
 	
 	ram_enabled: bool,
 	has_battery: bool,
+
+	// MBC1M: the upper bank bits are wired one bit lower, so each game gets 16 banks of its own
+	multicart: bool,
 }
 
 impl MBC1 {
@@ -28,6 +34,7 @@ impl MBC1 {
 
 		let ram = if has_ram { Some(vec![0; ram_size]) } else { None };
 		let rom_size = rom[0x148];
+		let multicart = is_multicart(&rom);
 
 		Self {
 			rom: rom,
@@ -42,10 +49,29 @@ impl MBC1 {
 
 			ram_enabled: false,
 			has_battery: has_battery,
+
+			multicart,
 		}
 
 	}
 
+	fn upper_bank_shift(&self) -> u8 {
+		if self.multicart { 4 } else { 5 }
+	}
+
+}
+
+// multicarts don't say so in their header, but each game in them starts with its own header (and logo) on a 256KiB boundary
+fn is_multicart(rom: &[u8]) -> bool {
+	if rom.len() != 0x100000 {
+		return false;
+	}
+
+	let logos = (1..4)
+		.filter(|game| rom[game * 0x40000 + 0x104..game * 0x40000 + 0x134] == NINTENDO_LOGO)
+		.count();
+
+	logos >= 2
 }
 
 impl MBC for MBC1 {
@@ -55,7 +81,7 @@ impl MBC for MBC1 {
 			// rom bank 0
 			0		..= 0x3FFF	=> {
 				let bank = if self.banking_mode {
-					(self.upper_bank as usize) << 5
+					(self.upper_bank as usize) << self.upper_bank_shift()
 				} else {
 					0
 				} % self.rom_banks;
@@ -64,7 +90,9 @@ impl MBC for MBC1 {
 			},
 			// rom bank x
 			0x4000	..= 0x7FFF	=> {
-				let bank = (self.rom_bank as usize | ((self.upper_bank as usize) << 5)) % self.rom_banks;
+				// the bank register is still 5 bits wide on a multicart, so a bank of 0x10 isn't bumped up to 1
+				let lower_bank = self.rom_bank as usize & ((1 << self.upper_bank_shift()) - 1);
+				let bank = (lower_bank | ((self.upper_bank as usize) << self.upper_bank_shift())) % self.rom_banks;
 
 				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
 			},
@@ -84,7 +112,7 @@ impl MBC for MBC1 {
 
 				0xFF
 			},
-			_ => panic!("invalid cart read")
+			_ => 0xFF
 		}
 
 	}
@@ -125,7 +153,7 @@ impl MBC for MBC1 {
 					}
 				}
 			},
-			_ => panic!("invalid cart write")
+			_ => {}
 		}
 
 	}
@@ -135,14 +163,36 @@ impl MBC for MBC1 {
 	}
 
 	fn dump_sram(&self) -> Vec<u8> {
-		if let Some(ref ram) = self.ram {
-			ram.clone()
-		} else {
-			panic!("attempt to dump sram when no sram is present");
+		self.ram.clone().unwrap_or_default()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.as_ref().map_or(0, |ram| ram.len()))?;
+
+		if self.ram.is_some() {
+			self.ram = Some(sram);
 		}
+
+		Ok(())
 	}
 
-	fn load_sram(&mut self, sram: Vec<u8>) {
-		self.ram = Some(sram);
+	fn save_state(&self, state: &mut StateWriter) {
+		save_ram(state, &self.ram);
+
+		state.write_u16(self.rom_bank);
+		state.write_u8(self.upper_bank);
+		state.write_bool(self.banking_mode);
+		state.write_bool(self.ram_enabled);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u16()?;
+		self.upper_bank = state.read_u8()? & 0b11;
+		self.banking_mode = state.read_bool()?;
+		self.ram_enabled = state.read_bool()?;
+
+		Ok(())
 	}
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
-use super::MBC;
+use super::{MBC, check_sram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
 
 pub struct MBC2 {
 	rom: Vec<u8>,
@@ -49,9 +51,9 @@ impl MBC for MBC2 {
 			// ram bank x
 			// only the lower 4 bits of ram are accessible
 			// MBC2 only reads the lower 9 bits of addr
-			0xA000	..=	0xBFFF	=> if self.ram_enabled { self.ram[((addr & 0x1FF)) as usize] | 0xF0 } else { 0xFF },
+			0xA000	..=	0xBFFF	if self.ram_enabled => self.ram[((addr & 0x1FF)) as usize] | 0xF0,
 
-			_ => panic!("invalid cart read 0x{:X}", addr)
+			_ => 0xFF
 		}
 
 	}
@@ -83,7 +85,28 @@ impl MBC for MBC2 {
 		self.ram.clone()
 	}
 
-	fn load_sram(&mut self, sram: Vec<u8>) {
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.len())?;
+
 		self.ram = sram;
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&self.ram);
+
+		state.write_u8(self.rom_bank);
+		state.write_bool(self.ram_enabled);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.ram.resize(512, 0xFF);
+		state.read_bytes(&mut self.ram)?;
+
+		self.rom_bank = state.read_u8()? & 0x0F;
+		self.ram_enabled = state.read_bool()?;
+
+		Ok(())
 	}
 }
\ No newline at end of file
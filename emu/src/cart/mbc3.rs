@@ -1,4 +1,143 @@
-use super::MBC;
+use super::{MBC, save_ram, load_ram, check_sram, unix_time, CYCLES_PER_SECOND};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// save files store the RTC after the RAM in the format used by VBA and BGB: the clock and latched registers as
+// 32-bit values, followed by a 64-bit UNIX timestamp (some emulators write a 32-bit one, making it 44 bytes)
+const RTC_TRAILER_LEN: usize = 48;
+const RTC_TRAILER_LEN_SHORT: usize = 44;
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+struct RtcRegisters {
+	seconds: u8,
+	minutes: u8,
+	hours: u8,
+	days: u16,		// 9 bits
+	halted: bool,
+	day_carry: bool,
+}
+
+impl RtcRegisters {
+
+	fn read(&self, reg: u8) -> u8 {
+		match reg {
+			0x08 => self.seconds,
+			0x09 => self.minutes,
+			0x0A => self.hours,
+			0x0B => self.days as u8,
+			0x0C => self.day_high(),
+			_ => 0xFF,
+		}
+	}
+
+	fn write(&mut self, reg: u8, write: u8) {
+		match reg {
+			0x08 => self.seconds = write & 0x3F,
+			0x09 => self.minutes = write & 0x3F,
+			0x0A => self.hours = write & 0x1F,
+			0x0B => self.days = (self.days & 0x100) | write as u16,
+			0x0C => self.set_day_high(write),
+			_ => {}
+		}
+	}
+
+	// DH: bit 0 is bit 8 of the day counter, bit 6 halts the clock and bit 7 is set when the day counter overflows
+	fn day_high(&self) -> u8 {
+		(self.days >> 8) as u8 | (self.halted as u8) << 6 | (self.day_carry as u8) << 7
+	}
+
+	fn set_day_high(&mut self, write: u8) {
+		self.days = (self.days & 0xFF) | ((write as u16 & 1) << 8);
+		self.halted = write & 0x40 != 0;
+		self.day_carry = write & 0x80 != 0;
+	}
+
+	// out of range values keep counting up to the register's bit width, then wrap to 0 without carrying
+	fn tick_second(&mut self) {
+
+		self.seconds = (self.seconds + 1) & 0x3F;
+		if self.seconds != 60 { return; }
+		self.seconds = 0;
+
+		self.minutes = (self.minutes + 1) & 0x3F;
+		if self.minutes != 60 { return; }
+		self.minutes = 0;
+
+		self.hours = (self.hours + 1) & 0x1F;
+		if self.hours != 24 { return; }
+		self.hours = 0;
+
+		self.days += 1;
+		if self.days == 512 {
+			self.days = 0;
+			self.day_carry = true;
+		}
+
+	}
+
+	fn advance(&mut self, mut seconds: u64) {
+
+		if self.halted {
+			return;
+		}
+
+		// step through any out of range values one second at a time, after which the rest can be added in one go
+		while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+			self.tick_second();
+			seconds -= 1;
+		}
+
+		let total = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 + seconds;
+		let days = self.days as u64 + total / 86400;
+
+		self.seconds = (total % 60) as u8;
+		self.minutes = (total / 60 % 60) as u8;
+		self.hours = (total / 3600 % 24) as u8;
+		self.days = (days % 512) as u16;
+
+		if days >= 512 {
+			self.day_carry = true;
+		}
+
+	}
+
+	fn save_trailer(&self, trailer: &mut Vec<u8>) {
+		for reg in [self.seconds, self.minutes, self.hours, self.days as u8, self.day_high()] {
+			trailer.extend_from_slice(&(reg as u32).to_le_bytes());
+		}
+	}
+
+	fn load_trailer(&mut self, trailer: &[u8]) {
+		let reg = |i: usize| trailer[i * 4];
+
+		self.seconds = reg(0) & 0x3F;
+		self.minutes = reg(1) & 0x3F;
+		self.hours = reg(2) & 0x1F;
+		self.days = reg(3) as u16;
+		self.set_day_high(reg(4));
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.seconds);
+		state.write_u8(self.minutes);
+		state.write_u8(self.hours);
+		state.write_u16(self.days);
+		state.write_bool(self.halted);
+		state.write_bool(self.day_carry);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.seconds = state.read_u8()?;
+		self.minutes = state.read_u8()?;
+		self.hours = state.read_u8()?;
+		self.days = state.read_u16()? & 0x1FF;
+		self.halted = state.read_bool()?;
+		self.day_carry = state.read_bool()?;
+
+		Ok(())
+	}
+
+}
 
 pub struct MBC3 {
 	rom: Vec<u8>,
@@ -12,6 +151,12 @@ pub struct MBC3 {
 	
 	ram_enabled: bool,
 	has_battery: bool,
+
+	has_rtc: bool,
+	rtc: RtcRegisters,
+	rtc_latched: RtcRegisters,
+	rtc_cycles: u64,	// T-states since the last RTC second
+	latch_write: u8,	// last value written to the latch register; writing 0 then 1 latches the clock
 }
 
 impl MBC3 {
@@ -28,6 +173,8 @@ impl MBC3 {
 			_ => false,
 		};
 
+		let has_rtc = matches!(rom[0x147], 0xF | 0x10);
+
 		let ram = if has_ram { Some(vec![0; ram_size]) } else { None };
 		let rom_size = rom[0x148];
 
@@ -43,10 +190,31 @@ impl MBC3 {
 
 			ram_enabled: false,
 			has_battery: has_battery,
+
+			has_rtc,
+			rtc: RtcRegisters::default(),
+			rtc_latched: RtcRegisters::default(),
+			rtc_cycles: 0,
+			latch_write: 0xFF,
 		}
 
 	}
 
+	fn write_rtc(&mut self, write: u8) {
+
+		if !self.ram_enabled || !self.has_rtc {
+			return;
+		}
+
+		// writing the seconds register also resets the sub-second counter
+		if self.ram_bank == 0x08 {
+			self.rtc_cycles = 0;
+		}
+
+		self.rtc.write(self.ram_bank, write);
+
+	}
+
 }
 
 impl MBC for MBC3 {
@@ -61,6 +229,14 @@ impl MBC for MBC3 {
 
 				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
 			},
+			// rtc register
+			0xA000	..= 0xBFFF	if self.ram_bank >= 0x08 => {
+				if self.ram_enabled && self.has_rtc {
+					return self.rtc_latched.read(self.ram_bank);
+				}
+
+				0xFF
+			},
 			// ram bank x
 			0xA000	..= 0xBFFF	=> {
 				if let Some(ref ram) = self.ram {
@@ -73,7 +249,7 @@ impl MBC for MBC3 {
 
 				0xFF
 			},
-			_ => panic!("invalid cart read 0x{:X}", addr)
+			_ => 0xFF
 		}
 
 	}
@@ -91,8 +267,18 @@ impl MBC for MBC3 {
 				if self.rom_bank == 0 { self.rom_bank = 1; }
 
 			}
-			// ram bank register
-			0x4000	..= 0x5FFF	=> self.ram_bank = write & 0b11,
+			// ram bank / rtc register select. 0x08-0x0C map an RTC register into 0xA000-0xBFFF
+			0x4000	..= 0x5FFF	=> self.ram_bank = if write >= 0x08 { write } else { write & 0b11 },
+			// rtc latch
+			0x6000	..= 0x7FFF	=> {
+				if self.latch_write == 0 && write == 1 {
+					self.rtc_latched = self.rtc;
+				}
+
+				self.latch_write = write;
+			},
+			// rtc write
+			0xA000	..= 0xBFFF	if self.ram_bank >= 0x08 => self.write_rtc(write),
 			// ram write
 			0xA000	..= 0xBFFF	=> {
 
@@ -113,15 +299,95 @@ impl MBC for MBC3 {
 		self.has_battery
 	}
 
+	fn tick(&mut self, cycles: u64) {
+
+		if !self.has_rtc || self.rtc.halted {
+			return;
+		}
+
+		self.rtc_cycles += cycles;
+
+		while self.rtc_cycles >= CYCLES_PER_SECOND {
+			self.rtc_cycles -= CYCLES_PER_SECOND;
+			self.rtc.tick_second();
+		}
+
+	}
+
 	fn dump_sram(&self) -> Vec<u8> {
-		if let Some(ref ram) = self.ram {
-			ram.clone()
+
+		let mut sram = match self.ram {
+			Some(ref ram) => ram.clone(),
+			None => Vec::new(),
+		};
+
+		if self.has_rtc {
+			self.rtc.save_trailer(&mut sram);
+			self.rtc_latched.save_trailer(&mut sram);
+			sram.extend_from_slice(&unix_time().to_le_bytes());
+		}
+
+		sram
+
+	}
+
+	fn load_sram(&mut self, mut sram: Vec<u8>) -> Result<(), Error> {
+
+		let ram_len = self.ram.as_ref().map_or(0, |ram| ram.len());
+
+		// saves from before the RTC was emulated (or from emulators without it) are just the RAM
+		if self.has_rtc && (sram.len() == ram_len + RTC_TRAILER_LEN || sram.len() == ram_len + RTC_TRAILER_LEN_SHORT) {
+
+			let trailer = sram.split_off(ram_len);
+
+			self.rtc.load_trailer(&trailer[0..20]);
+			self.rtc_latched.load_trailer(&trailer[20..40]);
+
+			let timestamp = match trailer.len() {
+				RTC_TRAILER_LEN => u64::from_le_bytes(trailer[40..48].try_into().unwrap()),
+				_ => u32::from_le_bytes(trailer[40..44].try_into().unwrap()) as u64,
+			};
+
+			// catch up on the time that passed while the game wasn't running
+			self.rtc.advance(unix_time().saturating_sub(timestamp));
+
 		} else {
-			panic!("attempt to dump sram when no sram is present");
+			check_sram(&sram, ram_len)?;
+		}
+
+		if self.ram.is_some() {
+			self.ram = Some(sram);
 		}
+
+		Ok(())
+
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		save_ram(state, &self.ram);
+
+		state.write_u16(self.rom_bank);
+		state.write_u8(self.ram_bank);
+		state.write_bool(self.ram_enabled);
+
+		self.rtc.save_state(state);
+		self.rtc_latched.save_state(state);
+		state.write_u64(self.rtc_cycles);
+		state.write_u8(self.latch_write);
 	}
 
-	fn load_sram(&mut self, sram: Vec<u8>) {
-		self.ram = Some(sram);
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u16()?;
+		self.ram_bank = state.read_u8()?;
+		self.ram_enabled = state.read_bool()?;
+
+		self.rtc.load_state(state)?;
+		self.rtc_latched.load_state(state)?;
+		self.rtc_cycles = state.read_u64()?;
+		self.latch_write = state.read_u8()?;
+
+		Ok(())
 	}
 }
\ No newline at end of file
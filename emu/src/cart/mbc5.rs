@@ -1,4 +1,6 @@
-use super::MBC;
+use super::{MBC, save_ram, load_ram, check_sram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
 
 pub struct MBC5 {
 	rom: Vec<u8>,
@@ -14,6 +16,8 @@ pub struct MBC5 {
 	
 	ram_enabled: bool,
 	has_battery: bool,
+	// on rumble carts, bit 3 of the ram bank register drives the motor instead of picking a bank
+	has_rumble: bool,
 }
 
 impl MBC5 {
@@ -30,6 +34,8 @@ impl MBC5 {
 			_ => false,
 		};
 
+		let has_rumble = matches!(rom[0x147], 0x1C ..= 0x1E);
+
 		let ram = if has_ram { Some(vec![0; ram_size]) } else { None };
 		let rom_size = rom[0x148];
 
@@ -47,10 +53,15 @@ impl MBC5 {
 
 			ram_enabled: false,
 			has_battery: has_battery,
+			has_rumble,
 		}
 
 	}
 
+	fn ram_bank(&self) -> usize {
+		if self.has_rumble { self.ram_bank as usize & 0x7 } else { self.ram_bank as usize }
+	}
+
 }
 
 impl MBC for MBC5 {
@@ -69,7 +80,7 @@ impl MBC for MBC5 {
 			0xA000	..= 0xBFFF	=> {
 				if let Some(ref ram) = self.ram {
 					if self.ram_enabled && self.ram_banks != 0 {
-						let bank = self.ram_bank as usize % self.ram_banks;
+						let bank = self.ram_bank() % self.ram_banks;
 
 						return ram[addr as usize - 0xA000 + (bank * 0x2000)];
 					}
@@ -79,7 +90,7 @@ impl MBC for MBC5 {
 
 				0xFF
 			},
-			_ => panic!("invalid cart read 0x{:X}", addr)
+			_ => 0xFF
 		}
 
 	}
@@ -98,9 +109,11 @@ impl MBC for MBC5 {
 			// ram write
 			0xA000	..= 0xBFFF	=> {
 
+				let ram_bank = self.ram_bank();
+
 				if let Some(ref mut ram) = self.ram {
 					if self.ram_enabled && self.ram_banks != 0 {
-						let bank = self.ram_bank as usize % self.ram_banks;
+						let bank = ram_bank % self.ram_banks;
 
 						return ram[addr as usize - 0xA000 + (bank * 0x2000)] = write;
 					}
@@ -115,15 +128,41 @@ impl MBC for MBC5 {
 		self.has_battery
 	}
 
+	fn rumble(&self) -> bool {
+		self.has_rumble && self.ram_bank & 0x8 != 0
+	}
+
 	fn dump_sram(&self) -> Vec<u8> {
-		if let Some(ref ram) = self.ram {
-			ram.clone()
-		} else {
-			panic!("attempt to dump sram when no sram is present");
+		self.ram.clone().unwrap_or_default()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.as_ref().map_or(0, |ram| ram.len()))?;
+
+		if self.ram.is_some() {
+			self.ram = Some(sram);
 		}
+
+		Ok(())
 	}
 
-	fn load_sram(&mut self, sram: Vec<u8>) {
-		self.ram = Some(sram);
+	fn save_state(&self, state: &mut StateWriter) {
+		save_ram(state, &self.ram);
+
+		state.write_u8(self.rom_bank);
+		state.write_u8(self.upper_rom_bank);
+		state.write_u8(self.ram_bank);
+		state.write_bool(self.ram_enabled);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u8()?;
+		self.upper_rom_bank = state.read_u8()? & 0x1;
+		self.ram_bank = state.read_u8()?;
+		self.ram_enabled = state.read_bool()?;
+
+		Ok(())
 	}
 }
\ No newline at end of file
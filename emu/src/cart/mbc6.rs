@@ -0,0 +1,279 @@
+use super::{MBC, check_sram, load_fixed_ram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// Net de Get, the only MBC6 game, has 32KiB of RAM and a 1MiB Macronix flash chip
+const RAM_SIZE: usize = 0x8000;
+const FLASH_SIZE: usize = 0x100000;
+// the flash is erased 128KiB at a time
+const SECTOR_SIZE: usize = 0x20000;
+
+// the manufacturer and device IDs read back in ID mode
+const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FlashState {
+	Read,
+	Unlock1,		// got 0xAA at 0x5555
+	Unlock2,		// got 0x55 at 0x2AAA, the next write to 0x5555 is a command
+	Id,				// reads return the chip ID
+	Program,		// the next write programs a byte
+	EraseUnlock,	// got 0x80, waiting for a second unlock sequence
+	EraseUnlock1,
+	EraseUnlock2,	// the next write picks a sector or the whole chip
+}
+
+impl FlashState {
+	fn from_u8(from: u8) -> Option<Self> {
+		match from {
+			0 => Some(Self::Read),
+			1 => Some(Self::Unlock1),
+			2 => Some(Self::Unlock2),
+			3 => Some(Self::Id),
+			4 => Some(Self::Program),
+			5 => Some(Self::EraseUnlock),
+			6 => Some(Self::EraseUnlock1),
+			7 => Some(Self::EraseUnlock2),
+			_ => None,
+		}
+	}
+}
+
+// two independently switched 8KiB windows at 0x4000 and 0x6000, each mapping either ROM or flash, and two 4KiB RAM
+// windows at 0xA000 and 0xB000
+pub struct MBC6 {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+	flash: Vec<u8>,
+
+	rom_banks: [u8; 2],	// 8KiB banks
+	use_flash: [bool; 2],
+	ram_banks: [u8; 2],	// 4KiB banks
+
+	rom_bank_count: usize,
+
+	ram_enabled: bool,
+	flash_enabled: bool,
+	flash_write_enabled: bool,
+
+	flash_state: FlashState,
+}
+
+impl MBC6 {
+
+	pub fn new(rom: Vec<u8>) -> Self {
+
+		let rom_bank_count = rom.len() / 0x2000;
+
+		Self {
+			rom,
+			ram: vec![0; RAM_SIZE],
+			flash: vec![0xFF; FLASH_SIZE],
+
+			rom_banks: [0; 2],
+			use_flash: [false; 2],
+			ram_banks: [0; 2],
+
+			rom_bank_count,
+
+			ram_enabled: false,
+			flash_enabled: false,
+			flash_write_enabled: false,
+
+			flash_state: FlashState::Read,
+		}
+
+	}
+
+	fn ram_addr(&self, addr: u16) -> usize {
+		let window = (addr as usize - 0xA000) >> 12;
+
+		(self.ram_banks[window] as usize * 0x1000 + (addr as usize & 0xFFF)) % RAM_SIZE
+	}
+
+	fn flash_addr(&self, window: usize, addr: u16) -> usize {
+		(self.rom_banks[window] as usize * 0x2000 + (addr as usize & 0x1FFF)) % FLASH_SIZE
+	}
+
+	fn flash_write(&mut self, addr: usize, write: u8) {
+
+		// the unlock and command addresses only look at the bottom 15 bits
+		let command_addr = addr & 0x7FFF;
+
+		// 0xF0 always gets back to read mode, unless it's the byte being programmed
+		if write == 0xF0 && self.flash_state != FlashState::Program {
+			self.flash_state = FlashState::Read;
+			return;
+		}
+
+		self.flash_state = match (self.flash_state, command_addr, write) {
+			(FlashState::Read, 0x5555, 0xAA) | (FlashState::Id, 0x5555, 0xAA) => FlashState::Unlock1,
+			(FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+			(FlashState::Unlock2, 0x5555, 0x90) => FlashState::Id,
+			(FlashState::Unlock2, 0x5555, 0xA0) => FlashState::Program,
+			(FlashState::Unlock2, 0x5555, 0x80) => FlashState::EraseUnlock,
+			(FlashState::EraseUnlock, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+			(FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+
+			(FlashState::Program, _, _) => {
+				// programming can only clear bits, erasing sets them again
+				if self.flash_write_enabled {
+					self.flash[addr] &= write;
+				}
+
+				FlashState::Read
+			},
+			(FlashState::EraseUnlock2, _, 0x30) => {
+				if self.flash_write_enabled {
+					let sector = addr & !(SECTOR_SIZE - 1);
+
+					self.flash[sector..sector + SECTOR_SIZE].fill(0xFF);
+				}
+
+				FlashState::Read
+			},
+			(FlashState::EraseUnlock2, 0x5555, 0x10) => {
+				if self.flash_write_enabled {
+					self.flash.fill(0xFF);
+				}
+
+				FlashState::Read
+			},
+
+			// anything out of sequence abandons the command
+			(FlashState::Id, _, _) => FlashState::Id,
+			_ => FlashState::Read,
+		};
+
+	}
+
+}
+
+impl MBC for MBC6 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom or flash banks
+			0x4000	..= 0x7FFF	=> {
+				let window = (addr as usize - 0x4000) >> 13;
+
+				if self.use_flash[window] {
+					if !self.flash_enabled {
+						return 0xFF;
+					}
+
+					let flash_addr = self.flash_addr(window, addr);
+
+					match self.flash_state {
+						FlashState::Id => FLASH_ID[flash_addr & 1],
+						_ => self.flash[flash_addr],
+					}
+				} else {
+					let bank = self.rom_banks[window] as usize % self.rom_bank_count;
+
+					self.rom[bank * 0x2000 + (addr as usize & 0x1FFF)]
+				}
+			},
+			// ram banks
+			0xA000	..= 0xBFFF	if self.ram_enabled => self.ram[self.ram_addr(addr)],
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// ram enable register
+			0		..= 0x03FF	=> self.ram_enabled = write & 0xF == 0xA,
+			// ram bank registers
+			0x0400	..= 0x07FF	=> self.ram_banks[0] = write & 0x7,
+			0x0800	..= 0x0BFF	=> self.ram_banks[1] = write & 0x7,
+			// flash enable register
+			0x0C00	..= 0x0FFF	=> self.flash_enabled = write & 1 != 0,
+			// flash write enable register
+			0x1000				=> self.flash_write_enabled = write & 1 != 0,
+			// rom/flash bank registers
+			0x2000	..= 0x27FF	=> self.rom_banks[0] = write & 0x7F,
+			0x2800	..= 0x2FFF	=> self.use_flash[0] = write == 0x08,
+			0x3000	..= 0x37FF	=> self.rom_banks[1] = write & 0x7F,
+			0x3800	..= 0x3FFF	=> self.use_flash[1] = write == 0x08,
+			// flash commands
+			0x4000	..= 0x7FFF	=> {
+				let window = (addr as usize - 0x4000) >> 13;
+
+				if self.use_flash[window] && self.flash_enabled {
+					self.flash_write(self.flash_addr(window, addr), write);
+				}
+			},
+			// ram write
+			0xA000	..= 0xBFFF	if self.ram_enabled => {
+				let addr = self.ram_addr(addr);
+
+				self.ram[addr] = write;
+			},
+			_ => {}
+		}
+
+	}
+
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	// the RAM, followed by the flash
+	fn dump_sram(&self) -> Vec<u8> {
+		let mut sram = self.ram.clone();
+
+		sram.extend_from_slice(&self.flash);
+
+		sram
+	}
+
+	fn load_sram(&mut self, mut sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, RAM_SIZE + FLASH_SIZE)?;
+
+		self.flash = sram.split_off(RAM_SIZE);
+		self.ram = sram;
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.ram);
+		state.write_vec(&self.flash);
+
+		state.write_bytes(&self.rom_banks);
+		state.write_bool(self.use_flash[0]);
+		state.write_bool(self.use_flash[1]);
+		state.write_bytes(&self.ram_banks);
+
+		state.write_bool(self.ram_enabled);
+		state.write_bool(self.flash_enabled);
+		state.write_bool(self.flash_write_enabled);
+
+		state.write_u8(self.flash_state as u8);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_fixed_ram(state, &mut self.ram)?;
+		load_fixed_ram(state, &mut self.flash)?;
+
+		state.read_bytes(&mut self.rom_banks)?;
+		self.use_flash = [state.read_bool()?, state.read_bool()?];
+		state.read_bytes(&mut self.ram_banks)?;
+
+		self.ram_enabled = state.read_bool()?;
+		self.flash_enabled = state.read_bool()?;
+		self.flash_write_enabled = state.read_bool()?;
+
+		self.flash_state = FlashState::from_u8(state.read_u8()?).ok_or(StateError::InvalidValue("MBC6 flash state"))?;
+
+		self.rom_banks.iter_mut().for_each(|bank| *bank &= 0x7F);
+		self.ram_banks.iter_mut().for_each(|bank| *bank &= 0x7);
+
+		Ok(())
+	}
+}
\ No newline at end of file
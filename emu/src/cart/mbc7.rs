@@ -0,0 +1,396 @@
+use super::MBC;
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// the accelerometer reads this when level, and moves by about this much per g
+const ACCEL_CENTRE: f32 = 0x81D0 as f32;
+const ACCEL_PER_G: f32 = 0x70 as f32;
+
+// carts use either a 93LC56 (256 bytes) or a 93LC66 (512 bytes). Nothing in the header says which, so saves decide
+const EEPROM_SIZES: [usize; 2] = [256, 512];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum EepromState {
+	Idle,		// waiting for a start bit
+	Command,	// shifting in the opcode and address
+	Read,		// shifting a word out
+	Write,		// shifting in a word to write
+	WriteAll,	// shifting in a word to write everywhere
+}
+
+impl EepromState {
+	fn from_u8(from: u8) -> Option<Self> {
+		match from {
+			0 => Some(Self::Idle),
+			1 => Some(Self::Command),
+			2 => Some(Self::Read),
+			3 => Some(Self::Write),
+			4 => Some(Self::WriteAll),
+			_ => None,
+		}
+	}
+}
+
+// a microwire EEPROM in 16-bit mode, bit banged by the game through one of the MBC7 registers
+struct Eeprom {
+	data: Vec<u8>,	// 16-bit words, little endian
+
+	cs: bool,
+	clk: bool,
+	di: bool,
+	dout: bool,
+
+	state: EepromState,
+	shift: u16,
+	bits: u8,
+	address: u8,
+	write_enabled: bool,
+}
+
+impl Eeprom {
+
+	fn new() -> Self {
+		Self {
+			data: vec![0xFF; EEPROM_SIZES[0]],
+
+			cs: false,
+			clk: false,
+			di: false,
+			dout: true,
+
+			state: EepromState::Idle,
+			shift: 0,
+			bits: 0,
+			address: 0,
+			write_enabled: false,
+		}
+	}
+
+	fn word_mask(&self) -> u8 {
+		(self.data.len() / 2 - 1) as u8
+	}
+
+	fn word(&self, address: u8) -> u16 {
+		let i = (address & self.word_mask()) as usize * 2;
+
+		u16::from_le_bytes([self.data[i], self.data[i + 1]])
+	}
+
+	fn set_word(&mut self, address: u8, word: u16) {
+		let i = (address & self.word_mask()) as usize * 2;
+
+		self.data[i..i + 2].copy_from_slice(&word.to_le_bytes());
+	}
+
+	fn read(&self) -> u8 {
+		(self.cs as u8) << 7 | (self.clk as u8) << 6 | (self.di as u8) << 1 | self.dout as u8
+	}
+
+	fn write(&mut self, write: u8) {
+
+		let cs = write & 0x80 != 0;
+		let clk = write & 0x40 != 0;
+		self.di = write & 0x02 != 0;
+
+		// deselecting the chip abandons whatever it was doing
+		if !cs {
+			self.state = EepromState::Idle;
+		} else if clk && !self.clk {
+			self.clock_bit();
+		}
+
+		self.cs = cs;
+		self.clk = clk;
+
+	}
+
+	// called on every rising edge of the clock while the chip is selected
+	fn clock_bit(&mut self) {
+
+		match self.state {
+			EepromState::Idle => {
+				if self.di {
+					self.state = EepromState::Command;
+					self.shift = 0;
+					self.bits = 0;
+				}
+			},
+			EepromState::Command => {
+				self.shift = self.shift << 1 | self.di as u16;
+				self.bits += 1;
+
+				// 2 opcode bits and 8 address bits
+				if self.bits == 10 {
+					self.command((self.shift >> 8) as u8 & 0x3, self.shift as u8);
+				}
+			},
+			EepromState::Read => {
+				self.dout = self.shift & 0x8000 != 0;
+				self.shift <<= 1;
+				self.bits -= 1;
+
+				if self.bits == 0 {
+					self.state = EepromState::Idle;
+				}
+			},
+			EepromState::Write | EepromState::WriteAll => {
+				self.shift = self.shift << 1 | self.di as u16;
+				self.bits += 1;
+
+				if self.bits == 16 {
+					if self.write_enabled {
+						match self.state {
+							EepromState::Write => self.set_word(self.address, self.shift),
+							_ => (0..=self.word_mask()).for_each(|address| self.set_word(address, self.shift)),
+						}
+					}
+
+					// writes finish instantly, so the chip reports that it's ready straight away
+					self.dout = true;
+					self.state = EepromState::Idle;
+				}
+			},
+		}
+
+	}
+
+	fn command(&mut self, opcode: u8, address: u8) {
+
+		self.state = EepromState::Idle;
+		self.shift = 0;
+		self.bits = 0;
+
+		match opcode {
+			// READ: a dummy 0 bit, then the word
+			0b10 => {
+				self.shift = self.word(address);
+				self.bits = 16;
+				self.dout = false;
+				self.state = EepromState::Read;
+			},
+			// WRITE
+			0b01 => {
+				self.address = address;
+				self.state = EepromState::Write;
+			},
+			// ERASE
+			0b11 => {
+				if self.write_enabled {
+					self.set_word(address, 0xFFFF);
+				}
+
+				self.dout = true;
+			},
+			// the top 2 address bits pick one of the other commands
+			_ => match address >> 6 {
+				// EWDS
+				0b00 => self.write_enabled = false,
+				// WRAL
+				0b01 => self.state = EepromState::WriteAll,
+				// ERAL
+				0b10 => {
+					if self.write_enabled {
+						self.data.fill(0xFF);
+					}
+
+					self.dout = true;
+				},
+				// EWEN
+				_ => self.write_enabled = true,
+			},
+		}
+
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.data);
+
+		state.write_bool(self.cs);
+		state.write_bool(self.clk);
+		state.write_bool(self.di);
+		state.write_bool(self.dout);
+
+		state.write_u8(self.state as u8);
+		state.write_u16(self.shift);
+		state.write_u8(self.bits);
+		state.write_u8(self.address);
+		state.write_bool(self.write_enabled);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.data = state.read_vec()?;
+
+		self.cs = state.read_bool()?;
+		self.clk = state.read_bool()?;
+		self.di = state.read_bool()?;
+		self.dout = state.read_bool()?;
+
+		self.state = EepromState::from_u8(state.read_u8()?).ok_or(StateError::InvalidValue("MBC7 EEPROM state"))?;
+		self.shift = state.read_u16()?;
+		self.bits = state.read_u8()?;
+		self.address = state.read_u8()?;
+		self.write_enabled = state.read_bool()?;
+
+		if !EEPROM_SIZES.contains(&self.data.len()) || self.bits > 16 {
+			return Err(StateError::InvalidValue("MBC7 EEPROM"));
+		}
+
+		Ok(())
+	}
+
+}
+
+// Kirby Tilt 'n' Tumble and Command Master: an accelerometer, and an EEPROM in place of RAM
+pub struct MBC7 {
+	rom: Vec<u8>,
+
+	rom_bank: u8,
+	rom_banks: usize,
+
+	// both have to be set before the registers at 0xA000 can be used
+	ram_enabled: bool,
+	ram_enabled2: bool,
+
+	accel: (u16, u16),			// what the sensor currently reads
+	accel_latched: (u16, u16),	// what the game sees
+	latch_armed: bool,			// the latch has been erased, and the next latch command will take a reading
+
+	eeprom: Eeprom,
+}
+
+impl MBC7 {
+
+	pub fn new(rom: Vec<u8>) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+
+		Self {
+			rom,
+
+			rom_bank: 1,
+			rom_banks,
+
+			ram_enabled: false,
+			ram_enabled2: false,
+
+			accel: (ACCEL_CENTRE as u16, ACCEL_CENTRE as u16),
+			accel_latched: (0x8000, 0x8000),
+			latch_armed: false,
+
+			eeprom: Eeprom::new(),
+		}
+
+	}
+
+	fn registers_enabled(&self) -> bool {
+		self.ram_enabled && self.ram_enabled2
+	}
+
+}
+
+impl MBC for MBC7 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom bank x
+			0x4000	..= 0x7FFF	=> {
+				let bank = self.rom_bank as usize % self.rom_banks;
+
+				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
+			},
+			// registers, picked by bits 4-7 of the address
+			0xA000	..= 0xAFFF	if self.registers_enabled() => match (addr >> 4) & 0xF {
+				0x2 => self.accel_latched.0 as u8,
+				0x3 => (self.accel_latched.0 >> 8) as u8,
+				0x4 => self.accel_latched.1 as u8,
+				0x5 => (self.accel_latched.1 >> 8) as u8,
+				0x6 => 0x00,
+				0x8 => self.eeprom.read(),
+				_ => 0xFF,
+			},
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// ram enable register
+			0		..= 0x1FFF	=> self.ram_enabled = write & 0xF == 0xA,
+			// rom bank register
+			0x2000	..= 0x3FFF	=> self.rom_bank = write & 0x7F,
+			// second ram enable register
+			0x4000	..= 0x5FFF	=> self.ram_enabled2 = write == 0x40,
+			0xA000	..= 0xAFFF	if self.registers_enabled() => match (addr >> 4) & 0xF {
+				// erase the latched reading
+				0x0 if write == 0x55 => {
+					self.accel_latched = (0x8000, 0x8000);
+					self.latch_armed = true;
+				},
+				// latch a new reading
+				0x1 if write == 0xAA && self.latch_armed => {
+					self.accel_latched = self.accel;
+					self.latch_armed = false;
+				},
+				0x8 => self.eeprom.write(write),
+				_ => {},
+			},
+			_ => {}
+		}
+
+	}
+
+	// the EEPROM doesn't need a battery, but it's saved the same way
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	fn set_accelerometer(&mut self, x: f32, y: f32) {
+		let reading = |g: f32| (ACCEL_CENTRE + g * ACCEL_PER_G).clamp(0.0, u16::MAX as f32) as u16;
+
+		self.accel = (reading(x), reading(y));
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		self.eeprom.data.clone()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		if !EEPROM_SIZES.contains(&sram.len()) {
+			return Err(Error::InvalidSram { expected: self.eeprom.data.len(), actual: sram.len() });
+		}
+
+		self.eeprom.data = sram;
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.rom_bank);
+		state.write_bool(self.ram_enabled);
+		state.write_bool(self.ram_enabled2);
+
+		state.write_u16(self.accel.0);
+		state.write_u16(self.accel.1);
+		state.write_u16(self.accel_latched.0);
+		state.write_u16(self.accel_latched.1);
+		state.write_bool(self.latch_armed);
+
+		self.eeprom.save_state(state);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.rom_bank = state.read_u8()? & 0x7F;
+		self.ram_enabled = state.read_bool()?;
+		self.ram_enabled2 = state.read_bool()?;
+
+		self.accel = (state.read_u16()?, state.read_u16()?);
+		self.accel_latched = (state.read_u16()?, state.read_u16()?);
+		self.latch_armed = state.read_bool()?;
+
+		self.eeprom.load_state(state)
+	}
+}
\ No newline at end of file
@@ -0,0 +1,200 @@
+use super::{MBC, save_ram, load_ram, check_sram};
+use super::header::{CartHeader, CartError};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// MMM01 multicarts boot into a menu stored in the last 32KiB of the ROM, so that's where the MMM01 header is. The
+// header at the start belongs to whichever game comes first
+pub fn menu_header(rom: &[u8]) -> Option<Result<CartHeader, CartError>> {
+	if rom.len() < 0x8000 || !rom.len().is_multiple_of(0x8000) {
+		return None;
+	}
+
+	let menu = &rom[rom.len() - 0x8000..];
+
+	match menu[0x147] {
+		0xB ..= 0xD => Some(CartHeader::parse(menu)),
+		_ => None,
+	}
+}
+
+// until the menu locks the mapper, the upper bank bits and bank mask can be set, picking which part of the ROM the
+// chosen game sees. After that it behaves like an MBC1 confined to that part
+pub struct MMM01 {
+	rom: Vec<u8>,
+	ram: Option<Vec<u8>>,
+
+	rom_bank: u16,		// 9 bits. Bits 5-8 are only writable before locking
+	rom_bank_mask: u16,	// bits 1-4 of the bank that the game can't change once locked
+	ram_bank: u8,
+
+	rom_banks: usize,
+	ram_banks: usize,
+
+	ram_enabled: bool,
+	locked: bool,
+	has_battery: bool,
+}
+
+impl MMM01 {
+
+	pub fn new(rom: Vec<u8>, cart_type: u8, ram_size: usize) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+		let ram = if cart_type != 0xB && ram_size != 0 { Some(vec![0; ram_size]) } else { None };
+
+		Self {
+			rom,
+			ram,
+
+			rom_bank: 0,
+			rom_bank_mask: 0,
+			ram_bank: 0,
+
+			rom_banks,
+			ram_banks: ram_size >> 13,
+
+			ram_enabled: false,
+			locked: false,
+			has_battery: cart_type == 0xD,
+		}
+
+	}
+
+	// the bits of the bank register the game can still write once locked
+	fn writable_bits(&self) -> u16 {
+		0x1F & !self.rom_bank_mask
+	}
+
+	fn rom_addr(&self, addr: u16) -> usize {
+
+		// before locking, the menu is mapped in place of everything
+		if !self.locked {
+			return self.rom.len() - 0x8000 + addr as usize;
+		}
+
+		let bank = match addr {
+			0 ..= 0x3FFF => self.rom_bank & !self.writable_bits(),
+			_ => {
+				// the same zero bank quirk as MBC1, limited to the bits the game controls
+				if self.rom_bank & self.writable_bits() == 0 {
+					self.rom_bank | 1
+				} else {
+					self.rom_bank
+				}
+			},
+		} as usize % self.rom_banks;
+
+		(addr as usize & 0x3FFF) + bank * 0x4000
+
+	}
+
+}
+
+impl MBC for MMM01 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			0		..= 0x7FFF	=> self.rom[self.rom_addr(addr)],
+			// ram bank x
+			0xA000	..= 0xBFFF	=> {
+				if let Some(ref ram) = self.ram {
+					if self.ram_enabled && self.ram_banks != 0 {
+						let bank = self.ram_bank as usize % self.ram_banks;
+
+						return ram[addr as usize - 0xA000 + (bank * 0x2000)];
+					}
+				}
+
+				0xFF
+			},
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		match addr {
+			// ram enable register. Bit 6 locks the mapper
+			0		..= 0x1FFF	=> {
+				self.ram_enabled = write & 0xF == 0xA;
+
+				if write & 0x40 != 0 {
+					self.locked = true;
+				}
+			},
+			// rom bank register
+			0x2000	..= 0x3FFF	=> {
+				let writable = if self.locked { self.writable_bits() } else { 0x7F };
+
+				self.rom_bank = (self.rom_bank & !writable) | (write as u16 & writable);
+			},
+			// ram bank register. Before locking, bits 4-5 are bits 7-8 of the rom bank
+			0x4000	..= 0x5FFF	=> {
+				if self.locked {
+					self.ram_bank = (self.ram_bank & 0xC) | (write & 0x3);
+				} else {
+					self.ram_bank = write & 0xF;
+					self.rom_bank = (self.rom_bank & 0x7F) | ((write as u16 >> 4) & 0x3) << 7;
+				}
+			},
+			// bits 2-5 are the rom bank mask before locking
+			0x6000	..= 0x7FFF	if !self.locked => self.rom_bank_mask = ((write as u16 >> 2) & 0xF) << 1,
+			// ram write
+			0xA000	..= 0xBFFF	=> {
+				if let Some(ref mut ram) = self.ram {
+					if self.ram_enabled && self.ram_banks != 0 {
+						let bank = self.ram_bank as usize % self.ram_banks;
+
+						ram[addr as usize - 0xA000 + (bank * 0x2000)] = write;
+					}
+				}
+			},
+			_ => {}
+		}
+
+	}
+
+	fn is_battery_backed(&self) -> bool {
+		self.has_battery
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		self.ram.clone().unwrap_or_default()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.as_ref().map_or(0, |ram| ram.len()))?;
+
+		if self.ram.is_some() {
+			self.ram = Some(sram);
+		}
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		save_ram(state, &self.ram);
+
+		state.write_u16(self.rom_bank);
+		state.write_u16(self.rom_bank_mask);
+		state.write_u8(self.ram_bank);
+
+		state.write_bool(self.ram_enabled);
+		state.write_bool(self.locked);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_ram(state, &mut self.ram)?;
+
+		self.rom_bank = state.read_u16()? & 0x1FF;
+		self.rom_bank_mask = state.read_u16()? & 0x1E;
+		self.ram_bank = state.read_u8()? & 0xF;
+
+		self.ram_enabled = state.read_bool()?;
+		self.locked = state.read_bool()?;
+
+		Ok(())
+	}
+}
\ No newline at end of file
@@ -3,34 +3,142 @@ mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod huc1;
+mod huc3;
+mod camera;
+mod tama5;
+mod header;
 
-pub trait MBC {
+pub use header::{CartHeader, CartError, HeaderWarning, CgbSupport, Licensee, Region, cart_type_name};
+pub use camera::{CAMERA_WIDTH, CAMERA_HEIGHT};
+
+use crate::savestate::{StateReader, StateWriter, StateError};
+use crate::Error;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// cart RTCs run off their own 32768Hz crystal, so they tick once per second of normal speed T-states
+const CYCLES_PER_SECOND: u64 = 4194304;
+
+pub trait MBC: Send {
 	fn read(&self, addr: u16) -> u8;
 	fn write(&mut self, addr: u16, write: u8);
 
 	fn is_battery_backed(&self) -> bool;
-	fn load_sram(&mut self, sram: Vec<u8>);
+	// fails if the save doesn't fit the cart's RAM, which leaves the RAM as it was
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error>;
 	fn dump_sram(&self) -> Vec<u8>;
+
+	// advances anything on the cart that runs on its own clock, like an RTC
+	fn tick(&mut self, _cycles: u64) {}
+
+	// whether the cart's rumble motor is running
+	fn rumble(&self) -> bool { false }
+
+	// MBC7 tilt sensor, in g. Positive x is tilting right, positive y is tilting towards the player
+	fn set_accelerometer(&mut self, _x: f32, _y: f32) {}
+	// Pocket Camera sensor, CAMERA_WIDTH * CAMERA_HEIGHT 8-bit greyscale pixels where 0 is black
+	fn set_camera_image(&mut self, _image: &[u8]) {}
+
+	// bank registers and external RAM. ROM contents are not included
+	fn save_state(&self, state: &mut StateWriter);
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+fn check_sram(sram: &[u8], expected: usize) -> Result<(), Error> {
+	if sram.len() != expected {
+		return Err(Error::InvalidSram { expected, actual: sram.len() });
+	}
+
+	Ok(())
+}
+
+fn save_ram(state: &mut StateWriter, ram: &Option<Vec<u8>>) {
+	match ram {
+		Some(ram) => {
+			state.write_bool(true);
+			state.write_vec(ram);
+		},
+		None => state.write_bool(false),
+	}
 }
 
-pub fn create_cart(rom: Vec<u8>) -> Box<dyn MBC> {
+// for carts that always have the same amount of RAM
+fn load_fixed_ram(state: &mut StateReader, ram: &mut Vec<u8>) -> Result<(), StateError> {
+	let saved = state.read_vec()?;
 
-	let ram_size = match rom[0x149] {
-		2 			=> 8 * 1024,
-		3 			=> 32 * 1024,
-		4 			=> 128 * 1024,
-		5			=> 64 * 1024,
-		0 | 1 | _ 	=> 0,
-	};
+	if saved.len() != ram.len() {
+		return Err(StateError::InvalidValue("cartridge RAM size"));
+	}
+
+	*ram = saved;
 
-	match rom[0x147] {
-		0x0 	=> Box::new(mbc0::MBC0::new(rom)),
+	Ok(())
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+// the cart's RAM layout comes from the ROM header, so a state has to match it exactly
+fn load_ram(state: &mut StateReader, ram: &mut Option<Vec<u8>>) -> Result<(), StateError> {
+	let saved = if state.read_bool()? { Some(state.read_vec()?) } else { None };
+
+	match (ram.as_ref(), saved.as_ref()) {
+		(Some(ram), Some(saved)) if ram.len() == saved.len() => {},
+		(None, None) => {},
+		_ => return Err(StateError::InvalidValue("cartridge RAM size")),
+	}
+
+	*ram = saved;
+
+	Ok(())
+}
+
+// the header the cart is mapped by. That's the one at the start, except on MMM01 multicarts
+pub fn cart_header(rom: &[u8]) -> Result<CartHeader, CartError> {
+	mmm01::menu_header(rom).unwrap_or_else(|| CartHeader::parse(rom))
+}
+
+pub fn create_cart(mut rom: Vec<u8>) -> Result<Box<dyn MBC>, CartError> {
+
+	// the menu has to stay at the end of the ROM, so these are never padded
+	if let Some(header) = mmm01::menu_header(&rom) {
+		let header = header?;
+
+		return Ok(Box::new(mmm01::MMM01::new(rom, header.cart_type, header.ram_size)));
+	}
+
+	let header = CartHeader::parse(&rom)?;
+	let ram_size = header.ram_size;
+
+	// the MBCs trust the header, so a truncated dump is padded out to the size it claims to be, and to whole banks
+	if rom.len() < header.rom_size {
+		rom.resize(header.rom_size, 0xFF);
+	}
+
+	if !rom.len().is_multiple_of(0x4000) {
+		rom.resize(rom.len().next_multiple_of(0x4000), 0xFF);
+	}
+
+	Ok(match header.cart_type {
+		0x0 | 0x8 | 0x9 => Box::new(mbc0::MBC0::new(rom, ram_size)),
 		0x1 | 0x2 | 0x3 => Box::new(mbc1::MBC1::new(rom, ram_size)),
 		0x5 | 0x6 => Box::new(mbc2::MBC2::new(rom)),
 		0xF | 0x10 | 0x11 | 0x12 | 0x13 => Box::new(mbc3::MBC3::new(rom, ram_size)),
 		0x19 | 0x1A | 0x1B | 0x1C | 0x1D | 0x1E => Box::new(mbc5::MBC5::new(rom, ram_size)),
+		0xB ..= 0xD => Box::new(mmm01::MMM01::new(rom, header.cart_type, ram_size)),
+		0x20	=> Box::new(mbc6::MBC6::new(rom)),
+		0x22	=> Box::new(mbc7::MBC7::new(rom)),
+		0xFC	=> Box::new(camera::Camera::new(rom)),
+		0xFD	=> Box::new(tama5::Tama5::new(rom)),
+		0xFE	=> Box::new(huc3::HuC3::new(rom, ram_size)),
+		0xFF	=> Box::new(huc1::HuC1::new(rom, ram_size)),
 
-		_ => panic!("unimplenented cart type 0x{:X}", rom[0x147])
-	}
+		cart_type => return Err(CartError::UnsupportedType(cart_type)),
+	})
 
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use super::{MBC, check_sram, load_fixed_ram};
+use crate::Error;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+const RAM_SIZE: usize = 32;
+
+// registers, picked by writing to 0xA001
+const REG_ROM_LOW: u8 = 0x0;
+const REG_ROM_HIGH: u8 = 0x1;
+const REG_DATA_LOW: u8 = 0x4;
+const REG_DATA_HIGH: u8 = 0x5;
+const REG_ADDR_HIGH: u8 = 0x6;	// bit 0 is bit 4 of the address, bits 1-3 are the command
+const REG_ADDR_LOW: u8 = 0x7;	// writing this runs the command
+const REG_READY: u8 = 0xA;
+const REG_READ_LOW: u8 = 0xC;
+const REG_READ_HIGH: u8 = 0xD;
+
+// commands
+const CMD_RAM_WRITE: u8 = 0x0;
+const CMD_RAM_READ: u8 = 0x1;
+
+// Bandai's mapper for Game de Hakken!! Tamagotchi 3. Everything goes through a nibble-wide register file at
+// 0xA000-0xA001, including 32 bytes of battery backed RAM. The TAMA6 microcontroller and its RTC aren't emulated, so
+// any other command does nothing
+pub struct Tama5 {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+
+	rom_banks: usize,
+
+	register: u8,	// selected register
+	rom_bank: u8,
+	data: u8,		// the byte to write
+	address: u8,	// 5-bit address, along with the command in the upper bits
+	read: u8,		// the result of the last read
+}
+
+impl Tama5 {
+
+	pub fn new(rom: Vec<u8>) -> Self {
+
+		let rom_banks = rom.len() / 0x4000;
+
+		Self {
+			rom,
+			ram: vec![0; RAM_SIZE],
+
+			rom_banks,
+
+			register: 0,
+			rom_bank: 1,
+			data: 0,
+			address: 0,
+			read: 0,
+		}
+
+	}
+
+	fn run_command(&mut self) {
+
+		let addr = self.address as usize & 0x1F;
+
+		match self.address >> 5 {
+			CMD_RAM_WRITE => self.ram[addr] = self.data,
+			CMD_RAM_READ => self.read = self.ram[addr],
+			_ => {},
+		}
+
+	}
+
+}
+
+impl MBC for Tama5 {
+	fn read(&self, addr: u16) -> u8 {
+
+		match addr {
+			// rom bank 0
+			0		..= 0x3FFF	=> self.rom[addr as usize],
+			// rom bank x
+			0x4000	..= 0x7FFF	=> {
+				let bank = self.rom_bank as usize % self.rom_banks;
+
+				self.rom[(addr as usize - 0x4000) + (0x4000 * bank)]
+			},
+			// registers only have 4 bits, the top ones read as set
+			0xA000				=> match self.register {
+				// bit 0 is set once the cart is ready to be talked to
+				REG_READY => 0xF1,
+				REG_READ_LOW => 0xF0 | (self.read & 0xF),
+				REG_READ_HIGH => 0xF0 | (self.read >> 4),
+				_ => 0xFF,
+			},
+			_ => 0xFF
+		}
+
+	}
+
+	fn write(&mut self, addr: u16, write: u8) {
+
+		let nibble = write & 0xF;
+
+		match addr {
+			0xA000	=> match self.register {
+				REG_ROM_LOW => self.rom_bank = (self.rom_bank & 0x10) | nibble,
+				REG_ROM_HIGH => self.rom_bank = (self.rom_bank & 0xF) | (nibble & 1) << 4,
+				REG_DATA_LOW => self.data = (self.data & 0xF0) | nibble,
+				REG_DATA_HIGH => self.data = (self.data & 0xF) | nibble << 4,
+				REG_ADDR_HIGH => self.address = (self.address & 0xF) | nibble << 4,
+				REG_ADDR_LOW => {
+					self.address = (self.address & 0xF0) | nibble;
+					self.run_command();
+				},
+				_ => {},
+			},
+			0xA001	=> self.register = nibble,
+			_ => {}
+		}
+
+	}
+
+	fn is_battery_backed(&self) -> bool {
+		true
+	}
+
+	fn dump_sram(&self) -> Vec<u8> {
+		self.ram.clone()
+	}
+
+	fn load_sram(&mut self, sram: Vec<u8>) -> Result<(), Error> {
+		check_sram(&sram, self.ram.len())?;
+
+		self.ram = sram;
+
+		Ok(())
+	}
+
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_vec(&self.ram);
+
+		state.write_u8(self.register);
+		state.write_u8(self.rom_bank);
+		state.write_u8(self.data);
+		state.write_u8(self.address);
+		state.write_u8(self.read);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		load_fixed_ram(state, &mut self.ram)?;
+
+		self.register = state.read_u8()? & 0xF;
+		self.rom_bank = state.read_u8()? & 0x1F;
+		self.data = state.read_u8()?;
+		self.address = state.read_u8()?;
+		self.read = state.read_u8()?;
+
+		Ok(())
+	}
+}
\ No newline at end of file
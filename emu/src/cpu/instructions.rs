@@ -30,15 +30,15 @@ impl Cond {
 
 pub fn get_imm8(cpu: &mut CPU) -> u8 {
 	cpu.pc = cpu.pc.wrapping_add(1);
-	cpu.bus.borrow().read_byte(cpu.pc)
+	cpu.read_byte(cpu.pc)
 }
 
 pub fn get_imm16(cpu: &mut CPU) -> u16 {
 	cpu.pc = cpu.pc.wrapping_add(1);
-	let lo_byte = cpu.bus.borrow().read_byte(cpu.pc);
+	let lo_byte = cpu.read_byte(cpu.pc);
 
 	cpu.pc = cpu.pc.wrapping_add(1);
-	let hi_byte = cpu.bus.borrow().read_byte(cpu.pc);
+	let hi_byte = cpu.read_byte(cpu.pc);
 
 	((hi_byte as u16) << 8) | lo_byte as u16
 }
@@ -227,9 +227,8 @@ FLAGS: - - - -
 
 */
 fn EI(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
-	// ? accuracy: the effects of EI are delayed by one instruction (important for the halt bug)
-	//cpu.ei = 1;
-	cpu.ime = true;
+	// the effects of EI are delayed by one instruction (important for the halt bug)
+	cpu.ei = 1;
 
 	cpu.pc = cpu.pc.wrapping_add(1);
 }
@@ -269,13 +268,15 @@ fn HALT(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 
 MNEMONIC: STOP
 OPCODES: 0x10
-DESC: Unimplemented. Permenently halts the CPU
+DESC: Switches CPU speed on the CGB if KEY1 was armed. Otherwise unimplemented (it should permanently halt the CPU)
 FLAGS: - - - -
 
 */
 fn STOP(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	//println!("!!! STOP INSTRUCTION IS UNIMPLEMENTED !!!");
 
+	cpu.bus.switch_speed();
+
 	cpu.pc = cpu.pc.wrapping_add(1);
 }
 
@@ -583,6 +584,9 @@ fn RET_COND(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 			Cond::nc => if !c 	{ jump = true; } else { jump = false; },
 			Cond::c => 	if c 	{ jump = true; } else { jump = false; }
 		}
+
+		// checking the condition takes an M-cycle before anything is popped
+		cpu.internal_cycle();
 	}
 
 	if !jump {
@@ -708,7 +712,7 @@ fn LD_R16MEM_A(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 		set_debug_str(cpu, "r16mem", format!("{:?}-", dest_info.0));
 	}
 
-	cpu.bus.borrow_mut().write_byte(dest, cpu.registers.get_8bit_reg(Register8Bit::A));
+	cpu.write_byte(dest, cpu.registers.get_8bit_reg(Register8Bit::A));
 
 	// postinc or postdec
 	cpu.registers.set_16bit_reg(dest_info.0, ((dest as i16).wrapping_add(dest_info.1 as i16)) as u16);
@@ -739,7 +743,7 @@ fn LD_A_R16MEM(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 		set_debug_str(cpu, "r16mem", format!("{:?}-", src_info.0));
 	}
 
-	let new_a = cpu.bus.borrow().read_byte(src);
+	let new_a = cpu.read_byte(src);
 
 	cpu.set_8bit_reg(Register8Bit::A, new_a);
 
@@ -760,7 +764,7 @@ FLAGS: - - - -
 */
 fn LD_A_A16(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	let addr = get_imm16(cpu);
-	let new_value = cpu.bus.borrow().read_byte(addr);
+	let new_value = cpu.read_byte(addr);
 
 	set_debug_str(cpu, "a16", format!("0x{:X}", addr));
 
@@ -783,7 +787,7 @@ fn LD_A16_A(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 
 	set_debug_str(cpu, "a16", format!("0x{:X}", addr));
 
-	cpu.bus.borrow_mut().write_byte(addr, a_value);
+	cpu.write_byte(addr, a_value);
 
 	cpu.pc = cpu.pc.wrapping_add(1);
 }
@@ -798,7 +802,7 @@ FLAGS: - - - -
 */
 fn LDH_A_A8(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	let addr: u16 = 0xFF00 + get_imm8(cpu) as u16;
-	let new_value = cpu.bus.borrow().read_byte(addr);
+	let new_value = cpu.read_byte(addr);
 
 	set_debug_str(cpu, "a8", format!("0x{:X}", addr));
 
@@ -821,7 +825,7 @@ fn LDH_A8_A(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 
 	set_debug_str(cpu, "a8", format!("0x{:X}", addr));
 
-	cpu.bus.borrow_mut().write_byte(addr, a_value);
+	cpu.write_byte(addr, a_value);
 
 	cpu.pc = cpu.pc.wrapping_add(1);
 }
@@ -836,7 +840,7 @@ FLAGS: - - - -
 */
 fn LDH_A_C(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	let addr = 0xFF00 + cpu.get_8bit_reg(Register8Bit::C) as u16;
-	let new_value = cpu.bus.borrow().read_byte(addr);
+	let new_value = cpu.read_byte(addr);
 
 	cpu.registers.set_8bit_reg(Register8Bit::A, new_value);
 
@@ -855,7 +859,7 @@ fn LDH_C_A(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	let addr = 0xFF00 + cpu.registers.get_8bit_reg(Register8Bit::C) as u16;
 	let a_value = cpu.registers.get_8bit_reg(Register8Bit::A);
 
-	cpu.bus.borrow_mut().write_byte(addr, a_value);
+	cpu.write_byte(addr, a_value);
 
 	cpu.pc = cpu.pc.wrapping_add(1);
 }
@@ -974,8 +978,8 @@ fn LD_A16_SP(cpu: &mut CPU, opcode: u8, cycles: &mut u16) {
 	set_debug_str(cpu, "e8", format!("0x{:X}", addr));
 
 	let sp = cpu.registers.get_16bit_reg(Register16Bit::SP);
-	cpu.bus.borrow_mut().write_byte(addr, (sp & 0xFF) as u8);
-	cpu.bus.borrow_mut().write_byte(addr + 1, (sp >> 8) as u8);
+	cpu.write_byte(addr, (sp & 0xFF) as u8);
+	cpu.write_byte(addr + 1, (sp >> 8) as u8);
 
 	cpu.pc = cpu.pc.wrapping_add(1);
 
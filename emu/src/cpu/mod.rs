@@ -3,41 +3,52 @@ mod instructions;
 
 use crate::bus::*;
 use crate::interrupt::*;
+use crate::Model;
+use crate::savestate::{StateReader, StateWriter, StateError};
 use self::registers::*;
 use self::instructions::*;
 
-use std::cell::RefCell;
-use std::rc::Rc;
-
 pub struct CPU {
 	pub registers: Registers,
-	pub bus: Rc<RefCell<Bus>>,
+	pub bus: Bus,
 	pub pc: u16,
 	pub ime: bool,			// interrupt master enable
 	pub ei: u8,				// ei instruction executed; wait another cycle before enabling ime
 	pub halted: bool,		// used with the HALT instruction
 	pub instr_cycles: u64,	// the amount of cycles the last instruction took
 	pub last_instruction: String,
+
+	cycles_ticked: u64,		// T-states the current instruction has ticked the rest of the system by
 }
 
 #[allow(dead_code)]
 impl CPU {
 
-	pub fn new(bus: Rc<RefCell<Bus>>) -> Self {
+	pub fn new(bus: Bus, model: Model) -> Self {
+
+		// the DMG bootrom's flags depend on the header checksum, and a CGB leaves different values for DMG games
+		let registers = Registers::after_boot(model, bus.cgb, bus.cart.read(0x14D));
+
 		CPU {
-			registers: Registers::new(),
-			bus: bus,
+			registers,
+			bus,
 			pc: 0x100,
 			ime: false,
 			ei: 0,
 			halted: false,
 			instr_cycles: 0,
 			last_instruction: String::new(),
+
+			cycles_ticked: 0,
 		}
 	}
 
+	// runs an instruction (or services an interrupt), ticking the rest of the system one M-cycle at a time as it goes.
+	// Returns the number of T-states it took
 	pub fn cycle(&mut self) -> u64 {
 
+		self.cycles_ticked = 0;
+
 		self.ei = match self.ei {
 			1 => 2,
 			2 => {
@@ -49,17 +60,52 @@ impl CPU {
 
 		let int_cycles = self.interrupt();
 
-		if int_cycles != 0 {
+		let cycles = if int_cycles != 0 {
 			int_cycles
 		} else if self.halted {
 			4	// effectively a NOP
 		} else {
 			self.exec()
+		};
+
+		// whatever's left doesn't touch memory (e.g. the extra cycle of a taken jump), so it can be ticked at the end
+		while self.cycles_ticked < cycles {
+			self.internal_cycle();
 		}
+
+		self.instr_cycles = self.cycles_ticked;
+
+		self.cycles_ticked
+	}
+
+	// every memory access takes an M-cycle. The rest of the system is ticked first, so the access sees the state at the end of that M-cycle.
+	// That makes them only safe to use while running an instruction; anything else should peek with bus.read_byte
+	pub(crate) fn read_byte(&mut self, addr: u16) -> u8 {
+		self.internal_cycle();
+
+		self.bus.cpu_read(addr)
+	}
+
+	pub(crate) fn write_byte(&mut self, addr: u16, write: u8) {
+		self.internal_cycle();
+
+		self.bus.cpu_write(addr, write);
+
+		// the rest of the system carries on while the CPU waits, e.g. for a general purpose VRAM DMA
+		for _ in 0..self.bus.take_cpu_stall() / 4 {
+			self.internal_cycle();
+		}
+	}
+
+	// an M-cycle that doesn't access memory
+	pub(crate) fn internal_cycle(&mut self) {
+		self.bus.tick(4);
+
+		self.cycles_ticked += 4;
 	}
 
 	pub fn exec(&mut self) -> u64 {
-		let mut byte: u8 = self.bus.borrow().read_byte(self.pc);
+		let mut byte: u8 = self.read_byte(self.pc);
 		let prefixed: bool;
 
 		if byte == 0xCB {
@@ -70,7 +116,7 @@ impl CPU {
 			prefixed = true;
 
 			self.pc = self.pc.wrapping_add(1);
-			byte = self.bus.borrow().read_byte(self.pc);
+			byte = self.read_byte(self.pc);
 
 		} else {
 			prefixed = false;
@@ -113,8 +159,8 @@ impl CPU {
 	pub fn interrupt(&mut self) -> u64 {
 		// check for interrupts
 		if self.ime || self.halted {
-			let if_flags = self.bus.borrow().read_register(MemRegister::IF);
-			let ie_flags = self.bus.borrow().read_register(MemRegister::IE);
+			let if_flags = self.bus.read_register(MemRegister::IF) & 0x1F;
+			let ie_flags = self.bus.read_register(MemRegister::IE);
 
 			if if_flags & ie_flags != 0 {
 				// handle interrupt
@@ -132,15 +178,17 @@ impl CPU {
 					
 					
 					// clear the bit in IF
-					let new_if = self.bus.borrow().read_register(MemRegister::IF) & !(flag as u8);
-					self.bus.borrow_mut().write_register(MemRegister::IF, new_if);
+					let new_if = self.bus.read_register(MemRegister::IF) & !(flag as u8);
+					self.bus.write_register(MemRegister::IF, new_if);
 			
 					self.ime = false;
-			
+
+					// 2 internal M-cycles (the second is part of the push), the push itself, then 1 more to jump
+					self.internal_cycle();
 					self.push16(self.pc);
 					self.pc = source as u16;
 
-					return 25; // 5 M-cycles
+					return 20; // 5 M-cycles
 				}
 
 			}
@@ -150,36 +198,39 @@ impl CPU {
 		return 0;
 	}
 	
-	pub fn push16(&mut self, to_push: u16) {
+	// takes 3 M-cycles: SP is decremented during an internal M-cycle before the 2 writes
+	pub(crate) fn push16(&mut self, to_push: u16) {
+		self.internal_cycle();
+
 		let mut target_addr = self.dec_sp();
-		self.bus.borrow_mut().write_byte(target_addr, (to_push >> 8) as u8); // high byte
+		self.write_byte(target_addr, (to_push >> 8) as u8); // high byte
 
 		target_addr = self.dec_sp();
-		self.bus.borrow_mut().write_byte(target_addr, (to_push & 0xFF) as u8); // low byte
+		self.write_byte(target_addr, (to_push & 0xFF) as u8); // low byte
 	}
 
-	pub fn pop16(&mut self) -> u16 {
-		let low_byte = self.bus.borrow().read_byte(self.registers.get_16bit_reg(Register16Bit::SP));
+	pub(crate) fn pop16(&mut self) -> u16 {
+		let low_byte = self.read_byte(self.registers.get_16bit_reg(Register16Bit::SP));
 		let sp = self.inc_sp();
-		let hi_byte = self.bus.borrow().read_byte(sp);
+		let hi_byte = self.read_byte(sp);
 		self.inc_sp();
 
 		(hi_byte as u16) << 8 | low_byte as u16
 	}
 
-	pub fn get_deref_hl(&self) -> u8 {
-		self.bus.borrow().read_byte(self.registers.get_16bit_reg(Register16Bit::HL))
+	pub(crate) fn get_deref_hl(&mut self) -> u8 {
+		self.read_byte(self.registers.get_16bit_reg(Register16Bit::HL))
 	}
 
-	pub fn set_deref_hl(&mut self, write: u8) {
-		self.bus.borrow_mut().write_byte(self.registers.get_16bit_reg(Register16Bit::HL), write);
+	pub(crate) fn set_deref_hl(&mut self, write: u8) {
+		self.write_byte(self.registers.get_16bit_reg(Register16Bit::HL), write);
 	}
 
 	/*
 		8bit register wrappers that include [HL]
 	*/
 
-	pub fn get_8bit_reg(&mut self, reg: Register8Bit) -> u8 {
+	pub(crate) fn get_8bit_reg(&mut self, reg: Register8Bit) -> u8 {
 		if reg == Register8Bit::HL {
 			self.get_deref_hl()
 		} else {
@@ -187,7 +238,7 @@ impl CPU {
 		}
 	}
 
-	pub fn set_8bit_reg(&mut self, reg: Register8Bit, write: u8) {
+	pub(crate) fn set_8bit_reg(&mut self, reg: Register8Bit, write: u8) {
 		if reg == Register8Bit::HL {
 			self.set_deref_hl(write);
 		} else {
@@ -261,4 +312,24 @@ impl CPU {
 
 		old_value.overflowing_sub(1).0
 	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		self.registers.save_state(state);
+
+		state.write_u16(self.pc);
+		state.write_bool(self.ime);
+		state.write_u8(self.ei);
+		state.write_bool(self.halted);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.registers.load_state(state)?;
+
+		self.pc = state.read_u16()?;
+		self.ime = state.read_bool()?;
+		self.ei = state.read_u8()?;
+		self.halted = state.read_bool()?;
+
+		Ok(())
+	}
 }
\ No newline at end of file
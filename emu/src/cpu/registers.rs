@@ -1,3 +1,6 @@
+use crate::savestate::{StateReader, StateWriter, StateError};
+use crate::Model;
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Register8Bit {
 	A,
@@ -68,6 +71,28 @@ impl Registers {
 			sp: 0xFFFE,
 		}
 	}
+
+	// what each model's bootrom leaves in the registers when it jumps to the cart. Games check A to tell which
+	// hardware they're on: 0x01 for a DMG or SGB, 0xFF for an MGB or SGB2, and 0x11 for a CGB
+	pub fn after_boot(model: Model, cgb: bool, header_checksum: u8) -> Registers {
+
+		// the DMG and MGB bootroms finish by adding up the header, leaving H and C set unless the checksum was 0
+		let checksum_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+
+		let [a, f, b, c, d, e, h, l] = match model {
+			Model::Dmg0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+			Model::Dmg => [0x01, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+			Model::Mgb => [0xFF, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+			Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+			Model::Sgb2 => [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+			Model::Cgb if cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+			// running a DMG game. B depends on the title of Nintendo's games, which the bootrom has palettes for
+			Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C],
+		};
+
+		Registers { a, f, b, c, d, e, h, l, sp: 0xFFFE }
+
+	}
 }
 
 #[allow(dead_code)]
@@ -172,6 +197,23 @@ impl Registers {
 		self.f |= (set as u8) << (flag as u8);
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&[self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l]);
+		state.write_u16(self.sp);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		let mut regs = [0; 8];
+		state.read_bytes(&mut regs)?;
+
+		[self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l] = regs;
+		self.f &= 0xF0;
+
+		self.sp = state.read_u16()?;
+
+		Ok(())
+	}
+
 }
 
 impl Register16Bit {
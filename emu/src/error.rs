@@ -0,0 +1,34 @@
+use crate::cart::CartError;
+
+// anything that can go wrong loading user supplied files into the emulator
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	Cart(CartError),
+	InvalidBootrom { expected: usize, actual: usize },
+	InvalidSram { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Cart(e) => write!(f, "{}", e),
+			Self::InvalidBootrom { expected, actual } => write!(f, "bootrom is {} bytes (expected {})", actual, expected),
+			Self::InvalidSram { expected, actual } => write!(f, "save file is {} bytes, which doesn't match the cartridge's RAM (expected {})", actual, expected),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Cart(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<CartError> for Error {
+	fn from(e: CartError) -> Self {
+		Self::Cart(e)
+	}
+}
\ No newline at end of file
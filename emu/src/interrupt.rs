@@ -1,3 +1,5 @@
+use crate::savestate::{StateReader, StateWriter, StateError};
+
 // used for setting bits in IE and IF
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InterruptFlag {
@@ -53,8 +55,8 @@ impl Interrupt {
 
 		match addr {
 			0xFFFF => self.enable,
-			0xFF0F => self.flags,
-			_ => panic!("Invalid address")
+			0xFF0F => self.flags | 0xE0,	// only 5 bits exist, the rest read as 1
+			_ => 0xFF
 		}
 
 	}
@@ -63,8 +65,8 @@ impl Interrupt {
 
 		match addr {
 			0xFFFF => self.enable = write,
-			0xFF0F => self.flags = write,
-			_ => panic!("Invalid address")
+			0xFF0F => self.flags = write & 0x1F,
+			_ => {}
 		}
 
 	}
@@ -73,6 +75,11 @@ impl Interrupt {
 		self.flags |= flag as u8;
 	}
 
+	// sets several IF bits at once
+	pub fn raise_flags(&mut self, flags: u8) {
+		self.flags |= flags;
+	}
+
 	pub fn clear(&mut self, flag: InterruptFlag) {
 		self.flags &= !(flag as u8);
 	}
@@ -84,4 +91,16 @@ impl Interrupt {
 		false
 	}
 
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.enable);
+		state.write_u8(self.flags);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enable = state.read_u8()?;
+		self.flags = state.read_u8()?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
@@ -1,9 +1,8 @@
-use crate::interrupt::{Interrupt, InterruptFlag};
+use crate::savestate::{StateReader, StateWriter, StateError};
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cell::Cell;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GBInput {
 	DPadUp,
 	DPadDown,
@@ -17,6 +16,12 @@ pub enum GBInput {
 
 impl GBInput {
 
+	// in the order of the bits in JOYP: the buttons, then the dpad
+	pub const ALL: [GBInput; 8] = [
+		GBInput::BtnA, GBInput::BtnB, GBInput::BtnSelect, GBInput::BtnStart,
+		GBInput::DPadRight, GBInput::DPadLeft, GBInput::DPadUp, GBInput::DPadDown,
+	];
+
 	pub fn into_mask(&self) -> u8 {
 		match self {
 			&GBInput::BtnA 		=> 1 << 0,
@@ -48,22 +53,22 @@ impl GBInput {
 }
 
 pub struct Joypad {
-	intf: Rc<RefCell<Interrupt>>,
-
 	dpad_state: u8,
 	btn_state: u8,
 	select: u8,
+
+	polled: Cell<bool>,	// JOYP was read since the last call to take_polled()
 }
 
 impl Joypad {
 
-	pub fn new(intf: Rc<RefCell<Interrupt>>) -> Self {
+	pub(crate) fn new() -> Self {
 		Self {
-			intf: intf,
-
 			dpad_state: 0xFF,
 			btn_state: 0xFF,
-			select: 0 
+			select: 0,
+
+			polled: Cell::new(false),
 		}
 	}
 
@@ -73,10 +78,23 @@ impl Joypad {
 
 		//println!("select:{:#010b} dpad:{:#010b} btn:{:#010b}", self.select, self.dpad_state, self.btn_state);
 
+		// the top two bits aren't connected, and read as 1
 		match self.select {
-			0b10 => (self.select << 4) | self.dpad_state,
-			0b01 => (self.select << 4) | self.btn_state,
-			_ 	 => (self.select << 4) | 0xF,
+			0b10 => 0xC0 | (self.select << 4) | (self.dpad_state & 0xF),
+			0b01 => 0xC0 | (self.select << 4) | (self.btn_state & 0xF),
+			_ 	 => 0xC0 | (self.select << 4) | 0xF,
+		}
+	}
+
+	// with the SGB's multiplayer adapter, the other controllers have nothing pressed and report which one they are when
+	// neither row is selected
+	pub fn read_player(&self, player: u8) -> u8 {
+		self.polled.set(true);
+
+		match (player, self.select) {
+			(0, _) => self.read(),
+			(_, 0b11) => 0xC0 | (self.select << 4) | (0xF - player),
+			_ => 0xC0 | (self.select << 4) | 0xF,
 		}
 	}
 
@@ -97,8 +115,6 @@ impl Joypad {
 			false 	=> self.btn_state &= !input.into_mask() as u8,
 		};
 
-		self.intf.borrow_mut().raise(InterruptFlag::Joypad);
-
 	}
 
 	pub fn btn_up(&mut self, input: GBInput) {
@@ -112,4 +128,28 @@ impl Joypad {
 
 	}
 
+	// the buttons held down, one bit each in the order of GBInput::ALL
+	pub fn held(&self) -> u8 {
+		(!self.btn_state & 0xF) | (!self.dpad_state & 0xF) << 4
+	}
+
+	// whether the game has read JOYP since this was last called. A frame where it hasn't is a lag frame
+	pub fn take_polled(&self) -> bool {
+		self.polled.take()
+	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.dpad_state);
+		state.write_u8(self.btn_state);
+		state.write_u8(self.select);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.dpad_state = state.read_u8()?;
+		self.btn_state = state.read_u8()?;
+		self.select = state.read_u8()?;
+
+		Ok(())
+	}
+
 }
\ No newline at end of file
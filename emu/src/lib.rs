@@ -1,9 +1,15 @@
 use cpu::*;
 use bus::Bus;
+use apu::AudioCallback;
 use joypad::GBInput;
+use serial::LinkPort;
+use ppu::{Renderer, RenderingMode};
+use cart::{CartHeader, cart_header};
+use sgb::{Sgb, SGB_WIDTH, SGB_HEIGHT};
+use rewind::{Rewind, SNAPSHOT_INTERVAL, SNAPSHOTS_PER_SECOND};
+use movie::{Movie, MovieError, MovieSession, MovieStart, MovieStatus, input_mask, rom_hash};
+use savestate::{StateReader, StateWriter, StateError, STATE_MAGIC, STATE_VERSION};
 
-use std::cell::RefCell;
-use std::rc::Rc;
 
 pub mod cpu;
 pub mod bus;
@@ -13,61 +19,602 @@ pub mod ppu;
 pub mod joypad;
 pub mod cart;
 pub mod apu;
+pub mod serial;
+pub mod sgb;
+pub mod link;
+pub mod savestate;
+pub mod rewind;
+pub mod movie;
+pub mod error;
+
+pub use error::Error;
+
+// a frame is 154 lines of 456 dots
+pub const FRAME_CYCLES: u64 = 70224;
+
+// whether CGB hardware is being emulated. Everything else runs in DMG mode
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+	Dmg,
+	Cgb,
+}
+
+// the hardware being emulated. Each model's bootrom leaves the CPU and IO registers in a slightly different state, which
+// games can use to tell them apart
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Model {
+	Dmg0,	// the first revision of the DMG, only sold in Japan
+	Dmg,
+	Mgb,	// Game Boy Pocket and Light
+	Sgb,
+	Sgb2,
+	Cgb,
+}
+
+impl Model {
+
+	pub const ALL: [Model; 6] = [Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb, Model::Sgb2, Model::Cgb];
+
+	// picks the hardware a game was made for from the CGB and SGB flags in its header. Games that support both get the CGB
+	pub fn detect(rom: &[u8]) -> Self {
+		match (rom.get(0x143), rom.get(0x146)) {
+			(Some(flag), _) if flag & 0x80 != 0 => Model::Cgb,
+			(_, Some(0x03)) => Model::Sgb,
+			_ => Model::Dmg,
+		}
+	}
+
+	pub fn mode(self) -> Mode {
+		match self {
+			Model::Cgb => Mode::Cgb,
+			_ => Mode::Dmg,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Model::Dmg0 => "DMG0",
+			Model::Dmg => "DMG",
+			Model::Mgb => "MGB",
+			Model::Sgb => "SGB",
+			Model::Sgb2 => "SGB2",
+			Model::Cgb => "CGB",
+		}
+	}
+
+}
+
+// what a call to run_frame() or run_cycles() did
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunResult {
+	pub frame: bool,				// a new frame is ready to show
+	pub cycles: u64,				// T-states run
+	pub audio_samples: usize,		// stereo samples generated. They've already been passed to the audio callback
+	pub rumble: bool,				// the rumble motor was running at some point
+	pub rumble_changes: Vec<(u64, bool)>,	// the cycle count each time the motor turned on (true) or off (false)
+	pub breakpoint: Option<u16>,	// stopped early because the CPU reached this breakpoint
+}
 
 pub struct Gameboy {
-	pub bus: Rc<RefCell<Bus>>,
-	pub cpu: CPU,
+	pub cpu: CPU,		// owns the bus, and through it everything else
 	pub cycles: u64,	// clock cycles in T-states
+
+	model: Model,
+	header: CartHeader,
+	rom_hash: u64,
+
+	rewind: Rewind,
+	next_snapshot: u64,	// when the next rewind snapshot is due, in T-states
+
+	movie: Option<MovieSession>,	// an input movie being recorded or played back
+
+	breakpoints: Vec<u16>,
 }
 
 impl Gameboy {
 
-	pub fn new(cart: Vec<u8>, audio_callback: Box<dyn Fn(&[f32])>) -> Gameboy {
+	// emulates the hardware the cart was made for
+	pub fn new(cart: Vec<u8>, audio_callback: AudioCallback) -> Result<Gameboy, Error> {
+		let model = Model::detect(&cart);
 
-		let bus = Rc::new(RefCell::new(Bus::new(cart, audio_callback)));
+		Self::with_model(cart, audio_callback, model)
+	}
 
-		Gameboy {
-			bus: Rc::clone(&bus),
-			cpu: CPU::new(Rc::clone(&bus)),
-			cycles: 0
-		}
+	// starts up in the state the model's bootrom leaves it in
+	pub fn with_model(cart: Vec<u8>, audio_callback: AudioCallback, model: Model) -> Result<Gameboy, Error> {
+
+		let header = cart_header(&cart)?;
+		let rom_hash = rom_hash(&cart);
+
+		let bus = Bus::new(cart, audio_callback, model)?;
+
+		let cpu = CPU::new(bus, model);
+
+		Ok(Gameboy {
+			cpu,
+			cycles: 0,
+
+			model,
+			header,
+			rom_hash,
+
+			rewind: Rewind::new(0),
+			next_snapshot: 0,
+
+			movie: None,
+
+			breakpoints: Vec::new(),
+		})
+
+	}
+
+	pub fn bus(&self) -> &Bus {
+		&self.cpu.bus
+	}
+
+	pub fn bus_mut(&mut self) -> &mut Bus {
+		&mut self.cpu.bus
+	}
+
+	pub fn model(&self) -> Model {
+		self.model
+	}
+
+	pub fn mode(&self) -> Mode {
+		self.model.mode()
+	}
+
+	// a bad header isn't an error, so frontends should pass on header().warnings() to the user
+	pub fn header(&self) -> &CartHeader {
+		&self.header
+	}
+
+	// header + global checksum; used to match save states to the ROM
+	fn rom_checksum(&self) -> [u8; 3] {
+		let global = self.header.global_checksum.to_be_bytes();
 
+		[self.header.header_checksum, global[0], global[1]]
 	}
 
 	pub fn tick(&mut self) -> bool {
 
+		let was_vblank = self.movie.is_some() && self.in_vblank();
+
+		// the CPU ticks the rest of the system as it goes
 		let instr_cycles = self.cpu.cycle();
+		self.cycles += instr_cycles;
+
+		if let Some(session) = &self.movie {
+			// a movie frame ends at VBlank, or after a frame's worth of cycles with the LCD off
+			if (!was_vblank && self.in_vblank()) || self.cycles - session.frame_start >= FRAME_CYCLES {
+				self.next_movie_frame();
+			}
+		}
+
+		if self.rewind.enabled() && self.cycles >= self.next_snapshot {
+			self.rewind.push(self.save_state());
+			self.next_snapshot = self.cycles + SNAPSHOT_INTERVAL;
+		}
+
+		self.cpu.bus.take_audio_buffer_full()
+	}
+
+	// runs until the PPU enters VBlank, or for a frame's worth of cycles if the LCD is off and there won't be one
+	pub fn run_frame(&mut self) -> RunResult {
+		let mut result = self.run(None, FRAME_CYCLES, true).0;
+
+		// with the LCD off there's no VBlank, but a frame's worth of time has still gone by
+		result.frame |= result.breakpoint.is_none();
+		result
+	}
+
+	// runs for at least `cycles` T-states. It stops on an instruction boundary, so it can go over by a few
+	pub fn run_cycles(&mut self, cycles: u64) -> RunResult {
+		self.run(None, cycles, false).0
+	}
 
-		self.bus.borrow_mut().timer.tick(instr_cycles);
-		let buffer_full = self.bus.borrow_mut().apu.tick(instr_cycles);
-		self.bus.borrow_mut().ppu.tick(instr_cycles);
+	// runs both linked gameboys until this one has a frame ready. Only this gameboy's breakpoints are checked
+	pub fn run_frame_linked(&mut self, other: &mut Gameboy) -> (RunResult, RunResult) {
+		let (mut result, other_result) = self.run(Some(other), FRAME_CYCLES, true);
 
-		buffer_full
+		result.frame |= result.breakpoint.is_none();
+		(result, other_result)
+	}
+
+	// runs for up to `cycles` T-states, stopping early at a breakpoint or, with `stop_on_frame`, when VBlank starts.
+	// `frame` is only set when there was a VBlank
+	pub(crate) fn run(&mut self, mut other: Option<&mut Gameboy>, cycles: u64, stop_on_frame: bool) -> (RunResult, RunResult) {
+
+		let (start, other_start) = (self.cycles, other.as_ref().map_or(0, |other| other.cycles));
+
+		self.cpu.bus.apu.take_samples();
+
+		if let Some(other) = other.as_deref_mut() {
+			other.cpu.bus.apu.take_samples();
+		}
+
+		let mut result = RunResult::default();
+		let mut other_result = RunResult::default();
+
+		let mut rumbling = self.rumble();
+		let mut other_rumbling = other.as_ref().is_some_and(|other| other.rumble());
+
+		while self.cycles - start < cycles {
+
+			let (last_cycles, was_vblank) = (self.cycles, self.in_vblank());
+
+			match other.as_deref_mut() {
+				Some(other) => {
+					let other_was_vblank = other.in_vblank();
+
+					self.tick_linked(other);
+
+					other_result.frame |= !other_was_vblank && other.in_vblank();
+					other_result.rumble |= other.rumble();
+
+					if other.rumble() != other_rumbling {
+						other_rumbling = other.rumble();
+						other_result.rumble_changes.push((other.cycles, other_rumbling));
+					}
+				},
+				None => { self.tick(); },
+			}
+
+			result.frame |= !was_vblank && self.in_vblank();
+			result.rumble |= self.rumble();
+
+			if self.rumble() != rumbling {
+				rumbling = self.rumble();
+				result.rumble_changes.push((self.cycles, rumbling));
+			}
+
+			// only once this gameboy has moved, so carrying on from a breakpoint doesn't stop straight away
+			if self.cycles != last_cycles && self.breakpoints.contains(&self.cpu.pc) {
+				result.breakpoint = Some(self.cpu.pc);
+				break;
+			}
+
+			if stop_on_frame && result.frame {
+				break;
+			}
+
+		}
+
+		result.cycles = self.cycles - start;
+		result.audio_samples = self.cpu.bus.apu.take_samples();
+
+		if let Some(other) = other {
+			other_result.cycles = other.cycles - other_start;
+			other_result.audio_samples = other.cpu.bus.apu.take_samples();
+		}
+
+		(result, other_result)
+
+	}
+
+	// run_frame() and run_cycles() stop when the CPU is about to run an instruction at one of these addresses
+	pub fn set_breakpoints(&mut self, breakpoints: Vec<u16>) {
+		self.breakpoints = breakpoints;
 	}
 
 	pub fn run_scanline(&mut self) {
 
-		let current_ly = self.bus.borrow().read_register(bus::MemRegister::LY);
+		let current_ly = self.cpu.bus.read_register(bus::MemRegister::LY);
 
-		while self.bus.borrow().read_register(bus::MemRegister::LY) != current_ly.wrapping_add(1) {
+		while self.cpu.bus.read_register(bus::MemRegister::LY) != current_ly.wrapping_add(1) {
 			self.tick();
 		}
 
 	}
 
-	pub fn load_bootrom(&mut self, bootrom: Vec<u8>, bootrom_enable: bool) {
-		self.bus.borrow_mut().bootrom = bootrom;
-		self.bus.borrow_mut().bootrom_loaded = bootrom_enable;
+	// the DMG bootrom is 256 bytes, and the CGB one is 2304 bytes with a gap for the cart header
+	pub fn load_bootrom(&mut self, bootrom: Vec<u8>, bootrom_enable: bool) -> Result<(), Error> {
+		let expected = match self.mode() {
+			Mode::Dmg => 0x100,
+			Mode::Cgb => 0x900,
+		};
+
+		if bootrom.len() != expected {
+			return Err(Error::InvalidBootrom { expected, actual: bootrom.len() });
+		}
+
+		self.cpu.bus.bootrom = bootrom;
+		self.cpu.bus.bootrom_loaded = bootrom_enable;
 
 		if bootrom_enable { self.cpu.pc = 0 }
+
+		Ok(())
+	}
+
+	// serialises the whole machine. The ROM and bootrom are not included.
+	pub fn save_state(&self) -> Vec<u8> {
+		let mut state = StateWriter::new();
+
+		state.write_bytes(&STATE_MAGIC);
+		state.write_u32(STATE_VERSION);
+		state.write_bytes(&self.rom_checksum());
+		state.write_u8(self.model as u8);
+
+		self.save_state_body(&mut state);
+
+		state.into_bytes()
+	}
+
+	fn save_state_body(&self, state: &mut StateWriter) {
+		state.write_u64(self.cycles);
+		self.cpu.save_state(state);
+		self.cpu.bus.save_state(state);
+	}
+
+	// restores a state created by save_state(). If the state is rejected the emulator is left untouched.
+	pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+		let mut state = StateReader::new(bytes);
+
+		let mut magic = [0; 4];
+		state.read_bytes(&mut magic).map_err(|_| StateError::InvalidMagic)?;
+
+		if magic != STATE_MAGIC {
+			return Err(StateError::InvalidMagic);
+		}
+
+		let version = state.read_u32()?;
+
+		if version != STATE_VERSION {
+			return Err(StateError::UnsupportedVersion(version));
+		}
+
+		let mut rom_checksum = [0; 3];
+		state.read_bytes(&mut rom_checksum)?;
+
+		if rom_checksum != self.rom_checksum() {
+			return Err(StateError::RomMismatch);
+		}
+
+		if state.read_u8()? != self.model as u8 {
+			return Err(StateError::InvalidValue("hardware model"));
+		}
+
+		let mut backup = StateWriter::new();
+		self.save_state_body(&mut backup);
+
+		if let Err(e) = self.load_state_body(&mut state) {
+			let backup = backup.into_bytes();
+			self.load_state_body(&mut StateReader::new(&backup)).expect("failed to restore backup state");
+
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	fn load_state_body(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.cycles = state.read_u64()?;
+		self.cpu.load_state(state)?;
+		self.cpu.bus.load_state(state)?;
+
+		if !state.is_empty() {
+			return Err(StateError::InvalidValue("trailing data"));
+		}
+
+		Ok(())
+	}
+
+	// switches between the scanline and pixel FIFO renderers. The scanline renderer is the default
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		self.cpu.bus.ppu.set_renderer(renderer);
+	}
+
+	// connects the serial port to a link cable backend. The port starts out disconnected.
+	pub fn set_link_port(&mut self, port: Box<dyn LinkPort>) {
+		self.cpu.bus.serial.set_port(port);
+	}
+
+	// plugs a virtual link cable between this gameboy and another one. Use tick_linked() to run them together.
+	pub fn link_with(&mut self, other: &mut Gameboy) {
+		let (port, other_port) = link::link_cable();
+
+		self.set_link_port(Box::new(port));
+		other.set_link_port(Box::new(other_port));
+	}
+
+	// ticks whichever of the two linked gameboys is behind, so their clocks never drift more than an instruction apart.
+	// Returns true when this gameboy's audio buffer is full.
+	pub fn tick_linked(&mut self, other: &mut Gameboy) -> bool {
+		if self.cycles <= other.cycles {
+			self.tick()
+		} else {
+			other.tick();
+			false
+		}
 	}
 
+	// while a movie is recording, buttons are only passed on when the next frame starts so playing it back presses them on
+	// the same cycle. While one is playing, the movie has the controls
 	pub fn btn_down(&mut self, btn: GBInput) {
-		self.bus.borrow_mut().joypad.btn_down(btn);
+		match &mut self.movie {
+			Some(session) if !session.playing => session.held |= input_mask(btn),
+			Some(_) => {},
+			None => self.cpu.bus.btn_down(btn),
+		}
 	}
 
 	pub fn btn_up(&mut self, btn: GBInput) {
-		self.bus.borrow_mut().joypad.btn_up(btn);
+		match &mut self.movie {
+			Some(session) if !session.playing => session.held &= !input_mask(btn),
+			Some(_) => {},
+			None => self.cpu.bus.btn_up(btn),
+		}
+	}
+
+	// starts recording the buttons held each frame. A movie from power-on can only be started before anything has run,
+	// otherwise it starts from a save state of where the emulator is now
+	pub fn record_movie(&mut self, from_power_on: bool) -> Result<(), MovieError> {
+		let start = if from_power_on {
+			if self.cycles != 0 {
+				return Err(MovieError::NotAtPowerOn);
+			}
+
+			let bus = &self.cpu.bus;
+
+			MovieStart::PowerOn { bootrom: bus.bootrom_loaded, renderer: bus.ppu.renderer() }
+		} else {
+			MovieStart::Snapshot(self.save_state())
+		};
+
+		let held = self.cpu.bus.joypad.held();
+
+		self.start_movie(Movie { rom_hash: self.rom_hash, model: self.model, start, frames: Vec::new() }, false, held);
+
+		Ok(())
+	}
+
+	// puts the emulator in the movie's starting state and plays it back. Input from the frontend is ignored until it ends
+	pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+		if movie.rom_hash != self.rom_hash {
+			return Err(MovieError::RomMismatch);
+		}
+
+		if movie.model != self.model {
+			return Err(MovieError::ModelMismatch { expected: movie.model, actual: self.model });
+		}
+
+		match &movie.start {
+			MovieStart::PowerOn { bootrom, renderer } => {
+				if self.cycles != 0 || self.cpu.bus.bootrom_loaded != *bootrom {
+					return Err(MovieError::NotAtPowerOn);
+				}
+
+				self.set_renderer(*renderer);
+			},
+			MovieStart::Snapshot(state) => self.load_state(state).map_err(MovieError::State)?,
+		}
+
+		self.start_movie(movie, true, 0);
+
+		Ok(())
+	}
+
+	// stops recording or playing, and returns the movie
+	pub fn stop_movie(&mut self) -> Option<Movie> {
+		self.movie.take().map(|session| session.movie)
+	}
+
+	pub fn movie_status(&self) -> Option<MovieStatus> {
+		self.movie.as_ref().map(MovieSession::status)
+	}
+
+	fn start_movie(&mut self, movie: Movie, playing: bool, held: u8) {
+		self.cpu.bus.joypad.take_polled();
+
+		self.movie = Some(MovieSession {
+			movie,
+			playing,
+
+			frame: 0,
+			frame_start: self.cycles,
+			lag_frames: 0,
+			lagged: false,
+
+			held,
+		});
+
+		self.start_movie_frame();
+	}
+
+	fn next_movie_frame(&mut self) {
+		let Some(session) = &mut self.movie else {
+			return;
+		};
+
+		session.lagged = !self.cpu.bus.joypad.take_polled();
+		session.lag_frames += session.lagged as usize;
+		session.frame += 1;
+		session.frame_start = self.cycles;
+
+		self.start_movie_frame();
+	}
+
+	// presses and releases buttons to match the frame that's starting. Playback ends once the movie runs out of frames
+	fn start_movie_frame(&mut self) {
+		let Some(session) = &mut self.movie else {
+			return;
+		};
+
+		let input = match (session.playing, session.movie.frames.get(session.frame)) {
+			(true, Some(input)) => *input,
+			(true, None) => {
+				self.movie = None;
+				return;
+			},
+			(false, _) => {
+				session.movie.frames.push(session.held);
+				session.held
+			},
+		};
+
+		let bus = &mut self.cpu.bus;
+		let changed = bus.joypad.held() ^ input;
+
+		for btn in GBInput::ALL.into_iter().filter(|btn| changed & input_mask(*btn) != 0) {
+			match input & input_mask(btn) != 0 {
+				true => bus.btn_down(btn),
+				false => bus.btn_up(btn),
+			}
+		}
+	}
+
+	fn in_vblank(&self) -> bool {
+		self.cpu.bus.ppu.rendering_mode == RenderingMode::VBlank
+	}
+
+	// keeps a snapshot every frame for the last `seconds` of gameplay, so it can be stepped back through with
+	// rewind_step(). 0 turns rewinding off. Changing the length throws away the snapshots taken so far
+	pub fn set_rewind_length(&mut self, seconds: usize) {
+		self.rewind = Rewind::new(seconds * SNAPSHOTS_PER_SECOND);
+		self.next_snapshot = self.cycles;
+	}
+
+	// goes back a frame. Holding a button that calls this once per frame rewinds at normal speed. Returns false once
+	// there's nothing left to rewind to
+	pub fn rewind_step(&mut self) -> bool {
+		let Some(snapshot) = self.rewind.pop() else {
+			return false;
+		};
+
+		if self.load_state(&snapshot).is_err() {
+			return false;
+		}
+
+		self.next_snapshot = self.cycles + SNAPSHOT_INTERVAL;
+
+		true
+	}
+
+	// how many frames can be rewound, and the memory they're taking up in bytes
+	pub fn rewind_usage(&self) -> (usize, usize) {
+		(self.rewind.len(), self.rewind.size())
+	}
+
+	// the SGB's output: the screen in colour, inside its border. None if the game doesn't use the SGB, in which case the
+	// PPU's frame is all there is
+	pub fn sgb_frame(&self) -> Option<[u16; SGB_WIDTH * SGB_HEIGHT]> {
+		self.cpu.bus.sgb.as_ref().map(Sgb::frame)
+	}
+
+	// whether the rumble motor on an MBC5 rumble cart is running. Games pulse it to vary the strength, so poll this
+	// often (e.g. after every tick) to catch every change
+	pub fn rumble(&self) -> bool {
+		self.cpu.bus.cart.rumble()
+	}
+
+	// tilts carts with an accelerometer (MBC7), in g. Other carts ignore it
+	pub fn set_accelerometer(&mut self, x: f32, y: f32) {
+		self.cpu.bus.cart.set_accelerometer(x, y);
+	}
+
+	// what the Pocket Camera sees: cart::CAMERA_WIDTH * cart::CAMERA_HEIGHT 8-bit greyscale pixels. Other carts ignore it
+	pub fn set_camera_image(&mut self, image: &[u8]) {
+		self.cpu.bus.cart.set_camera_image(image);
 	}
 
 }
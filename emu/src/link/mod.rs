@@ -0,0 +1,63 @@
+pub mod tcp;
+
+use crate::serial::LinkPort;
+
+use std::sync::{Arc, Mutex};
+
+// what one end of the cable has on the line
+#[derive(Default)]
+struct CableEnd {
+	listening: Option<u8>,	// SB of a gameboy waiting on an external clock
+	received: Option<u8>,	// byte clocked in by the other side, waiting to be picked up
+}
+
+// one side of a virtual link cable between two gameboys in the same process. Create a pair with link_cable().
+pub struct CablePort {
+	ends: Arc<Mutex<[CableEnd; 2]>>,
+	side: usize,
+}
+
+impl LinkPort for CablePort {
+
+	fn transfer(&mut self, out: u8) -> u8 {
+		let mut ends = self.ends.lock().unwrap();
+
+		// this side is driving the clock now, so it's no longer waiting on the other one
+		ends[self.side].listening = None;
+
+		let other = &mut ends[1 - self.side];
+
+		// the other side only shifts if it has a transfer waiting, otherwise the line stays high
+		match other.listening.take() {
+			Some(incoming) => {
+				other.received = Some(out);
+				incoming
+			},
+			None => 0xFF,
+		}
+	}
+
+	fn poll_external(&mut self, out: u8) -> Option<u8> {
+		let mut ends = self.ends.lock().unwrap();
+		let end = &mut ends[self.side];
+
+		match end.received.take() {
+			Some(incoming) => Some(incoming),
+			None => {
+				end.listening = Some(out);
+				None
+			}
+		}
+	}
+
+}
+
+// creates both ends of a link cable. Bytes sent through one end arrive at the other.
+pub fn link_cable() -> (CablePort, CablePort) {
+	let ends = Arc::new(Mutex::new([CableEnd::default(), CableEnd::default()]));
+
+	(
+		CablePort { ends: Arc::clone(&ends), side: 0 },
+		CablePort { ends, side: 1 },
+	)
+}
\ No newline at end of file
@@ -0,0 +1,343 @@
+use crate::serial::LinkPort;
+use crate::{Gameboy, RunResult, FRAME_CYCLES};
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/*
+	Both gameboys run in fixed windows of emulated time. At the end of every window each side sends what happened on
+	its end of the cable, and the peer applies it `delay` windows later. Neither side can start a window until it has
+	the peer's message from `delay` windows ago, so the cores can never be more than `delay` windows apart, and since
+	messages are applied at fixed points in emulated time, network latency never changes what either gameboy sees.
+*/
+
+const LINK_MAGIC: [u8; 4] = *b"GBLK";
+const LINK_VERSION: u8 = 1;
+
+// about 1ms of emulated time
+pub const DEFAULT_WINDOW: u64 = 4096;
+// the number of windows a message can be in flight before the receiving side has to wait for it
+pub const DEFAULT_DELAY: u32 = 8;
+
+// a peer that stops sending messages for this long is treated as disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MSG_WINDOW: u8 = 0;
+const MSG_BYE: u8 = 1;
+
+#[derive(Default)]
+struct LinkState {
+	// this side
+	listening: Option<u8>,	// SB while waiting on an external clock this window
+	sent: Vec<u8>,			// bytes clocked into a listening peer this window
+	sent_total: u32,
+	received_total: u32,
+
+	// the other side, as of the last message applied
+	peer_listening: Option<u8>,
+	peer_received_total: u32,
+	inbox: VecDeque<u8>,
+
+	closed: bool,
+}
+
+// the serial port's view of a TCP link. Installed on the gameboy by TcpLink::attach().
+pub struct TcpPort {
+	state: Arc<Mutex<LinkState>>,
+}
+
+impl LinkPort for TcpPort {
+
+	fn transfer(&mut self, out: u8) -> u8 {
+		let mut state = self.state.lock().unwrap();
+
+		// a listening peer only counts once it has picked up everything sent to it, otherwise it's a stale message
+		if state.peer_received_total != state.sent_total {
+			return 0xFF;
+		}
+
+		match state.peer_listening.take() {
+			Some(incoming) => {
+				state.sent.push(out);
+				state.sent_total = state.sent_total.wrapping_add(1);
+
+				incoming
+			},
+			None => 0xFF,
+		}
+	}
+
+	fn poll_external(&mut self, out: u8) -> Option<u8> {
+		let mut state = self.state.lock().unwrap();
+
+		match state.inbox.pop_front() {
+			Some(incoming) => {
+				state.received_total = state.received_total.wrapping_add(1);
+				state.listening = None;
+
+				Some(incoming)
+			},
+			None => {
+				state.listening = Some(out);
+				None
+			}
+		}
+	}
+
+}
+
+// a link cable to a gameboy in another process, usually on another machine
+pub struct TcpLink {
+	stream: TcpStream,
+	state: Arc<Mutex<LinkState>>,
+
+	window: u64,
+	delay: u32,
+
+	window_index: u32,
+	next_sync: u64,
+}
+
+impl TcpLink {
+
+	// waits for a peer to connect. The host decides the window length and delay used by both sides.
+	pub fn host(listener: &TcpListener, window: u64, delay: u32) -> io::Result<Self> {
+
+		if window == 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "link window must be at least 1 cycle"));
+		}
+
+		let (mut stream, _) = listener.accept()?;
+
+		let mut hello = Vec::new();
+		hello.extend_from_slice(&LINK_MAGIC);
+		hello.push(LINK_VERSION);
+		hello.extend_from_slice(&window.to_le_bytes());
+		hello.extend_from_slice(&delay.to_le_bytes());
+
+		stream.write_all(&hello)?;
+
+		let mut reply = [0; 5];
+		stream.read_exact(&mut reply)?;
+		check_hello(&reply)?;
+
+		Self::new(stream, window, delay)
+
+	}
+
+	pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+
+		let mut stream = TcpStream::connect(addr)?;
+
+		let mut hello = [0; 17];
+		stream.read_exact(&mut hello)?;
+		check_hello(&hello[0..5])?;
+
+		let window = u64::from_le_bytes(hello[5..13].try_into().unwrap());
+		let delay = u32::from_le_bytes(hello[13..17].try_into().unwrap());
+
+		if window == 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "host sent an invalid link window"));
+		}
+
+		let mut reply = Vec::new();
+		reply.extend_from_slice(&LINK_MAGIC);
+		reply.push(LINK_VERSION);
+
+		stream.write_all(&reply)?;
+
+		Self::new(stream, window, delay)
+
+	}
+
+	fn new(stream: TcpStream, window: u64, delay: u32) -> io::Result<Self> {
+
+		// messages are tiny and every one of them is waited on
+		stream.set_nodelay(true)?;
+		stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+		Ok(Self {
+			stream,
+			state: Arc::new(Mutex::new(LinkState::default())),
+
+			window,
+			delay,
+
+			window_index: 0,
+			next_sync: 0,
+		})
+
+	}
+
+	// plugs the link into a gameboy's serial port. The first window starts at the gameboy's current cycle.
+	pub fn attach(&mut self, gb: &mut Gameboy) {
+		gb.set_link_port(Box::new(TcpPort { state: Arc::clone(&self.state) }));
+
+		self.next_sync = gb.cycles + self.window;
+	}
+
+	// ticks the gameboy, waiting for the peer whenever a window ends. Returns true when the audio buffer is full.
+	pub fn tick(&mut self, gb: &mut Gameboy) -> io::Result<bool> {
+
+		let buffer_full = gb.tick();
+
+		while gb.cycles >= self.next_sync {
+			self.sync()?;
+			self.next_sync += self.window;
+		}
+
+		Ok(buffer_full)
+
+	}
+
+	// Gameboy::run_frame(), stopping to wait for the peer whenever a window ends
+	pub fn run_frame(&mut self, gb: &mut Gameboy) -> io::Result<RunResult> {
+
+		let mut result = self.run(gb, FRAME_CYCLES, true)?;
+
+		result.frame |= result.breakpoint.is_none();
+		Ok(result)
+
+	}
+
+	// Gameboy::run_cycles(), stopping to wait for the peer whenever a window ends
+	pub fn run_cycles(&mut self, gb: &mut Gameboy, cycles: u64) -> io::Result<RunResult> {
+		self.run(gb, cycles, false)
+	}
+
+	// runs a window at a time, so the gameboy never gets further than a window past the last sync
+	fn run(&mut self, gb: &mut Gameboy, cycles: u64, stop_on_frame: bool) -> io::Result<RunResult> {
+
+		let mut result = RunResult::default();
+
+		while result.cycles < cycles && !(stop_on_frame && result.frame) && result.breakpoint.is_none() {
+
+			let window = gb.run(None, (cycles - result.cycles).min(self.next_sync.saturating_sub(gb.cycles)), stop_on_frame).0;
+
+			result.frame |= window.frame;
+			result.cycles += window.cycles;
+			result.audio_samples += window.audio_samples;
+			result.rumble |= window.rumble;
+			result.rumble_changes.extend(window.rumble_changes);
+			result.breakpoint = window.breakpoint;
+
+			while gb.cycles >= self.next_sync {
+				self.sync()?;
+				self.next_sync += self.window;
+			}
+
+		}
+
+		Ok(result)
+
+	}
+
+	// true once the peer has hung up. The link then behaves like an unplugged cable.
+	pub fn is_closed(&self) -> bool {
+		self.state.lock().unwrap().closed
+	}
+
+	fn sync(&mut self) -> io::Result<()> {
+
+		if self.is_closed() {
+			return Ok(());
+		}
+
+		let mut msg = Vec::new();
+
+		{
+			let mut state = self.state.lock().unwrap();
+
+			let listening = state.listening.take();
+			let sent = std::mem::take(&mut state.sent);
+
+			msg.push(MSG_WINDOW);
+			msg.extend_from_slice(&self.window_index.to_le_bytes());
+			msg.push(listening.is_some() as u8);
+			msg.push(listening.unwrap_or(0xFF));
+			msg.extend_from_slice(&state.received_total.to_le_bytes());
+			msg.extend_from_slice(&(sent.len() as u16).to_le_bytes());
+			msg.extend_from_slice(&sent);
+		}
+
+		// a failed write means the peer has gone, which the read below reports (or it sent a goodbye first)
+		let _ = self.stream.write_all(&msg);
+
+		// the first `delay` windows run without waiting, which is what hides the latency
+		if self.window_index >= self.delay {
+			self.receive(self.window_index - self.delay)?;
+		}
+
+		self.window_index = self.window_index.wrapping_add(1);
+
+		Ok(())
+
+	}
+
+	fn receive(&mut self, expected_window: u32) -> io::Result<()> {
+
+		let mut kind = [0];
+		self.stream.read_exact(&mut kind)?;
+
+		match kind[0] {
+			MSG_BYE => {
+				let mut state = self.state.lock().unwrap();
+
+				state.closed = true;
+				state.peer_listening = None;
+
+				return Ok(());
+			},
+			MSG_WINDOW => {},
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown link message")),
+		}
+
+		let mut header = [0; 12];
+		self.stream.read_exact(&mut header)?;
+
+		let window = u32::from_le_bytes(header[0..4].try_into().unwrap());
+
+		if window != expected_window {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!("link out of sync (expected window {}, got {})", expected_window, window)));
+		}
+
+		let mut sent = vec![0; u16::from_le_bytes(header[10..12].try_into().unwrap()) as usize];
+		self.stream.read_exact(&mut sent)?;
+
+		let mut state = self.state.lock().unwrap();
+
+		state.peer_listening = if header[4] != 0 { Some(header[5]) } else { None };
+		state.peer_received_total = u32::from_le_bytes(header[6..10].try_into().unwrap());
+		state.inbox.extend(sent);
+
+		Ok(())
+
+	}
+
+}
+
+impl Drop for TcpLink {
+	// lets the peer finish its run instead of treating this side's exit as a dropped connection
+	fn drop(&mut self) {
+		if !self.is_closed() {
+			let _ = self.stream.write_all(&[MSG_BYE]);
+		}
+	}
+}
+
+fn check_hello(hello: &[u8]) -> io::Result<()> {
+
+	if hello[0..4] != LINK_MAGIC {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "peer isn't a gb-emu link"));
+	}
+
+	if hello[4] != LINK_VERSION {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("peer uses link protocol version {} (expected {})", hello[4], LINK_VERSION)));
+	}
+
+	Ok(())
+
+}
\ No newline at end of file
@@ -0,0 +1,193 @@
+use crate::Model;
+use crate::joypad::GBInput;
+use crate::ppu::Renderer;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// magic bytes at the start of every movie file
+pub const MOVIE_MAGIC: [u8; 4] = *b"GBMV";
+
+// bumped whenever the layout of a movie file changes
+pub const MOVIE_VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum MovieError {
+	InvalidMagic,
+	UnsupportedVersion(u32),
+	Truncated,
+	InvalidValue(&'static str),
+	RomMismatch,
+	ModelMismatch { expected: Model, actual: Model },
+	NotAtPowerOn,		// the movie starts from power-on, but the emulator has already run or has a different bootrom setting
+	State(StateError),	// the embedded snapshot couldn't be loaded
+}
+
+impl std::fmt::Display for MovieError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidMagic => write!(f, "not a movie file"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported movie version {} (expected {})", version, MOVIE_VERSION),
+			Self::Truncated => write!(f, "movie file is truncated"),
+			Self::InvalidValue(what) => write!(f, "movie file contains an invalid {}", what),
+			Self::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+			Self::ModelMismatch { expected, actual } => write!(f, "movie was recorded on a {} but this is a {}", expected.name(), actual.name()),
+			Self::NotAtPowerOn => write!(f, "movie starts from power-on, so it has to be played on a freshly started emulator with the same bootrom setting"),
+			Self::State(e) => write!(f, "movie's starting state couldn't be loaded: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for MovieError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::State(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<StateError> for MovieError {
+	fn from(e: StateError) -> Self {
+		match e {
+			StateError::UnexpectedEof => Self::Truncated,
+			e => Self::State(e),
+		}
+	}
+}
+
+// where a movie starts playing from
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovieStart {
+	PowerOn { bootrom: bool, renderer: Renderer },
+	Snapshot(Vec<u8>),	// a save state
+}
+
+// the buttons held during each frame of a run, and everything needed to play them back into the same emulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+	pub rom_hash: u64,
+	pub model: Model,
+	pub start: MovieStart,
+	pub frames: Vec<u8>,	// one bit per button, in the order of GBInput::ALL
+}
+
+impl Movie {
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut movie = StateWriter::new();
+
+		movie.write_bytes(&MOVIE_MAGIC);
+		movie.write_u32(MOVIE_VERSION);
+		movie.write_u64(self.rom_hash);
+		movie.write_u8(self.model as u8);
+
+		match &self.start {
+			MovieStart::PowerOn { bootrom, renderer } => {
+				movie.write_u8(0);
+				movie.write_bool(*bootrom);
+				movie.write_u8(*renderer as u8);
+			},
+			MovieStart::Snapshot(state) => {
+				movie.write_u8(1);
+				movie.write_vec(state);
+			},
+		}
+
+		movie.write_vec(&self.frames);
+
+		movie.into_bytes()
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+		let mut movie = StateReader::new(bytes);
+
+		let mut magic = [0; 4];
+		movie.read_bytes(&mut magic).map_err(|_| MovieError::InvalidMagic)?;
+
+		if magic != MOVIE_MAGIC {
+			return Err(MovieError::InvalidMagic);
+		}
+
+		let version = movie.read_u32()?;
+
+		if version != MOVIE_VERSION {
+			return Err(MovieError::UnsupportedVersion(version));
+		}
+
+		let rom_hash = movie.read_u64()?;
+
+		let model = movie.read_u8()?;
+		let model = Model::ALL.into_iter().find(|m| *m as u8 == model).ok_or(MovieError::InvalidValue("hardware model"))?;
+
+		let start = match movie.read_u8()? {
+			0 => MovieStart::PowerOn {
+				bootrom: movie.read_bool()?,
+				renderer: match movie.read_u8()? {
+					0 => Renderer::Scanline,
+					1 => Renderer::Fifo,
+					_ => return Err(MovieError::InvalidValue("renderer")),
+				},
+			},
+			1 => MovieStart::Snapshot(movie.read_vec()?),
+			_ => return Err(MovieError::InvalidValue("starting state")),
+		};
+
+		let frames = movie.read_vec()?;
+
+		if !movie.is_empty() {
+			return Err(MovieError::InvalidValue("trailing data"));
+		}
+
+		Ok(Movie {
+			rom_hash,
+			model,
+			start,
+			frames,
+		})
+	}
+
+}
+
+// 64-bit FNV-1a. Only used to tell ROMs apart, so it doesn't need to be cryptographic
+pub fn rom_hash(rom: &[u8]) -> u64 {
+	rom.iter().fold(0xCBF29CE484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001B3))
+}
+
+pub fn input_mask(btn: GBInput) -> u8 {
+	1 << GBInput::ALL.iter().position(|b| *b == btn).unwrap()
+}
+
+// where a recording or playback is up to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovieStatus {
+	pub playing: bool,		// false while recording
+	pub frame: usize,		// frames run since the movie started
+	pub length: usize,		// frames in the movie. While recording, this includes the one in progress
+	pub lag_frames: usize,	// frames where the game never read the joypad, so any input during them was missed
+	pub lagged: bool,		// whether the last frame was a lag frame
+}
+
+pub(crate) struct MovieSession {
+	pub movie: Movie,
+	pub playing: bool,
+
+	pub frame: usize,
+	pub frame_start: u64,	// in T-states
+	pub lag_frames: usize,
+	pub lagged: bool,
+
+	pub held: u8,	// while recording, the buttons the frontend is holding. They're only passed on when the next frame starts
+}
+
+impl MovieSession {
+
+	pub fn status(&self) -> MovieStatus {
+		MovieStatus {
+			playing: self.playing,
+			frame: self.frame,
+			length: self.movie.frames.len(),
+			lag_frames: self.lag_frames,
+			lagged: self.lagged,
+		}
+	}
+
+}
\ No newline at end of file
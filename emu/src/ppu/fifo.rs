@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+
+use super::{GBColour, Sprite, PPU};
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+/*
+	The pixel FIFO renderer. A fetcher reads tiles 8 pixels at a time into the background FIFO, and one pixel is
+	shifted out to the LCD each dot. Objects are fetched into a second FIFO when the LCD reaches them, which stalls
+	the background, so mode 3 gets longer with scrolling, the window and objects just like it does on hardware.
+	Registers are read when the hardware reads them, so mid-line writes take effect at the right pixel.
+*/
+
+// dots spent on the first (discarded) tile fetch of every line
+const STARTUP_DOTS: u8 = 6;
+// dots the object fetch itself takes, on top of waiting for the background fetcher
+const OBJ_FETCH_DOTS: u8 = 6;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+enum FetchStep {
+	#[default]
+	Tile = 0,
+	DataLow = 1,
+	DataHigh = 2,
+	Push = 3,
+}
+
+impl FetchStep {
+	fn from_u8(from: u8) -> Option<Self> {
+		match from {
+			0 => Some(Self::Tile),
+			1 => Some(Self::DataLow),
+			2 => Some(Self::DataHigh),
+			3 => Some(Self::Push),
+			_ => None
+		}
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+struct BgPixel {
+	colour: u8,
+	palette: u8,		// CGB palette 0-7
+	priority: bool,		// CGB tile attribute bit 7
+}
+
+#[derive(Clone, Copy, Default)]
+struct ObjPixel {
+	colour: u8,
+	palette: bool,		// OBP0/OBP1
+	cgb_palette: u8,
+	priority: bool,		// drawn behind background colours 1-3
+	oam_index: u8,		// the CGB picks between overlapping objects by OAM index
+}
+
+#[derive(Default)]
+pub(super) struct Fifo {
+	pub active: bool,		// mode 3 on this line is being run by the FIFO renderer
+	pub wy_triggered: bool,	// LY has matched WY this frame, so the window can start
+
+	bg: VecDeque<BgPixel>,
+	obj: VecDeque<ObjPixel>,
+
+	startup: u8,			// dots left of the startup fetch
+	step: FetchStep,
+	step_dots: u8,
+	fetch_x: u8,			// tile column being fetched, relative to the start of the background or window
+	tile_id: u8,
+	attributes: u8,
+	data_low: u8,
+	data_high: u8,
+
+	lcd_x: u8,				// next pixel on the LCD
+	discard: u8,			// pixels still to be thrown away for fine scrolling
+	in_window: bool,
+
+	sprites: Vec<u8>,		// OAM indices of the objects on this line that haven't been fetched yet
+	sprite_fetch: Option<u8>,
+	sprite_dots: u8,
+}
+
+impl PPU {
+
+	pub(super) fn start_fifo_line(&mut self) {
+
+		let size_y = if self.reg_lcdc.obj_size { 16 } else { 8 };
+		let ly = self.reg_ly as i16;
+
+		// OAM scan: the first 10 objects on this line
+		let sprites: Vec<u8> = (0..40_u8)
+			.filter(|index| {
+				let pos_y = self.oam[*index as usize * 4] as i16 - 16;
+				ly >= pos_y && ly < pos_y + size_y
+			})
+			.take(10)
+			.collect();
+
+		if self.reg_ly == self.reg_wy {
+			self.fifo.wy_triggered = true;
+		}
+
+		self.fifo = Fifo {
+			active: true,
+			wy_triggered: self.fifo.wy_triggered,
+
+			startup: STARTUP_DOTS,
+			discard: self.reg_scx % 8,
+
+			sprites,
+
+			..Fifo::default()
+		};
+
+	}
+
+	// runs one dot of mode 3. Returns true once the line has been drawn
+	pub(super) fn fifo_dot(&mut self) -> bool {
+
+		if self.fifo.startup > 0 {
+			self.fifo.startup -= 1;
+			return false;
+		}
+
+		if self.fifo.sprite_fetch.is_none() && self.reg_lcdc.obj_enable {
+			self.fifo.sprite_fetch = self.due_sprite();
+		}
+
+		// an object fetch stalls the LCD. The background fetcher gets to finish reading the tile it's on first,
+		// which adds up to 5 dots depending on where the object is relative to the background tiles
+		if let Some(index) = self.fifo.sprite_fetch {
+			let fetcher_ready = matches!(self.fifo.step, FetchStep::DataHigh | FetchStep::Push);
+
+			if !fetcher_ready || self.fifo.bg.is_empty() {
+				self.fetcher_dot();
+			} else {
+				self.fifo.sprite_dots += 1;
+
+				if self.fifo.sprite_dots == OBJ_FETCH_DOTS {
+					self.fetch_sprite(index);
+
+					self.fifo.sprite_fetch = None;
+					self.fifo.sprite_dots = 0;
+				}
+			}
+
+			return false;
+		}
+
+		self.check_window();
+		self.fetcher_dot();
+
+		let Some(bg) = self.fifo.bg.pop_front() else {
+			return false;
+		};
+
+		if self.fifo.discard > 0 {
+			self.fifo.discard -= 1;
+			return false;
+		}
+
+		let obj = self.fifo.obj.pop_front();
+
+		self.draw_buf[self.fifo.lcd_x as usize + 160 * self.reg_ly as usize] = self.mix_pixel(bg, obj);
+		self.fifo.lcd_x += 1;
+
+		if self.fifo.lcd_x < 160 {
+			return false;
+		}
+
+		if self.fifo.in_window {
+			self.win_ly = self.win_ly.wrapping_add(1);
+		}
+
+		true
+
+	}
+
+	// the next object to fetch, if the LCD has reached one
+	fn due_sprite(&mut self) -> Option<u8> {
+
+		let lcd_x = self.fifo.lcd_x as i16;
+
+		// objects hidden off the left edge are never fetched. Between objects at the same position the lowest OAM index goes first
+		let (position, index) = self.fifo.sprites.iter()
+			.copied()
+			.enumerate()
+			.filter(|(_, index)| {
+				let pos_x = self.oam[*index as usize * 4 + 1] as i16 - 8;
+				pos_x > -8 && pos_x <= lcd_x
+			})
+			.min_by_key(|(_, index)| (self.oam[*index as usize * 4 + 1], *index))?;
+
+		self.fifo.sprites.remove(position);
+
+		Some(index)
+
+	}
+
+	fn check_window(&mut self) {
+
+		if self.fifo.in_window || !self.fifo.wy_triggered || !self.reg_lcdc.window_enable {
+			return;
+		}
+
+		// the window starts at WX - 7. With WX below 7 the pixels left of the screen are thrown away instead
+		if self.fifo.lcd_x as u16 + 7 < self.reg_wx as u16 {
+			return;
+		}
+
+		self.fifo.in_window = true;
+		self.fifo.discard = 7_u8.saturating_sub(self.reg_wx);
+
+		self.fifo.bg.clear();
+		self.fifo.step = FetchStep::Tile;
+		self.fifo.step_dots = 0;
+		self.fifo.fetch_x = 0;
+
+	}
+
+	fn fetcher_dot(&mut self) {
+
+		self.fifo.step_dots += 1;
+
+		// every step but pushing takes 2 dots
+		match self.fifo.step {
+			FetchStep::Tile if self.fifo.step_dots == 2 => {
+				let (tilemap_area, x, y) = match self.fifo.in_window {
+					true => (self.reg_lcdc.window_tilemap_area, self.fifo.fetch_x, self.win_ly),
+					false => (self.reg_lcdc.bg_tilemap_area, (self.reg_scx / 8).wrapping_add(self.fifo.fetch_x), self.reg_ly.wrapping_add(self.reg_scy)),
+				};
+
+				let tilemap_addr = if tilemap_area { 0x9C00 } else { 0x9800 } + (y as u16 / 8) * 32 + (x & 0x1F) as u16;
+
+				self.fifo.tile_id = self.read_vram(0, tilemap_addr);
+				self.fifo.attributes = if self.cgb { self.read_vram(1, tilemap_addr) } else { 0 };
+
+				self.next_fetch_step(FetchStep::DataLow);
+			},
+			FetchStep::DataLow if self.fifo.step_dots == 2 => {
+				self.fifo.data_low = self.read_vram((self.fifo.attributes >> 3) & 1, self.fetcher_data_addr());
+
+				self.next_fetch_step(FetchStep::DataHigh);
+			},
+			FetchStep::DataHigh if self.fifo.step_dots == 2 => {
+				self.fifo.data_high = self.read_vram((self.fifo.attributes >> 3) & 1, self.fetcher_data_addr() + 1);
+
+				self.next_fetch_step(FetchStep::Push);
+			},
+			// the tile waits until the FIFO has room for all 8 pixels
+			FetchStep::Push if self.fifo.bg.is_empty() => {
+				for x in 0..8 {
+					// x flip
+					let bit = if self.fifo.attributes & 0x20 != 0 { x } else { 7 - x };
+
+					self.fifo.bg.push_back(BgPixel {
+						colour: (self.fifo.data_low >> bit & 1) | (self.fifo.data_high >> bit & 1) << 1,
+						palette: self.fifo.attributes & 0x7,
+						priority: self.fifo.attributes & 0x80 != 0,
+					});
+				}
+
+				self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+				self.next_fetch_step(FetchStep::Tile);
+			},
+			_ => {}
+		}
+
+	}
+
+	fn next_fetch_step(&mut self, step: FetchStep) {
+		self.fifo.step = step;
+		self.fifo.step_dots = 0;
+	}
+
+	// address of the low byte of the row being fetched
+	fn fetcher_data_addr(&self) -> u16 {
+
+		let y = match self.fifo.in_window {
+			true => self.win_ly,
+			false => self.reg_ly.wrapping_add(self.reg_scy),
+		};
+
+		let row = match self.fifo.attributes & 0x40 != 0 {
+			false => y % 8,
+			true => 7 - (y % 8),	// y flip
+		};
+
+		let tile_base_addr = match self.reg_lcdc.tile_data_area {
+			true => 0x8000 + self.fifo.tile_id as u16 * 16,
+			false => 0x9000_u16.wrapping_add(((self.fifo.tile_id as i8) as u16).wrapping_mul(16)),	// signed addressing
+		};
+
+		tile_base_addr + row as u16 * 2
+
+	}
+
+	fn fetch_sprite(&mut self, index: u8) {
+
+		let sprite = Sprite::from_oam(index as u16 * 4, &self.oam);
+
+		let size_y = if self.reg_lcdc.obj_size { 16 } else { 8 };
+
+		let sprite_line = match sprite.y_flip {
+			false => (self.reg_ly as i16 - sprite.pos_y) as u16,
+			true => (size_y - 1 - (self.reg_ly as i16 - sprite.pos_y)) as u16,
+		};
+
+		// 8x16 sprites ignore the LSB of the tile id
+		let tile_mask = if self.reg_lcdc.obj_size { 0xFE } else { 0xFF };
+
+		let tile_data_addr = 0x8000 + (sprite.tile_id & tile_mask) as u16 * 16 + sprite_line * 2;
+		let tile_bank = (self.cgb && sprite.cgb_bank) as u8;
+
+		let data_1 = self.read_vram(tile_bank, tile_data_addr);
+		let data_2 = self.read_vram(tile_bank, tile_data_addr + 1);
+
+		// pixels of an object hanging off the left edge are already past
+		let skip = (self.fifo.lcd_x as i16 - sprite.pos_x).clamp(0, 8) as usize;
+
+		for x in skip..8 {
+
+			let bit = if sprite.x_flip { x } else { 7 - x };
+
+			let pixel = ObjPixel {
+				colour: (data_1 >> bit & 1) | (data_2 >> bit & 1) << 1,
+				palette: sprite.palette,
+				cgb_palette: sprite.cgb_palette,
+				priority: sprite.priority,
+				oam_index: index,
+			};
+
+			// objects fetched earlier win on the DMG. On the CGB the lowest OAM index wins
+			match self.fifo.obj.get_mut(x - skip) {
+				Some(existing) => {
+					if pixel.colour != 0 && (existing.colour == 0 || (self.cgb && pixel.oam_index < existing.oam_index)) {
+						*existing = pixel;
+					}
+				},
+				None => self.fifo.obj.push_back(pixel),
+			}
+
+		}
+
+	}
+
+	fn mix_pixel(&self, bg: BgPixel, obj: Option<ObjPixel>) -> u16 {
+
+		// on the CGB, LCDC bit 0 doesn't turn the background off, it just puts objects above it
+		let bg_enabled = self.reg_lcdc.bg_enable || self.cgb;
+		let bg_index = if bg_enabled { bg.colour } else { 0 };
+
+		if let Some(obj) = obj {
+			let bg_over_obj = (obj.priority || bg.priority) && bg_index != 0 && self.reg_lcdc.bg_enable;
+
+			if obj.colour != 0 && self.reg_lcdc.obj_enable && !bg_over_obj {
+				return self.obj_colour(obj.palette, obj.cgb_palette, obj.colour);
+			}
+		}
+
+		match bg_enabled {
+			true => self.bg_colour(bg.palette, bg.colour),
+			false => GBColour::White.to_rgb555(),
+		}
+
+	}
+
+}
+
+impl Fifo {
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.active);
+		state.write_bool(self.wy_triggered);
+
+		state.write_u8(self.bg.len() as u8);
+		for pixel in self.bg.iter() {
+			state.write_bytes(&[pixel.colour, pixel.palette, pixel.priority as u8]);
+		}
+
+		state.write_u8(self.obj.len() as u8);
+		for pixel in self.obj.iter() {
+			state.write_bytes(&[pixel.colour, pixel.palette as u8, pixel.cgb_palette, pixel.priority as u8, pixel.oam_index]);
+		}
+
+		state.write_bytes(&[self.startup, self.step as u8, self.step_dots, self.fetch_x, self.tile_id, self.attributes, self.data_low, self.data_high]);
+		state.write_bytes(&[self.lcd_x, self.discard, self.in_window as u8]);
+
+		state.write_u8(self.sprites.len() as u8);
+		state.write_bytes(&self.sprites);
+		state.write_u8(self.sprite_fetch.map_or(0xFF, |index| index));
+		state.write_u8(self.sprite_dots);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.active = state.read_bool()?;
+		self.wy_triggered = state.read_bool()?;
+
+		let bg_len = state.read_u8()?;
+		if bg_len > 16 {
+			return Err(StateError::InvalidValue("background FIFO length"));
+		}
+
+		self.bg.clear();
+		for _ in 0..bg_len {
+			let mut pixel = [0; 3];
+			state.read_bytes(&mut pixel)?;
+
+			self.bg.push_back(BgPixel { colour: pixel[0] & 3, palette: pixel[1] & 7, priority: pixel[2] != 0 });
+		}
+
+		let obj_len = state.read_u8()?;
+		if obj_len > 8 {
+			return Err(StateError::InvalidValue("object FIFO length"));
+		}
+
+		self.obj.clear();
+		for _ in 0..obj_len {
+			let mut pixel = [0; 5];
+			state.read_bytes(&mut pixel)?;
+
+			self.obj.push_back(ObjPixel { colour: pixel[0] & 3, palette: pixel[1] != 0, cgb_palette: pixel[2] & 7, priority: pixel[3] != 0, oam_index: pixel[4] });
+		}
+
+		let mut fetcher = [0; 8];
+		state.read_bytes(&mut fetcher)?;
+
+		self.step = FetchStep::from_u8(fetcher[1]).ok_or(StateError::InvalidValue("FIFO fetcher step"))?;
+		[self.startup, _, self.step_dots, self.fetch_x, self.tile_id, self.attributes, self.data_low, self.data_high] = fetcher;
+
+		let mut lcd = [0; 3];
+		state.read_bytes(&mut lcd)?;
+
+		[self.lcd_x, self.discard, _] = lcd;
+		self.in_window = lcd[2] != 0;
+
+		let sprite_count = state.read_u8()?;
+		if sprite_count > 10 {
+			return Err(StateError::InvalidValue("FIFO object count"));
+		}
+
+		self.sprites = vec![0; sprite_count as usize];
+		state.read_bytes(&mut self.sprites)?;
+
+		self.sprite_fetch = match state.read_u8()? {
+			0xFF => None,
+			index => Some(index),
+		};
+		self.sprite_dots = state.read_u8()?;
+
+		if self.lcd_x > 160 || self.sprites.iter().chain(self.sprite_fetch.iter()).any(|index| *index >= 40) {
+			return Err(StateError::InvalidValue("FIFO state"));
+		}
+
+		Ok(())
+	}
+
+}
@@ -0,0 +1,944 @@
+mod fifo;
+
+use super::interrupt::*;
+use super::savestate::{StateReader, StateWriter, StateError};
+use super::Model;
+
+use fifo::Fifo;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderingMode {
+	HBlank 	= 0,    // Mode 0
+	VBlank 	= 1,    // Mode 1
+	OAMscan = 2,    // Mode 2
+	Draw 	= 3,	// Mode 3   
+}
+
+impl RenderingMode {
+	fn from_u8(from: u8) -> Option<Self> {
+		match from {
+			0 => Some(Self::HBlank),
+			1 => Some(Self::VBlank),
+			2 => Some(Self::OAMscan),
+			3 => Some(Self::Draw),
+			_ => None
+		}
+	}
+}
+
+// how mode 3 is emulated
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Renderer {
+	Scanline,	// draws the whole line when mode 3 ends, which always takes 172 dots
+	Fifo,		// pushes pixels through a FIFO one dot at a time, so mode 3 varies in length and mid-line register writes land on the right pixel
+}
+
+#[allow(dead_code)]
+enum StatFlag {
+	PPUMode		= 0x03,
+	LYCcmp		= 0x04,
+	Mode0Int	= 0x08,
+	Mode1Int	= 0x10,
+	Mode2Int	= 0x20,
+	LYCInt		= 0x40,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Sprite {
+	pos_y: i16,
+	pos_x: i16,
+	tile_id: u8,
+
+	priority: bool,
+	y_flip: bool,
+	x_flip: bool,
+	palette: bool,
+
+	cgb_bank: bool,		// CGB only: which VRAM bank the tile is in
+	cgb_palette: u8,	// CGB only: OBJ palette 0-7
+}
+
+impl Sprite {
+
+	pub fn from_oam(base_addr: u16, oam: &[u8; 160]) -> Self {
+
+		let attributes = oam[base_addr as usize + 3];
+
+		Self {
+			pos_y: oam[base_addr as usize] as i16 - 16,
+			pos_x: oam[base_addr as usize + 1] as i16 - 8,
+			tile_id: oam[base_addr as usize + 2],
+
+			priority: 	attributes & 0x80 != 0,
+			y_flip: 	attributes & 0x40 != 0,
+			x_flip:		attributes & 0x20 != 0,
+			palette:	attributes & 0x10 != 0,
+
+			cgb_bank:		attributes & 0x08 != 0,
+			cgb_palette:	attributes & 0x07,
+		}
+	}
+
+}
+
+struct LCDC {
+	lcd_enable: bool,			// 0: off 1: on
+	window_tilemap_area: bool,	// 0: 0x9800-0x9BFF 1: 0x9C00-0x9FFF
+	window_enable: bool,		// 0: on 1: on
+	tile_data_area: bool,		// (for both bg & window) 0: 0x8800-0x97FF 1: 0x8000-0x8FFF
+	bg_tilemap_area: bool,		// 0: 0x9800-0x9BFF 1: 0x9C00-0x9FFF
+	obj_size: bool,				// 0: 8x8 1: 8x16
+	obj_enable: bool,			// 0: objects disabled 1: objects enabled
+	bg_enable: bool				// 0: bg and window are disabled (even if window_enable is 1)
+}
+
+impl LCDC {
+
+	pub fn new(initial_write: u8) -> Self {
+		let mut lcdc = LCDC {
+			lcd_enable: false,
+			window_tilemap_area: false,
+			window_enable: false,
+			tile_data_area: false,
+			bg_tilemap_area: false,
+			obj_size: false,
+			obj_enable: false,
+			bg_enable: false
+		};
+
+		lcdc.write(initial_write);
+
+		lcdc
+	}
+
+	pub fn read(&self) -> u8 {
+		(if self.bg_enable		 			{ 0x01 } else { 0 })
+            | (if self.obj_enable 			{ 0x02 } else { 0 })
+            | (if self.obj_size 			{ 0x04 } else { 0 })
+            | (if self.bg_tilemap_area 		{ 0x08 } else { 0 })
+            | (if self.tile_data_area 		{ 0x10 } else { 0 })
+            | (if self.window_enable 		{ 0x20 } else { 0 })
+            | (if self.window_tilemap_area 	{ 0x40 } else { 0 })
+            | (if self.lcd_enable 			{ 0x80 } else { 0 })
+	}
+
+	pub fn write(&mut self, write: u8) {
+
+		self.bg_enable		 		= write & 0x01 != 0;
+		self.obj_enable				= write & 0x02 != 0;
+		self.obj_size 				= write & 0x04 != 0;
+		self.bg_tilemap_area 		= write & 0x08 != 0;
+		self.tile_data_area 		= write & 0x10 != 0;
+		self.window_enable 			= write & 0x20 != 0;
+		self.window_tilemap_area 	= write & 0x40 != 0;
+		self.lcd_enable 			= write & 0x80 != 0;
+	}
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GBColour {
+	White = 0,
+	LightGrey = 1,
+	DarkGrey = 2,
+	Black = 3
+}
+
+impl From<u8> for GBColour {
+	
+	fn from(from: u8) -> GBColour {
+		// only the low 2 bits are used
+		match from & 0b11 {
+			0 => GBColour::White,
+			1 => GBColour::LightGrey,
+			2 => GBColour::DarkGrey,
+			_ => GBColour::Black,
+		}
+	}
+
+}
+
+impl GBColour {
+
+	// the shade as a 15-bit RGB colour
+	pub fn to_rgb555(self) -> u16 {
+		match self {
+			GBColour::White => 0x7FFF,
+			GBColour::LightGrey => 0x56B5,
+			GBColour::DarkGrey => 0x294A,
+			GBColour::Black => 0x0000,
+		}
+	}
+
+}
+
+// frames are made of 15-bit RGB colours as stored in CGB palette RAM: red in bits 0-4, green in 5-9 and blue in 10-14
+pub fn rgb555_to_rgb888(colour: u16) -> [u8; 3] {
+	let scale = |c: u16| ((c << 3) | (c >> 2)) as u8;
+
+	[scale(colour & 0x1F), scale((colour >> 5) & 0x1F), scale((colour >> 10) & 0x1F)]
+}
+
+#[derive(Debug)]
+struct Palette {
+	id_0: GBColour,
+	id_1: GBColour,
+	id_2: GBColour,
+	id_3: GBColour,
+}
+
+impl Palette {
+
+	pub fn new(initial_write: u8) -> Self {
+		let mut pal = Self {
+			id_0: GBColour::White,
+			id_1: GBColour::LightGrey,
+			id_2: GBColour::DarkGrey,
+			id_3: GBColour::Black,
+		};
+
+		pal.write(initial_write);
+
+		return pal;
+	}
+
+	pub fn get_pal_value(&self, id: u8) -> GBColour {
+
+		match id & 0b11 {
+			0 => self.id_0,
+			1 => self.id_1,
+			2 => self.id_2,
+			_ => self.id_3,
+		}
+
+	}
+
+	pub fn read(&self) -> u8 {
+		self.id_0 as u8
+			| (self.id_1 as u8) << 2
+			| (self.id_2 as u8) << 4
+			| (self.id_3 as u8) << 6
+	}
+
+	pub fn write(&mut self, write: u8) {
+		self.id_0 = GBColour::from(write & 0b11);
+		self.id_1 = GBColour::from((write >> 2) & 0b11);
+		self.id_2 = GBColour::from((write >> 4) & 0b11);
+		self.id_3 = GBColour::from((write >> 6) & 0b11);
+	}
+
+}
+
+pub struct PPU {
+	pub rendering_mode: RenderingMode,
+
+	interrupts: u8,		// IF bits raised since the last call to take_interrupts()
+
+	reg_scy: u8,		// 0xFF42: Y scroll register
+	reg_scx: u8,		// 0xFF43: X scroll register
+	reg_wy: u8,			// 0xFF4A: window Y position
+	reg_wx: u8,			// 0xFF4B: window X position - 7
+
+	reg_ly: u8,         // 0xFF44: amount of lines drawn this frame, also the LY register
+	reg_lyc: u8,        // 0xFF45: if lyc == ly and LYC=LY in STAT register set, raise interrupt
+	reg_stat: u8,		// 0xFF41: LCD status register. Can be used to raise interrupts at various stages of rendering
+	reg_lcdc: LCDC,		// 0xFF40: LCD control register. Can be used to alter the behaviour of the LCD and PPU
+	
+	reg_bgp: Palette,	// 0xFF47: Background palette
+	reg_obp0: Palette,	// 0xFF48: Object palette 0
+	reg_obp1: Palette,	// 0xFF49: Object palette 1
+
+	win_ly: u8,			// window internal line counter
+	
+	pub line_dots: i32, // amount of dots that has passed; reset each line.
+	draw_dots: i32,		// length of mode 3 on the current line, which decides how long HBlank is
+
+	renderer: Renderer,
+	fifo: Fifo,
+
+	pub vram: [u8; 0x4000], // 2 banks of 8k; the second one is only used on the CGB
+	oam: [u8; 160],
+
+	cgb: bool,			// CGB features: VRAM bank 1, tile attributes and colour palettes
+	compat: bool,		// a DMG game on a CGB. Rendering works like a DMG, but colours go through palette RAM
+
+	vram_bank: u8,		// 0xFF4F: VBK
+
+	bcps: u8,			// 0xFF68: BG palette index, bit 7 auto-increments it after writing BCPD
+	ocps: u8,			// 0xFF6A: OBJ palette index
+	bg_palette_ram: [u8; 64],	// 8 palettes of 4 colours, read and written through BCPD (0xFF69)
+	obj_palette_ram: [u8; 64],	// read and written through OCPD (0xFF6B)
+
+	line_bg_index: [u8; 160],		// colour index of each background pixel on the current line
+	line_bg_priority: [bool; 160],	// CGB tile attribute bit 7: the background is drawn over objects
+
+	sprite_cache: [Sprite; 40],
+
+	pub display_buf: Box<[u16; 144 * 160]>,
+	draw_buf: Box<[u16; 144 * 160]>,
+
+	pub tile_data_buf: Vec<[u16; 8 * 8]>
+}
+
+// where each model's bootrom hands over, as LY and the dots into that line. The DMG0's bootrom hands over partway into
+// VBlank, the later ones right as line 0 starts. The SGB and CGB positions haven't been measured, so they use the DMG's
+fn boot_position(model: Model) -> (u8, i32) {
+	match model {
+		Model::Dmg0 => (144, 253),
+		_ => (0, 0),
+	}
+}
+
+impl PPU {
+
+	pub fn new(model: Model, cgb: bool, compat: bool) -> Self {
+
+		let (reg_ly, line_dots) = boot_position(model);
+		let rendering_mode = if reg_ly < 144 { RenderingMode::OAMscan } else { RenderingMode::VBlank };
+		let lyc_cmp = if reg_ly == 0 { StatFlag::LYCcmp as u8 } else { 0 };
+
+		// palette RAM starts out white. In compatibility mode the bootrom loads greyscale palettes for BGP, OBP0 and OBP1
+		let mut bg_palette_ram = [0xFF; 64];
+		let mut obj_palette_ram = [0xFF; 64];
+
+		if compat {
+			for (i, shade) in [GBColour::White, GBColour::LightGrey, GBColour::DarkGrey, GBColour::Black].iter().enumerate() {
+				let colour = shade.to_rgb555().to_le_bytes();
+
+				bg_palette_ram[i * 2..i * 2 + 2].copy_from_slice(&colour);
+				obj_palette_ram[i * 2..i * 2 + 2].copy_from_slice(&colour);
+				obj_palette_ram[8 + i * 2..8 + i * 2 + 2].copy_from_slice(&colour);
+			}
+		}
+
+		Self {
+			rendering_mode,
+
+			interrupts: 0,
+
+			reg_scy: 0,
+			reg_scx: 0,
+			reg_wy: 0,
+			reg_wx: 0,
+
+			reg_ly,
+			reg_lyc: 0,
+			reg_stat: 0x80 | lyc_cmp | rendering_mode as u8,
+			reg_lcdc: LCDC::new(0x91),
+
+			reg_bgp: Palette::new(0xFC),
+			reg_obp0: Palette::new(0),
+			reg_obp1: Palette::new(0),
+
+			win_ly: 0,
+
+			line_dots,
+			draw_dots: 172,
+
+			renderer: Renderer::Scanline,
+			fifo: Fifo::default(),
+
+			vram: [0; 0x4000],
+			oam: [0; 160],
+
+			cgb,
+			compat,
+
+			vram_bank: 0,
+
+			bcps: 0,
+			ocps: 0,
+			bg_palette_ram,
+			obj_palette_ram,
+
+			line_bg_index: [0; 160],
+			line_bg_priority: [false; 160],
+
+			sprite_cache: [Sprite::default(); 40],
+
+			display_buf: Box::new([GBColour::LightGrey.to_rgb555(); 144 * 160]),
+			draw_buf: Box::new([GBColour::LightGrey.to_rgb555(); 144 * 160]),
+
+			tile_data_buf: vec![[0; 8 * 8]; 384],
+		}
+	}
+
+	fn change_mode(&mut self, mode: RenderingMode) {
+
+		self.rendering_mode = mode;
+		self.reg_stat = (self.reg_stat & !0x3) | self.rendering_mode as u8;
+
+		match mode {
+			RenderingMode::Draw => {
+				// the renderer is picked per line, so switching renderers never leaves a line half-drawn
+				match self.renderer {
+					Renderer::Scanline => self.fifo.active = false,
+					Renderer::Fifo => self.start_fifo_line(),
+				}
+			},
+			RenderingMode::HBlank => {
+
+				if !self.fifo.active {
+					self.draw_scanline();
+				}
+
+				// compare lyc at the start of hblank, increment ly at the end of hblank
+				self.compare_lyc();
+
+				if self.reg_stat & StatFlag::Mode0Int as u8 != 0 && self.rendering_mode != RenderingMode::HBlank {
+					self.interrupts |= InterruptFlag::LCDC as u8;
+				}
+			},
+			RenderingMode::VBlank => {
+				self.interrupts |= InterruptFlag::VBlank as u8;
+
+				if self.reg_stat & StatFlag::Mode1Int as u8 != 0 {
+					self.interrupts |= InterruptFlag::LCDC as u8;
+				}
+			},
+			RenderingMode::OAMscan => {
+				if self.reg_stat & StatFlag::Mode2Int as u8 != 0 && self.rendering_mode != RenderingMode::OAMscan {
+					self.interrupts |= InterruptFlag::LCDC as u8;
+				}
+			},
+		}
+
+	}
+
+	pub fn take_interrupts(&mut self) -> u8 {
+		std::mem::take(&mut self.interrupts)
+	}
+
+	pub fn renderer(&self) -> Renderer {
+		self.renderer
+	}
+
+	// takes effect from the next line
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		self.renderer = renderer;
+	}
+
+	pub fn tick(&mut self, cycles: u64) {
+
+		if !self.reg_lcdc.lcd_enable {
+			self.rendering_mode = RenderingMode::HBlank;
+			self.reg_ly = 0;
+			self.line_dots = 0;
+
+			self.reg_stat = (self.reg_stat & !0x3) | self.rendering_mode as u8;
+
+			self.fifo.active = false;
+			self.fifo.wy_triggered = false;
+
+			return;
+		}
+
+		// 1 T-state == 1 dot
+		for _ in 0..cycles {
+			self.tick_dot();
+		}
+
+	}
+
+	fn tick_dot(&mut self) {
+
+		self.line_dots += 1;
+
+		match self.rendering_mode {
+			RenderingMode::OAMscan if self.line_dots >= 80 => {
+				self.line_dots -= 80;
+
+				self.change_mode(RenderingMode::Draw)
+			},
+			RenderingMode::Draw if self.fifo.active => {
+				if self.fifo_dot() {
+					self.draw_dots = self.line_dots;
+					self.line_dots = 0;
+
+					self.change_mode(RenderingMode::HBlank);
+				}
+			},
+			RenderingMode::Draw if self.line_dots >= 172 => {
+				self.line_dots -= 172;
+				self.draw_dots = 172;
+
+				self.change_mode(RenderingMode::HBlank);
+			},
+			// LY moves on to the next line 4 dots before the next line's mode does, so STAT still reads HBlank (or VBlank) for
+			// the first 4 dots of each line
+			RenderingMode::HBlank if self.line_dots == 372 - self.draw_dots => {
+				self.reg_ly += 1;
+			},
+			// mode 2, 3 and 0 always add up to 376 dots
+			RenderingMode::HBlank if self.line_dots >= 376 - self.draw_dots => {
+				self.line_dots -= 376 - self.draw_dots;
+
+				if self.reg_ly == 144 {
+					self.change_mode(RenderingMode::VBlank);
+				} else {
+					self.change_mode(RenderingMode::OAMscan);
+				}
+			},
+			RenderingMode::VBlank if self.line_dots == 452 => {
+				self.reg_ly = (self.reg_ly + 1) % 154;
+			},
+			RenderingMode::VBlank if self.line_dots >= 456 => {
+				self.line_dots -= 456;
+
+				if self.reg_ly == 0 {
+
+					std::mem::swap(&mut self.draw_buf, &mut self.display_buf);
+
+					self.win_ly = 0;
+					self.fifo.wy_triggered = false;
+					
+					self.change_mode(RenderingMode::OAMscan);
+
+				}
+
+				// idk if this should be before or after the ly increment
+				self.compare_lyc();
+			},
+			_ => {}
+		}
+
+	}
+
+	fn compare_lyc(&mut self) {
+		if self.reg_ly + 1 == self.reg_lyc || (self.reg_ly == 153 && self.reg_lyc == 0) {
+			
+			if self.reg_stat & StatFlag::LYCInt as u8 != 0 {
+				self.reg_stat |= StatFlag::LYCcmp as u8;
+				self.interrupts |= InterruptFlag::LCDC as u8;
+			}
+
+		} else {
+			self.reg_stat &= !(StatFlag::LYCcmp as u8);
+		}
+	}
+
+	fn draw_scanline(&mut self) {
+
+		if self.reg_lcdc.window_enable && self.reg_ly > self.reg_wy && self.reg_wx < 159 + 7 && self.reg_wy < 144 {
+			self.win_ly = self.win_ly.wrapping_add(1);
+		}
+
+		self.cache_all_sprites();
+
+		// on the CGB, LCDC bit 0 doesn't turn the background off, it just puts objects above it
+		if self.reg_lcdc.bg_enable || self.cgb {
+			self.draw_tiles()
+		} else {
+			for x in 0..160 {
+				self.draw_buf[x + 160 * self.reg_ly as usize] = GBColour::White.to_rgb555();
+				self.line_bg_index[x] = 0;
+				self.line_bg_priority[x] = false;
+			}
+		}
+
+		if self.reg_lcdc.obj_enable {
+			self.draw_sprites()
+		}
+
+	}
+
+	fn draw_tiles(&mut self) {
+
+		let (tile_data_area, sign): (u16, bool) = match self.reg_lcdc.tile_data_area {
+			false => (0x9000, true),	// signed addressing uses 0x9000 as a base pointer
+			true => (0x8000, false),
+		};
+
+		for x in 0..160_u8 {
+
+			let in_window = self.reg_lcdc.window_enable && self.reg_ly >= self.reg_wy && x as isize >= self.reg_wx as isize - 7;
+
+			let y_pos = match in_window {
+				true => self.win_ly,
+				false => self.reg_ly.wrapping_add(self.reg_scy)
+			};
+
+			let x_pos = match in_window {
+				true => x.wrapping_sub(self.reg_wx.wrapping_sub(7)),
+				false => x.wrapping_add(self.reg_scx)
+			};
+
+			let tilemap_area: u16 = match in_window {
+				false => match self.reg_lcdc.bg_tilemap_area {
+					false => 0x9800,
+					true => 0x9C00,
+				},
+				true => match self.reg_lcdc.window_tilemap_area {
+					false => 0x9800,
+					true => 0x9C00,
+				}
+			};
+
+			let tilemap_addr = tilemap_area + ((y_pos as u16 / 8) * 32) + (x_pos as u16 / 8);
+
+			// get tile id
+			let tile_id = self.read_vram(0, tilemap_addr);
+
+			// CGB tile attributes are stored in VRAM bank 1, at the same address as the tile id
+			let attributes = if self.cgb { self.read_vram(1, tilemap_addr) } else { 0 };
+
+			// get tile data base address
+			let tile_base_addr = match sign {
+				false => tile_data_area + tile_id as u16 * 16,
+				true => tile_data_area.wrapping_add(((tile_id as i8) as u16).wrapping_mul(16))
+			};
+
+			let tile_row = match attributes & 0x40 != 0 {
+				false => y_pos % 8,
+				true => 7 - (y_pos % 8),	// y flip
+			};
+
+			let tile_addr_offset = tile_row as u16 * 2;
+			let tile_bank = (attributes >> 3) & 1;
+
+			let data_1 = self.read_vram(tile_bank, tile_base_addr + tile_addr_offset);
+			let data_2 = self.read_vram(tile_bank, tile_base_addr + tile_addr_offset + 1);
+
+			// the index of the tiles used to form the palette id for this pixel
+			let mut pixel_index = match in_window {
+				true => self.reg_wx.wrapping_sub(x) % 8,
+				false => 7 - (x_pos % 8),		// the 7 is there to swap which bit is selected
+			};
+
+			// x flip
+			if attributes & 0x20 != 0 {
+				pixel_index = 7 - pixel_index;
+			}
+
+			let pal_id = (data_1 >> pixel_index & 1) | (data_2 >> pixel_index & 1) << 1;
+
+			self.line_bg_index[x as usize] = pal_id;
+			self.line_bg_priority[x as usize] = attributes & 0x80 != 0;
+
+			self.draw_buf[x as usize + 160 * self.reg_ly as usize] = self.bg_colour(attributes & 0x7, pal_id);
+
+		}
+
+	}
+
+	pub fn draw_sprites(&mut self) {
+
+		let size_y = match self.reg_lcdc.obj_size {
+			true => 16,
+			false => 8,
+		};
+
+		let sprites = self.cache_sprites_on_line();
+
+		for sprite in sprites.iter() {
+
+			// what row of the sprite the scanline intersects with
+			let sprite_line = match sprite.y_flip {
+				false => (self.reg_ly as i16 - sprite.pos_y) as u16,
+				true => (size_y as i16 - 1 - (self.reg_ly as i16 - sprite.pos_y)) as u16,
+			};
+
+			// 8x16 sprites ignore the LSB of the tile id (i.e it becomes every second tile)
+			let tile_mask = match self.reg_lcdc.obj_size {
+				true => 0xFE,
+				false => 0xFF,
+			};
+
+			let tile_data_addr = (0x8000 + (sprite.tile_id & tile_mask) as u16 * 16) + sprite_line * 2;
+			let tile_bank = (self.cgb && sprite.cgb_bank) as u8;
+
+			let data_1 = self.read_vram(tile_bank, tile_data_addr);
+			let data_2 = self.read_vram(tile_bank, tile_data_addr + 1);
+
+			for x in 0..8 {
+
+				let x_offset = sprite.pos_x + x;
+
+				if x_offset < 0 || x_offset > 159 {
+					continue;
+				}
+
+				let pixel_buf_index = x_offset as usize + 160 * self.reg_ly as usize;
+
+				// background colours 1-3 cover the object if either it or (on the CGB) the tile asks for it,
+				// unless LCDC bit 0 is cleared on the CGB, which puts every object on top
+				let bg_over_obj = (sprite.priority || self.line_bg_priority[x_offset as usize])
+					&& self.line_bg_index[x_offset as usize] != 0
+					&& (self.reg_lcdc.bg_enable || !self.cgb);
+
+				if bg_over_obj {
+					continue;
+				}
+
+				let pixel_index = match sprite.x_flip {
+					false => 7 - x,
+					true => x,
+				};
+
+				let pal_id = (data_1 >> pixel_index & 1) | (data_2 >> pixel_index & 1) << 1;
+
+				if pal_id != 0 {
+					self.draw_buf[pixel_buf_index] = self.obj_colour(sprite.palette, sprite.cgb_palette, pal_id);
+				}
+
+			}
+
+		}
+
+	}
+
+	pub fn cache_all_sprites(&mut self) {
+
+		for sprite_index in 0..40 {
+			self.sprite_cache[sprite_index] = Sprite::from_oam(sprite_index as u16 * 4, &self.oam)
+		}
+
+	}
+
+	fn cache_sprites_on_line(&mut self) -> Vec<Sprite> {
+
+		let mut sprites_on_line: Vec<Sprite> = Vec::new();
+
+		let size_y = match self.reg_lcdc.obj_size {
+			true => 16,
+			false => 8,
+		};
+
+		for sprite in self.sprite_cache {
+			
+			if self.reg_ly as i16 >= sprite.pos_y && (self.reg_ly as i16) < (sprite.pos_y + size_y) {
+				sprites_on_line.push(sprite.clone());
+			}
+
+		}
+
+		sprites_on_line.truncate(10);
+		
+		// sort sprites by lowest x position (insertion sort). The CGB only uses OAM order for priority
+		for i in 1..sprites_on_line.len() {
+			let mut j = i;
+			while !self.cgb && j > 0 && sprites_on_line[j - 1].pos_x > sprites_on_line[j].pos_x {
+				sprites_on_line.swap(j - 1, j);
+				j -= 1;
+			}
+		}
+
+		// theres probably a more performant way to do this by modifying insertion sort algorithm,
+		// but reversing the order of the sort breaks same x-coordinate priority
+		sprites_on_line.reverse();
+
+		sprites_on_line
+
+	}
+
+	pub fn draw_tile_data(&mut self) {
+
+		for tile in 0..384_u16 {
+			
+			for tile_offset in 0..8_u16 {
+
+				let data_1 = self.read_vram(0, 0x8000 + tile * 16 + (tile_offset * 2));
+				let data_2 = self.read_vram(0, 0x8000 + tile * 16 + (tile_offset * 2 + 1));
+
+				for pixel in 0..8 {
+
+					let pal_id = (data_1 >> pixel & 1) | (data_2 >> pixel & 1) << 1;
+
+					self.tile_data_buf[tile as usize][(pixel + 8 * tile_offset) as usize] = self.bg_colour(0, pal_id);
+
+				}
+
+			}
+
+		}
+
+	}
+
+	// 15-bit RGB, see rgb555_to_rgb888()
+	pub fn get_frame(&self) -> [u16; 160 * 144] {
+		*self.display_buf
+	}
+
+	fn read_vram(&self, bank: u8, addr: u16) -> u8 {
+		self.vram[bank as usize * 0x2000 + (addr - 0x8000) as usize]
+	}
+
+	fn palette_colour(palette_ram: &[u8; 64], palette: u8, pal_id: u8) -> u16 {
+		let index = palette as usize * 8 + pal_id as usize * 2;
+
+		u16::from_le_bytes([palette_ram[index], palette_ram[index + 1]]) & 0x7FFF
+	}
+
+	fn bg_colour(&self, palette: u8, pal_id: u8) -> u16 {
+		if self.cgb {
+			Self::palette_colour(&self.bg_palette_ram, palette, pal_id)
+		} else if self.compat {
+			Self::palette_colour(&self.bg_palette_ram, 0, self.reg_bgp.get_pal_value(pal_id) as u8)
+		} else {
+			self.reg_bgp.get_pal_value(pal_id).to_rgb555()
+		}
+	}
+
+	// `palette` is OBP0/OBP1 on the DMG, and `cgb_palette` is used in CGB mode
+	fn obj_colour(&self, palette: bool, cgb_palette: u8, pal_id: u8) -> u16 {
+		let dmg_palette = match palette {
+			false => &self.reg_obp0,
+			true => &self.reg_obp1,
+		};
+
+		if self.cgb {
+			Self::palette_colour(&self.obj_palette_ram, cgb_palette, pal_id)
+		} else if self.compat {
+			Self::palette_colour(&self.obj_palette_ram, palette as u8, dmg_palette.get_pal_value(pal_id) as u8)
+		} else {
+			dmg_palette.get_pal_value(pal_id).to_rgb555()
+		}
+	}
+
+	pub fn read(&self, addr: u16) -> u8 {
+		match addr {
+			0x8000..=0x9FFF => self.read_vram(self.vram_bank, addr),
+			0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize],
+
+			0xFF40 => self.reg_lcdc.read(),
+			0xFF41 => self.reg_stat,
+			0xFF42 => self.reg_scy,
+			0xFF43 => self.reg_scx,
+			0xFF44 => self.reg_ly,
+			0xFF45 => self.reg_lyc,
+			0xFF47 => self.reg_bgp.read(),
+			0xFF48 => self.reg_obp0.read(),
+			0xFF49 => self.reg_obp1.read(),
+			0xFF4A => self.reg_wy,
+			0xFF4B => self.reg_wx,
+
+			// CGB registers
+			0xFF4F if self.cgb => 0xFE | self.vram_bank,
+			0xFF68 if self.cgb => self.bcps | 0x40,
+			0xFF69 if self.cgb => self.bg_palette_ram[(self.bcps & 0x3F) as usize],
+			0xFF6A if self.cgb => self.ocps | 0x40,
+			0xFF6B if self.cgb => self.obj_palette_ram[(self.ocps & 0x3F) as usize],
+			// CGB registers without CGB features enabled are open bus, like anything else the PPU doesn't map
+			_ => 0xFF
+		}
+	}
+
+	pub fn write(&mut self, addr: u16, write: u8) {
+		match addr {
+			0x8000..=0x9FFF => self.vram[self.vram_bank as usize * 0x2000 + (addr - 0x8000) as usize] = write,
+			0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize] = write,
+
+			0xFF40 => self.reg_lcdc.write(write),
+			0xFF41 => self.reg_stat = write & !(self.reg_stat & 0b111), // PPUmode and LY=LYC are read only
+			0xFF42 => self.reg_scy = write,
+			0xFF43 => self.reg_scx = write,
+			0xFF44 => {},
+			0xFF45 => self.reg_lyc = write,
+			0xFF47 => self.reg_bgp.write(write),
+			0xFF48 => self.reg_obp0.write(write),
+			0xFF49 => self.reg_obp1.write(write),
+			0xFF4A => self.reg_wy = write,
+			0xFF4B => self.reg_wx = write,
+
+			// CGB registers
+			0xFF4F if self.cgb => self.vram_bank = write & 1,
+			0xFF68 if self.cgb => self.bcps = write & 0xBF,
+			0xFF69 if self.cgb => {
+				self.bg_palette_ram[(self.bcps & 0x3F) as usize] = write;
+				self.bcps = Self::increment_palette_index(self.bcps);
+			},
+			0xFF6A if self.cgb => self.ocps = write & 0xBF,
+			0xFF6B if self.cgb => {
+				self.obj_palette_ram[(self.ocps & 0x3F) as usize] = write;
+				self.ocps = Self::increment_palette_index(self.ocps);
+			},
+			_ => {}
+		}
+	}
+
+	fn increment_palette_index(index: u8) -> u8 {
+		match index & 0x80 != 0 {
+			true => 0x80 | ((index + 1) & 0x3F),
+			false => index,
+		}
+	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.rendering_mode as u8);
+
+		state.write_bytes(&[self.reg_scy, self.reg_scx, self.reg_wy, self.reg_wx, self.reg_ly, self.reg_lyc, self.reg_stat]);
+		state.write_u8(self.reg_lcdc.read());
+
+		state.write_u8(self.reg_bgp.read());
+		state.write_u8(self.reg_obp0.read());
+		state.write_u8(self.reg_obp1.read());
+
+		state.write_u8(self.win_ly);
+		state.write_i32(self.line_dots);
+		state.write_i32(self.draw_dots);
+
+		state.write_u8(self.renderer as u8);
+		self.fifo.save_state(state);
+
+		state.write_bytes(&self.vram);
+		state.write_bytes(&self.oam);
+
+		state.write_u8(self.vram_bank);
+		state.write_u8(self.bcps);
+		state.write_u8(self.ocps);
+		state.write_bytes(&self.bg_palette_ram);
+		state.write_bytes(&self.obj_palette_ram);
+
+		// the half-drawn frame is needed to resume rendering mid-frame
+		for pixel in self.draw_buf.iter().chain(self.display_buf.iter()) {
+			state.write_u16(*pixel);
+		}
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.rendering_mode = RenderingMode::from_u8(state.read_u8()?).ok_or(StateError::InvalidValue("PPU mode"))?;
+
+		let mut regs = [0; 7];
+		state.read_bytes(&mut regs)?;
+		[self.reg_scy, self.reg_scx, self.reg_wy, self.reg_wx, self.reg_ly, self.reg_lyc, self.reg_stat] = regs;
+
+		self.reg_lcdc.write(state.read_u8()?);
+
+		self.reg_bgp.write(state.read_u8()?);
+		self.reg_obp0.write(state.read_u8()?);
+		self.reg_obp1.write(state.read_u8()?);
+
+		self.win_ly = state.read_u8()?;
+		self.line_dots = state.read_i32()?;
+		self.draw_dots = state.read_i32()?;
+
+		self.renderer = match state.read_u8()? {
+			0 => Renderer::Scanline,
+			1 => Renderer::Fifo,
+			_ => return Err(StateError::InvalidValue("renderer")),
+		};
+		self.fifo.load_state(state)?;
+
+		if !(0..=376).contains(&self.draw_dots) {
+			return Err(StateError::InvalidValue("mode 3 length"));
+		}
+
+		state.read_bytes(&mut self.vram)?;
+		state.read_bytes(&mut self.oam)?;
+
+		self.vram_bank = state.read_u8()? & 1;
+		self.bcps = state.read_u8()?;
+		self.ocps = state.read_u8()?;
+		state.read_bytes(&mut self.bg_palette_ram)?;
+		state.read_bytes(&mut self.obj_palette_ram)?;
+
+		for buf in [&mut self.draw_buf, &mut self.display_buf] {
+			for pixel in buf.iter_mut() {
+				*pixel = state.read_u16()? & 0x7FFF;
+			}
+		}
+
+		Ok(())
+	}
+
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+// snapshots are taken once a frame
+pub const SNAPSHOT_INTERVAL: u64 = crate::FRAME_CYCLES;
+pub const SNAPSHOTS_PER_SECOND: usize = 60;
+
+// a ring of save states, newest last. Only the newest is kept whole: every older one is stored as the bytes that differ
+// from the one after it, so a second of gameplay that only touches a little memory takes up very little room
+pub struct Rewind {
+	capacity: usize,	// snapshots, 0 if rewinding is off
+
+	latest: Option<Vec<u8>>,
+	deltas: VecDeque<Vec<u8>>,	// each turns the snapshot after it back into the one before
+}
+
+impl Rewind {
+
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+
+			latest: None,
+			deltas: VecDeque::new(),
+		}
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.capacity != 0
+	}
+
+	pub fn len(&self) -> usize {
+		self.deltas.len() + self.latest.is_some() as usize
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.latest.is_none()
+	}
+
+	// the memory the snapshots are using, in bytes
+	pub fn size(&self) -> usize {
+		self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+	}
+
+	pub fn push(&mut self, snapshot: Vec<u8>) {
+
+		if !self.enabled() {
+			return;
+		}
+
+		if let Some(latest) = self.latest.take() {
+			self.deltas.push_back(encode_delta(&snapshot, &latest));
+
+			if self.deltas.len() >= self.capacity {
+				self.deltas.pop_front();
+			}
+		}
+
+		self.latest = Some(snapshot);
+
+	}
+
+	// takes the newest snapshot out
+	pub fn pop(&mut self) -> Option<Vec<u8>> {
+
+		let latest = self.latest.take()?;
+
+		self.latest = self.deltas.pop_back().map(|delta| apply_delta(&latest, &delta));
+
+		Some(latest)
+
+	}
+
+	pub fn clear(&mut self) {
+		self.latest = None;
+		self.deltas.clear();
+	}
+
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+	while value >= 0x80 {
+		out.push(value as u8 | 0x80);
+		value >>= 7;
+	}
+
+	out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+	let mut value = 0;
+	let mut shift = 0;
+
+	while let Some(&byte) = bytes.get(*pos) {
+		*pos += 1;
+		value |= (byte as usize & 0x7F) << shift;
+		shift += 7;
+
+		if byte & 0x80 == 0 {
+			break;
+		}
+	}
+
+	value
+}
+
+// the length of `to`, then alternating runs of bytes that are the same in both and XORs of the bytes that aren't.
+// Anything past the end of `from` is compared against 0
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+
+	let mut delta = Vec::new();
+	write_varint(&mut delta, to.len());
+
+	let xor = |i: usize| to[i] ^ from.get(i).copied().unwrap_or(0);
+	let mut i = 0;
+
+	while i < to.len() {
+
+		let same_start = i;
+		while i < to.len() && xor(i) == 0 {
+			i += 1;
+		}
+
+		let diff_start = i;
+		while i < to.len() && xor(i) != 0 {
+			i += 1;
+		}
+
+		write_varint(&mut delta, diff_start - same_start);
+		write_varint(&mut delta, i - diff_start);
+		delta.extend((diff_start..i).map(xor));
+
+	}
+
+	delta
+
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+
+	let mut pos = 0;
+	let len = read_varint(delta, &mut pos);
+
+	let mut to = from.to_vec();
+	to.resize(len, 0);
+
+	let mut i = 0;
+
+	while pos < delta.len() && i < len {
+
+		i += read_varint(delta, &mut pos);
+		let diff_len = read_varint(delta, &mut pos);
+
+		for (byte, xor) in to.iter_mut().skip(i).zip(delta[pos..].iter().take(diff_len)) {
+			*byte ^= xor;
+		}
+
+		i += diff_len;
+		pos += diff_len;
+
+	}
+
+	to
+
+}
\ No newline at end of file
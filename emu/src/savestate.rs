@@ -0,0 +1,157 @@
+// magic bytes at the start of every save state
+pub const STATE_MAGIC: [u8; 4] = *b"GBSS";
+
+// bumped whenever the layout of a save state changes. States with a different version are rejected.
+pub const STATE_VERSION: u32 = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+	InvalidMagic,
+	UnsupportedVersion(u32),
+	RomMismatch,
+	UnexpectedEof,
+	InvalidValue(&'static str),
+}
+
+impl std::fmt::Display for StateError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidMagic => write!(f, "not a save state"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported save state version {} (expected {})", version, STATE_VERSION),
+			Self::RomMismatch => write!(f, "save state was created with a different ROM"),
+			Self::UnexpectedEof => write!(f, "save state is truncated"),
+			Self::InvalidValue(what) => write!(f, "save state contains an invalid {}", what),
+		}
+	}
+}
+
+impl std::error::Error for StateError {}
+
+// serialises component state into a flat little-endian byte buffer
+#[derive(Default)]
+pub struct StateWriter {
+	buf: Vec<u8>,
+}
+
+impl StateWriter {
+
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn write_u8(&mut self, value: u8) {
+		self.buf.push(value);
+	}
+
+	pub fn write_bool(&mut self, value: bool) {
+		self.buf.push(value as u8);
+	}
+
+	pub fn write_u16(&mut self, value: u16) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_u32(&mut self, value: u32) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_u64(&mut self, value: u64) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_i32(&mut self, value: i32) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_f32(&mut self, value: f32) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	// writes a fixed-size block; the reader must know the length ahead of time
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+
+	// writes a variable-size block prefixed with its length
+	pub fn write_vec(&mut self, bytes: &[u8]) {
+		self.write_u32(bytes.len() as u32);
+		self.buf.extend_from_slice(bytes);
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.buf
+	}
+
+}
+
+pub struct StateReader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+
+	pub fn new(buf: &'a [u8]) -> Self {
+		Self {
+			buf,
+			pos: 0,
+		}
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+		if self.buf.len() - self.pos < len {
+			return Err(StateError::UnexpectedEof);
+		}
+
+		let bytes = &self.buf[self.pos..self.pos + len];
+		self.pos += len;
+
+		Ok(bytes)
+	}
+
+	pub fn read_u8(&mut self) -> Result<u8, StateError> {
+		Ok(self.take(1)?[0])
+	}
+
+	pub fn read_bool(&mut self) -> Result<bool, StateError> {
+		Ok(self.read_u8()? != 0)
+	}
+
+	pub fn read_u16(&mut self) -> Result<u16, StateError> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32, StateError> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, StateError> {
+		Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+
+	pub fn read_i32(&mut self) -> Result<i32, StateError> {
+		Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	pub fn read_f32(&mut self) -> Result<f32, StateError> {
+		Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	// fills `out` with the next out.len() bytes
+	pub fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+		out.copy_from_slice(self.take(out.len())?);
+
+		Ok(())
+	}
+
+	pub fn read_vec(&mut self) -> Result<Vec<u8>, StateError> {
+		let len = self.read_u32()? as usize;
+
+		Ok(self.take(len)?.to_vec())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pos == self.buf.len()
+	}
+
+}
\ No newline at end of file
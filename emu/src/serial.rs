@@ -0,0 +1,190 @@
+use crate::interrupt::InterruptFlag;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// the internal clock runs at 8192Hz, so each bit takes 512 T-states
+const INTERNAL_BIT_PERIOD: u64 = 512;
+// the CGB's fast clock (SC bit 1) runs at 262144Hz
+const FAST_BIT_PERIOD: u64 = 16;
+
+// the other end of the link cable. Frontends implement this to connect the serial port to something.
+pub trait LinkPort: Send {
+	// called when a transfer is started using the internal clock, with the byte being sent.
+	// Returns the byte the other side shifts back in.
+	fn transfer(&mut self, out: u8) -> u8;
+
+	// polled while a transfer is waiting on an external clock, with the byte in SB.
+	// Returns the byte shifted in once the other side has clocked a transfer.
+	fn poll_external(&mut self, _out: u8) -> Option<u8> {
+		None
+	}
+}
+
+// no cable plugged in. Every bit reads as 1 and external clock transfers never complete.
+pub struct DisconnectedPort;
+
+impl LinkPort for DisconnectedPort {
+	fn transfer(&mut self, _out: u8) -> u8 {
+		0xFF
+	}
+}
+
+// passes every byte sent with the internal clock to a sink, e.g. to collect the output of test roms
+pub struct CapturePort {
+	sink: Box<dyn FnMut(u8) + Send>,
+}
+
+impl CapturePort {
+	pub fn new(sink: Box<dyn FnMut(u8) + Send>) -> Self {
+		Self {
+			sink
+		}
+	}
+}
+
+impl LinkPort for CapturePort {
+	fn transfer(&mut self, out: u8) -> u8 {
+		(self.sink)(out);
+
+		0xFF
+	}
+}
+
+pub struct Serial {
+	interrupts: u8,		// IF bits raised since the last call to take_interrupts()
+	port: Box<dyn LinkPort>,
+
+	cgb: bool,
+
+	sb: u8,
+	sc: u8,
+
+	incoming: u8,	// byte being shifted into SB, MSB first
+	bits_left: u8,
+	bit_clock: u64,	// T-states since the last bit was shifted
+}
+
+impl Serial {
+
+	pub fn new(cgb: bool) -> Self {
+		Self {
+			interrupts: 0,
+			port: Box::new(DisconnectedPort),
+
+			cgb,
+
+			sb: 0,
+			sc: 0,
+
+			incoming: 0xFF,
+			bits_left: 0,
+			bit_clock: 0,
+		}
+	}
+
+	pub fn set_port(&mut self, port: Box<dyn LinkPort>) {
+		self.port = port;
+	}
+
+	pub fn read(&self, addr: u16) -> u8 {
+		match addr {
+			0xFF01 => self.sb,
+			0xFF02 => self.sc | if self.cgb { 0x7C } else { 0x7E },	// unused bits read as 1
+			_ => 0xFF
+		}
+	}
+
+	pub fn write(&mut self, addr: u16, write: u8) {
+		match addr {
+			0xFF01 => self.sb = write,
+			0xFF02 => {
+				self.sc = write & if self.cgb { 0x83 } else { 0x81 };
+
+				if self.transfer_requested() && self.internal_clock() {
+					self.incoming = self.port.transfer(self.sb);
+					self.bits_left = 8;
+					self.bit_clock = 0;
+				}
+			}
+			_ => {}
+		}
+	}
+
+	pub fn tick(&mut self, cycles: u64) {
+
+		if !self.transfer_requested() {
+			return;
+		}
+
+		if !self.internal_clock() {
+			// the other side drives the clock, so the whole byte arrives at once
+			if let Some(incoming) = self.port.poll_external(self.sb) {
+				self.sb = incoming;
+				self.finish_transfer();
+			}
+
+			return;
+		}
+
+		let bit_period = if self.sc & 0x2 != 0 { FAST_BIT_PERIOD } else { INTERNAL_BIT_PERIOD };
+
+		self.bit_clock += cycles;
+
+		while self.bit_clock >= bit_period && self.bits_left > 0 {
+			self.bit_clock -= bit_period;
+
+			self.sb = (self.sb << 1) | (self.incoming >> 7);
+			self.incoming <<= 1;
+			self.bits_left -= 1;
+
+			if self.bits_left == 0 {
+				self.finish_transfer();
+			}
+		}
+
+	}
+
+	fn finish_transfer(&mut self) {
+		self.sc &= !0x80;
+		self.bit_clock = 0;
+
+		self.interrupts |= InterruptFlag::Serial as u8;
+	}
+
+	pub fn take_interrupts(&mut self) -> u8 {
+		std::mem::take(&mut self.interrupts)
+	}
+
+	fn transfer_requested(&self) -> bool {
+		self.sc & 0x80 != 0
+	}
+
+	fn internal_clock(&self) -> bool {
+		self.sc & 0x1 != 0
+	}
+
+	// the link port itself isn't part of the state
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.sb);
+		state.write_u8(self.sc);
+
+		state.write_u8(self.incoming);
+		state.write_u8(self.bits_left);
+		state.write_u64(self.bit_clock);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.sb = state.read_u8()?;
+		self.sc = state.read_u8()?;
+
+		self.incoming = state.read_u8()?;
+		self.bits_left = state.read_u8()?;
+		self.bit_clock = state.read_u64()?;
+
+		if self.bits_left > 8 {
+			return Err(StateError::InvalidValue("serial bit count"));
+		}
+
+		Ok(())
+	}
+
+}
\ No newline at end of file
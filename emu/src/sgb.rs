@@ -0,0 +1,660 @@
+use crate::ppu::GBColour;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+// where the Game Boy's screen sits inside the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: u8 = PACKET_SIZE as u8 * 8;
+
+// VRAM transfers send 4KiB by putting it on screen as 256 tiles, 20 to a row
+const TRANSFER_SIZE: usize = 0x1000;
+
+// palettes are picked for each 8x8 cell of the screen
+const CELLS_WIDTH: usize = SCREEN_WIDTH / 8;
+const CELLS_HEIGHT: usize = SCREEN_HEIGHT / 8;
+const CELLS: usize = CELLS_WIDTH * CELLS_HEIGHT;
+
+// ATTR_TRN sends 45 attribute files, each packing a palette for every cell into 2 bits
+const ATTR_FILE_SIZE: usize = CELLS / 4;
+const ATTR_FILES: usize = 45;
+
+const SYSTEM_PALETTES: usize = 512;
+
+// the border is 32x28 tiles of 4bpp SNES tile data, with 4 palettes of 16 colours
+const BORDER_TILES: usize = 256;
+const BORDER_WIDTH: usize = SGB_WIDTH / 8;
+const BORDER_HEIGHT: usize = SGB_HEIGHT / 8;
+
+// commands
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+// what the next frame is captured as
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Transfer {
+	None,
+	Palettes,
+	BorderTilesLow,
+	BorderTilesHigh,
+	BorderMap,
+	AttrFiles,
+}
+
+impl Transfer {
+	fn from_u8(from: u8) -> Option<Self> {
+		match from {
+			0 => Some(Self::None),
+			1 => Some(Self::Palettes),
+			2 => Some(Self::BorderTilesLow),
+			3 => Some(Self::BorderTilesHigh),
+			4 => Some(Self::BorderMap),
+			5 => Some(Self::AttrFiles),
+			_ => None,
+		}
+	}
+}
+
+// set by MASK_EN to hide the screen, usually while a VRAM transfer is on it
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mask {
+	None,
+	Freeze,	// keep showing the last frame
+	Black,
+	Colour0,
+}
+
+impl Mask {
+	fn from_u8(from: u8) -> Self {
+		match from & 0x3 {
+			0 => Self::None,
+			1 => Self::Freeze,
+			2 => Self::Black,
+			_ => Self::Colour0,
+		}
+	}
+}
+
+// the Super Game Boy. Games talk to it by sending 16-byte packets a bit at a time through P14 and P15 of JOYP, and
+// it colours the screen and draws a border around it. Only games with the SGB flag set in their header get this,
+// anything else is shown as it would be on a DMG
+pub struct Sgb {
+	// packet being received
+	lines: u8,			// P14 and P15 as last written
+	receiving: bool,
+	packet: [u8; PACKET_SIZE],
+	packet_bits: u8,
+
+	// commands can be up to 7 packets long
+	command: Vec<u8>,
+	packets_left: u8,
+
+	players: u8,	// controllers read in turn by MLT_REQ
+	player: u8,
+
+	palettes: [[u16; 4]; 4],
+	system_palettes: Vec<u8>,
+	attributes: [u8; CELLS],	// palette for each cell
+	attr_files: Vec<u8>,
+
+	border_tiles: Vec<u8>,
+	border_map: [u16; BORDER_WIDTH * BORDER_HEIGHT],
+	border_palettes: [[u16; 16]; 4],
+
+	mask: Mask,
+	transfer: Transfer,
+
+	screen: Vec<u8>,	// the last frame as shades
+}
+
+impl Sgb {
+
+	pub(crate) fn new() -> Self {
+
+		let shades = [GBColour::White, GBColour::LightGrey, GBColour::DarkGrey, GBColour::Black].map(GBColour::to_rgb555);
+
+		Self {
+			lines: 0x30,
+			receiving: false,
+			packet: [0; PACKET_SIZE],
+			packet_bits: 0,
+
+			command: Vec::new(),
+			packets_left: 0,
+
+			players: 1,
+			player: 0,
+
+			palettes: [shades; 4],
+			system_palettes: vec![0; SYSTEM_PALETTES * 8],
+			attributes: [0; CELLS],
+			attr_files: vec![0; ATTR_FILES * ATTR_FILE_SIZE],
+
+			// with no border loaded, everything around the screen is colour 0
+			border_tiles: vec![0; BORDER_TILES * 32],
+			border_map: [0; BORDER_WIDTH * BORDER_HEIGHT],
+			border_palettes: [[0; 16]; 4],
+
+			mask: Mask::None,
+			transfer: Transfer::None,
+
+			screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+		}
+
+	}
+
+	// the controller JOYP reads from. 0 is the one plugged into the emulator, the others have nothing pressed
+	pub fn player(&self) -> u8 {
+		self.player
+	}
+
+	// a low pulse on both lines starts a packet, then a pulse on P14 sends a 0 and a pulse on P15 sends a 1, with both
+	// lines going high between bits. Packets end with a 0 bit
+	pub fn write_joypad(&mut self, write: u8) {
+
+		let lines = write & 0x30;
+		let last = std::mem::replace(&mut self.lines, lines);
+
+		if lines == last {
+			return;
+		}
+
+		match lines {
+			0x00 => {
+				self.receiving = true;
+				self.packet = [0; PACKET_SIZE];
+				self.packet_bits = 0;
+			},
+			0x10 | 0x20 if self.receiving && last == 0x30 => {
+				let bit = lines == 0x10;
+
+				if self.packet_bits == PACKET_BITS {
+					self.receiving = false;
+
+					// a stop bit that isn't 0 throws the packet away
+					if !bit {
+						self.receive_packet();
+					}
+				} else {
+					let byte = self.packet_bits as usize / 8;
+
+					self.packet[byte] |= (bit as u8) << (self.packet_bits % 8);
+					self.packet_bits += 1;
+				}
+			},
+			// with multiplayer on, P15 going high moves on to the next controller
+			0x30 if !self.receiving && last & 0x20 == 0 => {
+				self.player = (self.player + 1) % self.players;
+			},
+			_ => {},
+		}
+
+	}
+
+	fn receive_packet(&mut self) {
+
+		if self.packets_left == 0 {
+			self.command.clear();
+			self.packets_left = (self.packet[0] & 0x7).max(1);
+		}
+
+		self.command.extend_from_slice(&self.packet);
+		self.packets_left -= 1;
+
+		if self.packets_left == 0 {
+			self.run_command();
+		}
+
+	}
+
+	fn run_command(&mut self) {
+
+		// the command is taken out while it runs, so the handlers can change everything else
+		let data = std::mem::take(&mut self.command);
+
+		match data[0] >> 3 {
+			PAL01 => self.set_palettes(0, 1, &data),
+			PAL23 => self.set_palettes(2, 3, &data),
+			PAL03 => self.set_palettes(0, 3, &data),
+			PAL12 => self.set_palettes(1, 2, &data),
+			ATTR_BLK => self.attr_blk(&data),
+			ATTR_LIN => self.attr_lin(&data),
+			ATTR_DIV => self.attr_div(&data),
+			ATTR_CHR => self.attr_chr(&data),
+			PAL_SET => self.pal_set(&data),
+			PAL_TRN => self.transfer = Transfer::Palettes,
+			MLT_REQ => {
+				self.players = match data[1] & 0x3 {
+					1 => 2,
+					3 => 4,
+					_ => 1,
+				};
+				self.player = 0;
+			},
+			CHR_TRN => self.transfer = if data[1] & 1 != 0 { Transfer::BorderTilesHigh } else { Transfer::BorderTilesLow },
+			PCT_TRN => self.transfer = Transfer::BorderMap,
+			ATTR_TRN => self.transfer = Transfer::AttrFiles,
+			ATTR_SET => {
+				self.apply_attr_file(data[1] & 0x3F);
+
+				if data[1] & 0x40 != 0 {
+					self.mask = Mask::None;
+				}
+			},
+			MASK_EN => self.mask = Mask::from_u8(data[1]),
+			// sound, SNES code and the rest aren't emulated
+			_ => {},
+		}
+
+		self.command = data;
+
+	}
+
+	// colour 0 is shared by every palette, so setting it for one sets it for all of them
+	fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+
+		let colour = |i: usize| u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) & 0x7FFF;
+
+		for palette in self.palettes.iter_mut() {
+			palette[0] = colour(0);
+		}
+
+		for i in 1..4 {
+			self.palettes[first][i] = colour(i);
+			self.palettes[second][i] = colour(i + 3);
+		}
+
+	}
+
+	fn set_cell(&mut self, x: usize, y: usize, palette: u8) {
+		if x < CELLS_WIDTH && y < CELLS_HEIGHT {
+			self.attributes[y * CELLS_WIDTH + x] = palette & 0x3;
+		}
+	}
+
+	// colours the inside, outside and edges of up to 18 rectangles
+	fn attr_blk(&mut self, data: &[u8]) {
+
+		for block in data[2..].chunks_exact(6).take(data[1] as usize) {
+
+			let (inside, border, outside) = (block[0] & 1 != 0, block[0] & 2 != 0, block[0] & 4 != 0);
+			let (inside_palette, mut border_palette, outside_palette) = (block[1] & 0x3, (block[1] >> 2) & 0x3, (block[1] >> 4) & 0x3);
+
+			// with only one of inside or outside set, the edges get its palette too
+			let border = match (inside, border, outside) {
+				(true, false, false) => {
+					border_palette = inside_palette;
+					true
+				},
+				(false, false, true) => {
+					border_palette = outside_palette;
+					true
+				},
+				_ => border,
+			};
+
+			let (x1, y1, x2, y2) = (block[2] as usize, block[3] as usize, block[4] as usize, block[5] as usize);
+
+			for y in 0..CELLS_HEIGHT {
+				for x in 0..CELLS_WIDTH {
+
+					let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+					let edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+
+					let palette = if edge {
+						border.then_some(border_palette)
+					} else if within {
+						inside.then_some(inside_palette)
+					} else {
+						outside.then_some(outside_palette)
+					};
+
+					if let Some(palette) = palette {
+						self.set_cell(x, y, palette);
+					}
+
+				}
+			}
+
+		}
+
+	}
+
+	// colours whole rows or columns of cells
+	fn attr_lin(&mut self, data: &[u8]) {
+
+		for &line in data[2..].iter().take(data[1] as usize) {
+
+			let (index, palette) = ((line & 0x1F) as usize, (line >> 5) & 0x3);
+
+			if line & 0x80 != 0 {
+				(0..CELLS_WIDTH).for_each(|x| self.set_cell(x, index, palette));
+			} else {
+				(0..CELLS_HEIGHT).for_each(|y| self.set_cell(index, y, palette));
+			}
+
+		}
+
+	}
+
+	// splits the screen in two at a row or column of cells, which gets a palette of its own
+	fn attr_div(&mut self, data: &[u8]) {
+
+		let (after, before, line) = (data[1] & 0x3, (data[1] >> 2) & 0x3, (data[1] >> 4) & 0x3);
+		let horizontal = data[1] & 0x40 != 0;
+		let split = data[2] as usize;
+
+		for y in 0..CELLS_HEIGHT {
+			for x in 0..CELLS_WIDTH {
+
+				let position = if horizontal { y } else { x };
+
+				let palette = match position.cmp(&split) {
+					std::cmp::Ordering::Less => before,
+					std::cmp::Ordering::Equal => line,
+					std::cmp::Ordering::Greater => after,
+				};
+
+				self.set_cell(x, y, palette);
+
+			}
+		}
+
+	}
+
+	// colours cells one at a time from a starting point, left to right or top to bottom
+	fn attr_chr(&mut self, data: &[u8]) {
+
+		let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+		let count = (u16::from_le_bytes([data[3], data[4]]) as usize).min(CELLS);
+		let vertical = data[5] & 1 != 0;
+
+		let palettes = data[6..].iter().flat_map(|byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 0x3));
+
+		for palette in palettes.take(count) {
+
+			if x >= CELLS_WIDTH || y >= CELLS_HEIGHT {
+				break;
+			}
+
+			self.set_cell(x, y, palette);
+
+			if vertical {
+				y += 1;
+
+				if y == CELLS_HEIGHT {
+					y = 0;
+					x += 1;
+				}
+			} else {
+				x += 1;
+
+				if x == CELLS_WIDTH {
+					x = 0;
+					y += 1;
+				}
+			}
+
+		}
+
+	}
+
+	// copies 4 of the system palettes sent by PAL_TRN, with colour 0 of the first used for all of them
+	fn pal_set(&mut self, data: &[u8]) {
+
+		for palette in 0..4 {
+
+			let index = (u16::from_le_bytes([data[1 + palette * 2], data[2 + palette * 2]]) as usize) % SYSTEM_PALETTES;
+
+			for colour in 0..4 {
+				let addr = index * 8 + colour * 2;
+
+				self.palettes[palette][colour] = u16::from_le_bytes([self.system_palettes[addr], self.system_palettes[addr + 1]]) & 0x7FFF;
+			}
+
+		}
+
+		for palette in 1..4 {
+			self.palettes[palette][0] = self.palettes[0][0];
+		}
+
+		if data[9] & 0x80 != 0 {
+			self.apply_attr_file(data[9] & 0x3F);
+		}
+
+		if data[9] & 0x40 != 0 {
+			self.mask = Mask::None;
+		}
+
+	}
+
+	fn apply_attr_file(&mut self, file: u8) {
+
+		let file = file as usize;
+
+		if file >= ATTR_FILES {
+			return;
+		}
+
+		let bytes = &self.attr_files[file * ATTR_FILE_SIZE..(file + 1) * ATTR_FILE_SIZE];
+
+		for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+			*attribute = (bytes[cell / 4] >> (6 - (cell % 4) * 2)) & 0x3;
+		}
+
+	}
+
+	// called with each frame the PPU finishes. The PPU runs as a DMG, so every pixel is one of the 4 shades
+	pub fn frame_done(&mut self, frame: &[u16]) {
+
+		let shades = [GBColour::White, GBColour::LightGrey, GBColour::DarkGrey, GBColour::Black].map(GBColour::to_rgb555);
+		let shade = |colour: &u16| shades.iter().position(|shade| shade == colour).unwrap_or(0) as u8;
+
+		if self.transfer != Transfer::None {
+			let data = Self::screen_data(&frame.iter().map(shade).collect::<Vec<u8>>());
+
+			self.finish_transfer(&data);
+		}
+
+		if self.mask != Mask::Freeze {
+			for (pixel, colour) in self.screen.iter_mut().zip(frame.iter()) {
+				*pixel = shade(colour);
+			}
+		}
+
+	}
+
+	// reads the screen back as the tiles it's made of, assuming BGP is 0xE4
+	fn screen_data(shades: &[u8]) -> Vec<u8> {
+
+		let mut data = vec![0; TRANSFER_SIZE];
+
+		for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+			for row in 0..8 {
+
+				let y = (tile / CELLS_WIDTH) * 8 + row;
+
+				for x in 0..8 {
+					let shade = shades[y * SCREEN_WIDTH + (tile % CELLS_WIDTH) * 8 + x];
+
+					bytes[row * 2] |= (shade & 1) << (7 - x);
+					bytes[row * 2 + 1] |= (shade >> 1) << (7 - x);
+				}
+
+			}
+		}
+
+		data
+
+	}
+
+	fn finish_transfer(&mut self, data: &[u8]) {
+
+		match self.transfer {
+			Transfer::Palettes => self.system_palettes.copy_from_slice(data),
+			Transfer::BorderTilesLow => self.border_tiles[..TRANSFER_SIZE].copy_from_slice(data),
+			Transfer::BorderTilesHigh => self.border_tiles[TRANSFER_SIZE..].copy_from_slice(data),
+			Transfer::BorderMap => {
+				for (entry, bytes) in self.border_map.iter_mut().zip(data.chunks_exact(2)) {
+					*entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+				}
+
+				let colours = data[0x800..0x880].chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7FFF);
+
+				for (i, colour) in colours.enumerate() {
+					self.border_palettes[i / 16][i % 16] = colour;
+				}
+			},
+			Transfer::AttrFiles => self.attr_files.copy_from_slice(&data[..ATTR_FILES * ATTR_FILE_SIZE]),
+			Transfer::None => {},
+		}
+
+		self.transfer = Transfer::None;
+
+	}
+
+	// 15-bit RGB like the PPU's frames, with the Game Boy's screen in the middle of the border
+	pub fn frame(&self) -> [u16; SGB_WIDTH * SGB_HEIGHT] {
+
+		let mut frame = [self.palettes[0][0]; SGB_WIDTH * SGB_HEIGHT];
+
+		for y in 0..SCREEN_HEIGHT {
+			for x in 0..SCREEN_WIDTH {
+
+				let shade = self.screen[y * SCREEN_WIDTH + x] as usize;
+				let palette = self.attributes[(y / 8) * CELLS_WIDTH + x / 8] as usize;
+
+				frame[(SCREEN_Y + y) * SGB_WIDTH + SCREEN_X + x] = match self.mask {
+					Mask::Black => 0,
+					Mask::Colour0 => self.palettes[0][0],
+					Mask::None | Mask::Freeze => self.palettes[palette][shade],
+				};
+
+			}
+		}
+
+		// the border goes over the screen, which shows through wherever it uses colour 0
+		for (i, entry) in self.border_map.iter().enumerate() {
+
+			let tile = &self.border_tiles[(*entry as usize & 0xFF) * 32..][..32];
+			let palette = &self.border_palettes[(*entry as usize >> 10) & 0x3];
+			let (flip_x, flip_y) = (entry & 0x4000 != 0, entry & 0x8000 != 0);
+
+			for row in 0..8 {
+				for col in 0..8 {
+
+					let tile_row = if flip_y { 7 - row } else { row };
+					let bit = if flip_x { col } else { 7 - col };
+
+					let colour = [tile[tile_row * 2], tile[tile_row * 2 + 1], tile[16 + tile_row * 2], tile[17 + tile_row * 2]]
+						.iter()
+						.enumerate()
+						.fold(0, |colour, (plane, byte)| colour | ((byte >> bit) & 1) << plane) as usize;
+
+					if colour != 0 {
+						frame[((i / BORDER_WIDTH) * 8 + row) * SGB_WIDTH + (i % BORDER_WIDTH) * 8 + col] = palette[colour];
+					}
+
+				}
+			}
+
+		}
+
+		frame
+
+	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.lines);
+		state.write_bool(self.receiving);
+		state.write_bytes(&self.packet);
+		state.write_u8(self.packet_bits);
+
+		state.write_vec(&self.command);
+		state.write_u8(self.packets_left);
+
+		state.write_u8(self.players);
+		state.write_u8(self.player);
+
+		self.palettes.iter().flatten().for_each(|colour| state.write_u16(*colour));
+		state.write_bytes(&self.system_palettes);
+		state.write_bytes(&self.attributes);
+		state.write_bytes(&self.attr_files);
+
+		state.write_bytes(&self.border_tiles);
+		self.border_map.iter().for_each(|entry| state.write_u16(*entry));
+		self.border_palettes.iter().flatten().for_each(|colour| state.write_u16(*colour));
+
+		state.write_u8(self.mask as u8);
+		state.write_u8(self.transfer as u8);
+
+		state.write_bytes(&self.screen);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.lines = state.read_u8()? & 0x30;
+		self.receiving = state.read_bool()?;
+		state.read_bytes(&mut self.packet)?;
+		self.packet_bits = state.read_u8()?.min(PACKET_BITS);
+
+		self.command = state.read_vec()?;
+		self.packets_left = state.read_u8()?;
+
+		if self.packets_left > 7 || self.command.len() + self.packets_left as usize * PACKET_SIZE > 7 * PACKET_SIZE {
+			return Err(StateError::InvalidValue("SGB command"));
+		}
+
+		self.players = state.read_u8()?;
+		self.player = state.read_u8()?;
+
+		if !matches!(self.players, 1 | 2 | 4) || self.player >= self.players {
+			return Err(StateError::InvalidValue("SGB player"));
+		}
+
+		for colour in self.palettes.iter_mut().flatten() {
+			*colour = state.read_u16()? & 0x7FFF;
+		}
+		state.read_bytes(&mut self.system_palettes)?;
+		state.read_bytes(&mut self.attributes)?;
+		self.attributes.iter_mut().for_each(|attribute| *attribute &= 0x3);
+		state.read_bytes(&mut self.attr_files)?;
+
+		state.read_bytes(&mut self.border_tiles)?;
+		for entry in self.border_map.iter_mut() {
+			*entry = state.read_u16()?;
+		}
+		for colour in self.border_palettes.iter_mut().flatten() {
+			*colour = state.read_u16()? & 0x7FFF;
+		}
+
+		self.mask = Mask::from_u8(state.read_u8()?);
+		self.transfer = Transfer::from_u8(state.read_u8()?).ok_or(StateError::InvalidValue("SGB transfer"))?;
+
+		state.read_bytes(&mut self.screen)?;
+		self.screen.iter_mut().for_each(|shade| *shade &= 0x3);
+
+		Ok(())
+	}
+
+}
\ No newline at end of file
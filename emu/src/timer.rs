@@ -1,123 +1,180 @@
-use crate::interrupt::{Interrupt, InterruptFlag};
-
-use std::cell::RefCell;
-use std::rc::Rc;
-
-struct Clock {
-	carry: u64,
-	period: u64
-}
-
-impl Clock {
-	
-	fn new(period: u64) -> Self {
-		Self {
-			carry: 0,
-			period: period
-		}
+use crate::interrupt::InterruptFlag;
+use crate::savestate::{StateReader, StateWriter, StateError};
+use crate::Model;
+
+// the internal counter DIV is the top half of, as each model's bootrom leaves it. The bootroms take different amounts
+// of time, so they all hand over at a different phase. The SGB ones also send the cart header to the SNES, and a 1 bit
+// takes an M-cycle less to send than a 0 bit, so their phase depends on the header too
+fn boot_counter(model: Model, rom: &[u8]) -> u16 {
+	match model {
+		Model::Dmg0 => 0x182C,
+		Model::Dmg | Model::Mgb => 0xABC8,
+		Model::Sgb | Model::Sgb2 => {
+			let ones: u32 = rom.get(0x104..0x150).unwrap_or(&[]).iter().map(|byte| byte.count_ones()).sum();
+
+			0xDC84u16.wrapping_sub(ones as u16 * 4)
+		},
+		Model::Cgb => 0x1EA0,
 	}
-
-	fn tick(&mut self, cycles: u64) -> u64 {
-
-		self.carry += cycles;
-		let inc = self.carry / self.period;
-		self.carry %= self.period;
-
-		inc
-
-	}
-
 }
 
+// the bit of the system counter that clocks TIMA for each TAC frequency
+const TIMA_BITS: [u16; 4] = [
+	9,	// 4096Hz, every 1024 cycles
+	3,	// 262144Hz, every 16 cycles
+	5,	// 65536Hz, every 64 cycles
+	7,	// 16384Hz, every 256 cycles
+];
+
+// DIV is the top byte of a 16-bit counter that goes up every T-state, and TIMA goes up whenever the counter bit picked by
+// TAC (ANDed with the enable bit) goes from 1 to 0. That means resetting DIV or changing TAC can bump TIMA too.
 pub struct Timer {
-	intf: Rc<RefCell<Interrupt>>,
+	interrupts: u8,		// IF bits raised since the last call to take_interrupts()
 
-	div: u8,
-	div_clock: Clock,
+	counter: u16,
 
 	tima: u8,
-	tima_clock: Clock,
-
 	tma: u8,
-	tac: u8
+	tac: u8,
+
+	// TIMA reads 0 for an M-cycle after it overflows, before it's reloaded from TMA and the interrupt is raised. Writing
+	// TIMA in that M-cycle cancels the reload, and during the M-cycle it's reloaded, TMA writes go through to TIMA too
+	overflowed: bool,
+	reloading: bool,
 }
 
 impl Timer {
 
-	pub fn new(intf: Rc<RefCell<Interrupt>>) -> Self {
+	pub fn new(model: Model, rom: &[u8]) -> Self {
 
 		Self {
-			intf: intf,
+			interrupts: 0,
 
-			div: 0,
-			div_clock: Clock::new(256),
+			counter: boot_counter(model, rom),
 
 			tima: 0,
-			tima_clock: Clock::new(1024),
-
 			tma: 0,
-			tac: 0
+			tac: 0,
+
+			overflowed: false,
+			reloading: false,
 		}
 
 	}
 
 	pub fn read(&self, addr: u16) -> u8 {
 		match addr {
-			0xFF04 => self.div,
+			0xFF04 => (self.counter >> 8) as u8,
 			0xFF05 => self.tima,
 			0xFF06 => self.tma,
-			0xFF07 => self.tac,
-			_ => panic!("invalid address")
+			0xFF07 => self.tac | 0xF8,
+			_ => 0xFF
 		}
 	}
 
 	pub fn write(&mut self, addr: u16, write: u8) {
+
+		let signal = self.signal();
+
 		match addr {
-			0xFF04 => self.div = 0,
-			0xFF05 => self.tima = write,
-			0xFF06 => self.tma = write,
-			0xFF07 => {
-				if (self.tac & 0x3) != (write & 0x3) {
-					self.tima_clock.carry = 0;
-					self.tima_clock.period = match write & 0x3 {
-						0b00 => 1024,	// 4096Hz, update every 1024 cycles 
-						0b01 => 16,		// 262144Hz, update every 16 cycles
-						0b10 => 64,		// 65536Hz, update every 64 cycles
-						0b11 => 256,	// 16384Hz, update every 256 cycles
-						_ => panic!("invalid speed")
-					};
-
-					self.tima = self.tma;
+			0xFF04 => self.counter = 0,
+			// TIMA ignores writes while it's being reloaded
+			0xFF05 if self.reloading => {},
+			0xFF05 => {
+				self.tima = write;
+				self.overflowed = false;
+			},
+			0xFF06 => {
+				self.tma = write;
+
+				if self.reloading {
+					self.tima = write;
 				}
+			},
+			0xFF07 => self.tac = write & 0x7,
+			_ => {}
+		}
 
-				self.tac = write;
-			}
-			_ => panic!("invalid address")
+		self.detect_edge(signal);
+
+		// writes land partway through the M-cycle, so an overflow they cause is already reloaded by the end of it
+		if self.overflowed {
+			self.reload();
 		}
+
 	}
 
+	// cycles are CPU T-states, so the timer speeds up in double speed mode
 	pub fn tick(&mut self, cycles: u64) {
 
-		self.div = self.div.wrapping_add(self.div_clock.tick(cycles) as u8);
+		for _ in 0..cycles / 4 {
 
-		// if TIMA is enabled
-		if (self.tac & 0x4) != 0 {
+			self.reloading = false;
 
-			let inc = self.tima_clock.tick(cycles);
+			if self.overflowed {
+				self.reload();
+			}
 
-			for _ in 0..inc {
+			let signal = self.signal();
+			self.counter = self.counter.wrapping_add(4);
+			self.detect_edge(signal);
 
-				self.tima = self.tima.wrapping_add(1);
+		}
 
-				if self.tima == 0 {
-					self.tima = self.tma;
+	}
 
-					// raise interrupt
-					self.intf.borrow_mut().raise(InterruptFlag::Timer);
-				}
-			}
+	fn reload(&mut self) {
+		self.overflowed = false;
+		self.reloading = true;
+
+		self.tima = self.tma;
+		self.interrupts |= InterruptFlag::Timer as u8;
+	}
+
+	pub fn take_interrupts(&mut self) -> u8 {
+		std::mem::take(&mut self.interrupts)
+	}
+
+	// the input to TIMA's falling edge detector
+	fn signal(&self) -> bool {
+		self.tac & 0x4 != 0 && self.counter & (1 << TIMA_BITS[self.tac as usize & 0x3]) != 0
+	}
+
+	fn detect_edge(&mut self, old_signal: bool) {
+
+		if !old_signal || self.signal() {
+			return;
 		}
 
+		let (tima, overflowed) = self.tima.overflowing_add(1);
+
+		self.tima = tima;
+		self.overflowed |= overflowed;
+
+	}
+
+	pub fn save_state(&self, state: &mut StateWriter) {
+		state.write_u16(self.counter);
+
+		state.write_u8(self.tima);
+		state.write_u8(self.tma);
+		state.write_u8(self.tac);
+
+		state.write_bool(self.overflowed);
+		state.write_bool(self.reloading);
+	}
+
+	pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.counter = state.read_u16()?;
+
+		self.tima = state.read_u8()?;
+		self.tma = state.read_u8()?;
+		self.tac = state.read_u8()? & 0x7;
+
+		self.overflowed = state.read_bool()?;
+		self.reloading = state.read_bool()?;
+
+		Ok(())
 	}
 
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::fs;
+
+use emu::cart::{CartHeader, CartError, HeaderWarning, CgbSupport, Licensee, Region};
+use emu::{Error, Gameboy};
+
+#[test]
+fn parses_dmg_acid2_header() {
+
+	let rom = fs::read("../roms/dmg-acid2.gb").expect("TEST ERROR: unable to read dmg-acid2.gb");
+	let header = CartHeader::parse(&rom).unwrap();
+
+	assert_eq!(header.title, "DMG-ACID2");
+	assert_eq!(header.manufacturer, None);
+	assert_eq!(header.cgb_support, CgbSupport::None);
+	assert!(!header.sgb_support);
+	assert_eq!(header.cart_type_name(), "ROM ONLY");
+	assert_eq!(header.rom_banks(), 2);
+	assert_eq!(header.ram_size, 0);
+
+	assert!(header.logo_valid);
+	assert!(header.header_checksum_valid);
+	assert!(header.global_checksum_valid);
+
+}
+
+#[test]
+fn bad_checksum_loads_with_a_warning() {
+
+	let mut rom = fs::read("../roms/dmg-acid2.gb").expect("TEST ERROR: unable to read dmg-acid2.gb");
+	rom[0x14D] ^= 0xFF;
+
+	let gb = Gameboy::new(rom, Box::new(|_| {})).unwrap();
+
+	// the header checksum is part of the ROM checksum too
+	assert_eq!(gb.header().warnings(), vec![HeaderWarning::BadHeaderChecksum, HeaderWarning::BadGlobalChecksum]);
+
+	let rom = fs::read("../roms/dmg-acid2.gb").unwrap();
+	assert_eq!(Gameboy::new(rom, Box::new(|_| {})).unwrap().header().warnings(), vec![]);
+
+}
+
+#[test]
+fn parses_cgb_header_fields() {
+
+	let mut rom = vec![0; 0x8000];
+	rom[0x134..0x13F].copy_from_slice(b"COLOURGAME\0");
+	rom[0x13F..0x143].copy_from_slice(b"ACGE");
+	rom[0x143] = 0xC0;
+	rom[0x144..0x146].copy_from_slice(b"01");
+	rom[0x146] = 0x03;
+	rom[0x147] = 0x1B;
+	rom[0x148] = 0x02;
+	rom[0x149] = 0x03;
+	rom[0x14A] = 0x01;
+	rom[0x14B] = 0x33;
+	rom[0x14C] = 0x02;
+
+	let header = CartHeader::parse(&rom).unwrap();
+
+	assert_eq!(header.title, "COLOURGAME");
+	assert_eq!(header.manufacturer.as_deref(), Some("ACGE"));
+	assert_eq!(header.cgb_support, CgbSupport::Exclusive);
+	assert!(header.sgb_support);
+	assert_eq!(header.licensee, Licensee::New("01".to_string()));
+	assert_eq!(header.cart_type_name(), "MBC5+RAM+BATTERY");
+	assert_eq!(header.rom_size, 128 * 1024);
+	assert_eq!(header.ram_size, 32 * 1024);
+	assert_eq!(header.region, Region::Overseas);
+	assert_eq!(header.version, 2);
+
+	// nothing here was filled in properly
+	assert!(!header.logo_valid);
+	assert!(!header.header_checksum_valid);
+
+}
+
+#[test]
+fn bad_roms_are_rejected() {
+
+	let load = |rom: Vec<u8>| match Gameboy::new(rom, Box::new(|_| {})) {
+		Err(Error::Cart(e)) => Some(e),
+		_ => None,
+	};
+
+	assert_eq!(load(Vec::new()), Some(CartError::TooShort(0)));
+	assert_eq!(load(vec![0; 0x100]), Some(CartError::TooShort(0x100)));
+
+	let mut rom = vec![0; 0x8000];
+	rom[0x147] = 0x42;
+	assert_eq!(load(rom), Some(CartError::UnsupportedType(0x42)));
+
+	let mut rom = vec![0; 0x8000];
+	rom[0x148] = 0x20;
+	assert_eq!(load(rom), Some(CartError::InvalidRomSize(0x20)));
+
+}
+
+#[test]
+fn truncated_roms_are_padded() {
+
+	// an MBC1 cart that claims to be 128KiB, but was cut off right after the header
+	let mut rom = vec![0; 0x150];
+	rom[0x147] = 0x01;
+	rom[0x148] = 0x02;
+
+	let mut gb = Gameboy::new(rom, Box::new(|_| {})).unwrap();
+
+	gb.bus_mut().write_byte(0x2000, 0x07);
+	assert_eq!(gb.bus().read_byte(0x4000), 0xFF);
+	assert_eq!(gb.bus().read_byte(0x7FFF), 0xFF);
+
+}
+
+// a 1MiB MBC1 cart where the first byte of every bank is its bank number
+fn mbc1_1mib(logos_at: &[usize]) -> Gameboy {
+	let mut rom = vec![0; 0x100000];
+
+	for bank in 0..64 {
+		rom[bank * 0x4000] = bank as u8;
+	}
+
+	let logo = fs::read("../roms/dmg-acid2.gb").expect("TEST ERROR: unable to read dmg-acid2.gb")[0x104..0x134].to_vec();
+
+	for game in logos_at {
+		rom[game * 0x40000 + 0x104..game * 0x40000 + 0x134].copy_from_slice(&logo);
+	}
+
+	rom[0x147] = 0x01;
+	rom[0x148] = 0x05;
+
+	Gameboy::new(rom, Box::new(|_| {})).unwrap()
+}
+
+#[test]
+fn mbc1_multicarts_shift_the_upper_bank_bits() {
+
+	let mut gb = mbc1_1mib(&[0, 1, 2, 3]);
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0x4000, 0x01);
+	bus.write_byte(0x2000, 0x02);
+	assert_eq!(bus.read_byte(0x4000), 0x12);
+
+	// bank 0x10 isn't treated as bank 0, but only its low 4 bits are used
+	bus.write_byte(0x2000, 0x10);
+	assert_eq!(bus.read_byte(0x4000), 0x10);
+
+	// in mode 1 the upper bits pick the game mapped at 0x0000
+	bus.write_byte(0x4000, 0x03);
+	bus.write_byte(0x6000, 0x01);
+	assert_eq!(bus.read_byte(0x0000), 0x30);
+
+	// without the extra logos it's a normal MBC1
+	let mut gb = mbc1_1mib(&[0]);
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0x4000, 0x01);
+	bus.write_byte(0x2000, 0x02);
+	assert_eq!(bus.read_byte(0x4000), 0x22);
+
+}
@@ -0,0 +1,227 @@
+use emu::{Gameboy, Mode, Model};
+
+// builds a 32KiB ROM-only cart marked as a CGB game, with `code` at the entry point
+fn cgb_rom(code: &[u8]) -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x143] = 0x80;
+	rom[0x100..0x100 + code.len()].copy_from_slice(code);
+
+	rom
+}
+
+fn cgb() -> Gameboy {
+	Gameboy::new(cgb_rom(&[0x18, 0xFE]), Box::new(|_| {})).unwrap()
+}
+
+#[test]
+fn cgb_mode_is_detected_from_header() {
+
+	let gb = cgb();
+
+	assert_eq!(gb.model(), Model::Cgb);
+	assert_eq!(gb.mode(), Mode::Cgb);
+	// games look for A = 0x11 to detect a CGB
+	assert_eq!(gb.cpu.registers.get_16bit_reg(emu::cpu::registers::Register16Bit::AF) >> 8, 0x11);
+
+	// the override runs the same cart on a DMG, where the CGB registers don't exist
+	let mut dmg = Gameboy::with_model(cgb_rom(&[0x18, 0xFE]), Box::new(|_| {}), Model::Dmg).unwrap();
+	assert_eq!(dmg.mode(), Mode::Dmg);
+	let bus = dmg.bus_mut();
+
+	bus.write_byte(0xFF70, 0x03);
+	bus.write_byte(0xFF4F, 0x01);
+
+	assert_eq!(bus.read_byte(0xFF70), 0xFF);
+	assert_eq!(bus.read_byte(0xFF4F), 0xFF);
+	assert_eq!(bus.read_byte(0xFF4D), 0xFF);
+
+}
+
+#[test]
+fn palette_index_auto_increments() {
+
+	let mut gb = cgb();
+	let bus = gb.bus_mut();
+
+	// BG palette 1, colour 0, with auto-increment
+	bus.write_byte(0xFF68, 0x88);
+	for byte in [0x1F, 0x00, 0xE0, 0x03] {
+		bus.write_byte(0xFF69, byte);
+	}
+
+	assert_eq!(bus.read_byte(0xFF68), 0x80 | 0x40 | 0x0C);
+
+	// without auto-increment the index stays put
+	bus.write_byte(0xFF68, 0x0A);
+	assert_eq!(bus.read_byte(0xFF69), 0xE0);
+	bus.write_byte(0xFF69, 0x55);
+	assert_eq!(bus.read_byte(0xFF69), 0x55);
+	assert_eq!(bus.read_byte(0xFF68), 0x40 | 0x0A);
+
+	// OBJ palettes are separate
+	bus.write_byte(0xFF6A, 0x88);
+	assert_ne!(bus.read_byte(0xFF6B), 0x1F);
+
+}
+
+#[test]
+fn vram_and_wram_are_banked() {
+
+	let mut gb = cgb();
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0x8000, 0x11);
+	bus.write_byte(0xFF4F, 0x01);
+	bus.write_byte(0x8000, 0x22);
+
+	assert_eq!(bus.read_byte(0xFF4F), 0xFF);
+	assert_eq!(bus.read_byte(0x8000), 0x22);
+	bus.write_byte(0xFF4F, 0x00);
+	assert_eq!(bus.read_byte(0x8000), 0x11);
+
+	// bank 0 is always at 0xC000, and selecting bank 0 at 0xD000 selects bank 1
+	for bank in 1..=7 {
+		bus.write_byte(0xFF70, bank);
+		bus.write_byte(0xD000, bank * 0x10);
+	}
+
+	bus.write_byte(0xFF70, 0x00);
+	assert_eq!(bus.read_byte(0xFF70), 0xF9);
+	assert_eq!(bus.read_byte(0xD000), 0x10);
+
+	bus.write_byte(0xFF70, 0x05);
+	assert_eq!(bus.read_byte(0xD000), 0x50);
+
+}
+
+#[test]
+fn general_purpose_dma_copies_to_vram() {
+
+	let mut gb = cgb();
+	let bus = gb.bus_mut();
+
+	for i in 0..0x20 {
+		bus.write_byte(0xC100 + i, i as u8 + 1);
+	}
+
+	bus.write_byte(0xFF4F, 0x01);
+
+	bus.write_byte(0xFF51, 0xC1);
+	bus.write_byte(0xFF52, 0x00);
+	bus.write_byte(0xFF53, 0x02);
+	bus.write_byte(0xFF54, 0x40);
+	// two blocks of 16 bytes
+	bus.write_byte(0xFF55, 0x01);
+
+	assert_eq!(bus.read_byte(0xFF55), 0xFF);
+
+	for i in 0..0x20 {
+		assert_eq!(bus.read_byte(0x8240 + i), i as u8 + 1);
+	}
+
+	bus.write_byte(0xFF4F, 0x00);
+	assert_eq!(bus.read_byte(0x8240), 0x00);
+
+}
+
+// runs `code` up to the LDH (0x55), A that starts a two block general purpose DMA, and returns the T-states it took
+fn general_purpose_dma_cycles(code: &[u8]) -> u64 {
+
+	let mut gb = Gameboy::new(cgb_rom(code), Box::new(|_| {})).unwrap();
+
+	{
+		let bus = gb.bus_mut();
+
+		bus.write_byte(0xFF51, 0xC0);
+		bus.write_byte(0xFF52, 0x00);
+		bus.write_byte(0xFF53, 0x00);
+		bus.write_byte(0xFF54, 0x00);
+	}
+
+	let ldh = 0x100 + code.len() as u16 - 4;
+
+	while gb.cpu.pc != ldh {
+		gb.tick();
+	}
+
+	let start = gb.cycles;
+	gb.tick();
+
+	assert_eq!(gb.bus().read_byte(0xFF55), 0xFF);
+
+	gb.cycles - start
+
+}
+
+#[test]
+fn general_purpose_dma_halts_the_cpu() {
+
+	// LD A, 1; LDH (0x55), A; JR -2
+	// the LDH takes its own 3 M-cycles, then 8 more for each block
+	assert_eq!(general_purpose_dma_cycles(&[0x3E, 0x01, 0xE0, 0x55, 0x18, 0xFE]), 12 + 2 * 32);
+
+	// LD A, 1; LDH (0x4D), A; STOP; LD A, 1; LDH (0x55), A; JR -2
+	// in double speed a block takes 16 M-cycles, so it's the same length of time
+	assert_eq!(general_purpose_dma_cycles(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x3E, 0x01, 0xE0, 0x55, 0x18, 0xFE]), 12 + 2 * 64);
+
+}
+
+#[test]
+fn hblank_dma_copies_a_block_per_line() {
+
+	let mut gb = cgb();
+
+	{
+		let bus = gb.bus_mut();
+
+		for i in 0..0x30 {
+			bus.write_byte(0xC000 + i, 0xA0 + i as u8);
+		}
+
+		bus.write_byte(0xFF51, 0xC0);
+		bus.write_byte(0xFF52, 0x00);
+		bus.write_byte(0xFF53, 0x00);
+		bus.write_byte(0xFF54, 0x00);
+		bus.write_byte(0xFF55, 0x82);
+
+		assert_eq!(bus.read_byte(0xFF55), 0x02);
+	}
+
+	// after the boot ROM the PPU starts on line 0, so the first three lines each copy a block
+	while gb.cycles < 456 * 4 {
+		gb.tick();
+	}
+
+	let bus = gb.bus();
+
+	assert_eq!(bus.read_byte(0xFF55), 0xFF);
+	assert_eq!(bus.read_byte(0x8000), 0xA0);
+	assert_eq!(bus.read_byte(0x802F), 0xCF);
+
+}
+
+#[test]
+fn stop_switches_speed() {
+
+	// LD A, 1; LDH (0x4D), A; STOP; JR -2
+	let mut gb = Gameboy::new(cgb_rom(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x18, 0xFE]), Box::new(|_| {})).unwrap();
+
+	assert_eq!(gb.bus().read_byte(0xFF4D), 0x7E);
+
+	while gb.cpu.pc < 0x106 {
+		gb.tick();
+	}
+
+	assert_eq!(gb.bus().read_byte(0xFF4D), 0xFE);
+
+	// the PPU keeps its speed, so a line now takes twice as many CPU cycles
+	let start_cycles = gb.cycles;
+	let start_ly = gb.bus().read_byte(0xFF44);
+
+	while gb.cycles - start_cycles < 456 * 2 * 10 {
+		gb.tick();
+	}
+
+	assert_eq!(gb.bus().read_byte(0xFF44).wrapping_sub(start_ly), 10);
+
+}
\ No newline at end of file
@@ -0,0 +1,25 @@
+// fixtures shared by the integration tests. Each test file is its own crate and only uses some of them
+#![allow(dead_code)]
+
+use emu::{Gameboy, FRAME_CYCLES};
+
+// a 32KiB ROM-only cart that counts up in WRAM at 0xC000 forever: ld hl, 0xC000; loop: inc (hl); jr loop
+pub fn loop_rom() -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x106].copy_from_slice(&[0x21, 0x00, 0xC0, 0x34, 0x18, 0xFD]);
+
+	rom
+}
+
+pub fn gameboy(rom: Vec<u8>) -> Gameboy {
+	Gameboy::new(rom, Box::new(|_| {})).unwrap()
+}
+
+// ticks through whole frames' worth of cycles, whether or not the LCD is on
+pub fn run_frames(gb: &mut Gameboy, frames: u64) {
+	let end = gb.cycles + frames * FRAME_CYCLES;
+
+	while gb.cycles < end {
+		gb.tick();
+	}
+}
\ No newline at end of file
@@ -54,7 +54,9 @@ struct Test {
 #[test]
 fn sm83_test_data() {
 
-	let mut gb = Gameboy::new(Vec::new(), Box::new(|_| {}));
+	let mut gb = Gameboy::new(vec![0; 0x8000], Box::new(|_| {})).unwrap();
+	gb.bus_mut().set_flat_memory(true);
+	gb.bus_mut().clear_test_mem();
 
 	for (i, entry_res) in fs::read_dir("../tests/sm83-test-data").expect("TEST ERROR: unable to read test data").enumerate() {
 
@@ -73,7 +75,7 @@ fn sm83_test_data() {
 			println!("--------Test: {} ({})", test.name, i);
 
 			for (addr, data) in test.initial_state.ram.iter() {
-				gb.bus.borrow_mut().write_byte(*addr, *data);
+				gb.bus_mut().write_byte(*addr, *data);
 			}
 
 			gb.cpu.pc = test.initial_state.pc;
@@ -91,7 +93,7 @@ fn sm83_test_data() {
 			gb.cpu.ime = test.initial_state.ime != 0;
 			gb.cpu.ei = 0;
 			gb.cpu.halted = false;
-			//gb.bus.borrow_mut().write_register(MemRegister::IE, test.initial_state.ie);
+			//gb.bus_mut().write_register(MemRegister::IE, test.initial_state.ie);
 
 			/*
 			for i in 0..test.cycles.len() {
@@ -162,7 +164,7 @@ fn sm83_test_data() {
 				if gb.cpu.registers.get_flag(Flag::H) { "H" } else { "_" },
 				if gb.cpu.registers.get_flag(Flag::C) { "C" } else { "_" },
 				test.final_state.f,
-				gb.cpu.get_8bit_reg(Register8Bit::F),
+				gb.cpu.registers.get_8bit_reg(Register8Bit::F),
 			);
 			assert_eq!(gb.cpu.registers.get_8bit_reg(Register8Bit::H), test.final_state.h, "H comparison failed (initial: 0x{:x} final: 0x{:x} actual: 0x{:x})",
 				test.initial_state.h,
@@ -190,14 +192,14 @@ fn sm83_test_data() {
 			);
 
 			for (addr, data) in test.final_state.ram.iter() {
-				assert_eq!(gb.bus.borrow().read_byte(*addr), *data, "[0x{:x}] RAM comparison failed (final: 0x{:x} actual: 0x{:x})", 
+				assert_eq!(gb.bus().read_byte(*addr), *data, "[0x{:x}] RAM comparison failed (final: 0x{:x} actual: 0x{:x})", 
 					*addr,
 					*data, 
-					gb.bus.borrow().read_byte(*addr)
+					gb.bus().read_byte(*addr)
 				);
 			}
 
-			gb.bus.borrow_mut().clear_test_mem();
+			gb.bus_mut().clear_test_mem();
 
 		}
 
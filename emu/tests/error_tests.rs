@@ -0,0 +1,60 @@
+use emu::{Error, Gameboy, Model};
+
+fn rom_with_type(cart_type: u8, ram_size: u8) -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x147] = cart_type;
+	rom[0x149] = ram_size;
+
+	rom
+}
+
+#[test]
+fn bootroms_must_match_the_hardware() {
+
+	let mut gb = Gameboy::with_model(rom_with_type(0x00, 0), Box::new(|_| {}), Model::Dmg).unwrap();
+
+	assert_eq!(gb.load_bootrom(vec![0; 0x900], true), Err(Error::InvalidBootrom { expected: 0x100, actual: 0x900 }));
+	assert_eq!(gb.cpu.pc, 0x100, "a rejected bootrom shouldn't start");
+	assert_eq!(gb.load_bootrom(vec![0; 0x100], true), Ok(()));
+
+	let mut gb = Gameboy::with_model(rom_with_type(0x00, 0), Box::new(|_| {}), Model::Cgb).unwrap();
+	assert_eq!(gb.load_bootrom(vec![0; 0x100], true), Err(Error::InvalidBootrom { expected: 0x900, actual: 0x100 }));
+
+}
+
+#[test]
+fn saves_must_fit_the_cart() {
+
+	// MBC1+RAM+BATTERY with 8KiB of RAM
+	let mut gb = Gameboy::new(rom_with_type(0x03, 0x02), Box::new(|_| {})).unwrap();
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0x0000, 0x0A);
+	bus.write_byte(0xA000, 0x42);
+
+	assert_eq!(bus.cart.load_sram(vec![0; 100]), Err(Error::InvalidSram { expected: 0x2000, actual: 100 }));
+	assert_eq!(bus.read_byte(0xA000), 0x42, "a rejected save shouldn't touch RAM");
+
+	assert_eq!(bus.cart.load_sram(vec![0x24; 0x2000]), Ok(()));
+	assert_eq!(bus.read_byte(0xA000), 0x24);
+
+	// carts without RAM don't have anything to dump
+	let gb = Gameboy::new(rom_with_type(0x01, 0), Box::new(|_| {})).unwrap();
+	assert!(gb.bus().cart.dump_sram().is_empty());
+
+}
+
+#[test]
+fn unmapped_reads_are_open_bus() {
+
+	let mut gb = Gameboy::new(rom_with_type(0x00, 0), Box::new(|_| {})).unwrap();
+	let bus = gb.bus_mut();
+
+	assert_eq!(bus.ppu.read(0x1234), 0xFF);
+	assert_eq!(bus.timer.read(0xFF03), 0xFF);
+	assert_eq!(bus.serial.read(0xFF00), 0xFF);
+
+	bus.ppu.write(0x1234, 0x00);
+	bus.timer.write(0xFF03, 0x00);
+
+}
\ No newline at end of file
@@ -0,0 +1,154 @@
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use emu::{cpu::registers::Register8Bit, link::tcp::{TcpLink, DEFAULT_DELAY, DEFAULT_WINDOW}, Gameboy};
+
+// sends C over and over with the internal clock, counting up after every transfer
+const COUNTER_MASTER: [u8; 26] = [
+	0x0E, 0x00,			// LD C, 0
+	0x79,				// LD A, C
+	0xE0, 0x01,			// LDH (0x01), A
+	0x3E, 0x81,			// LD A, 0x81
+	0xE0, 0x02,			// LDH (0x02), A
+	0xF0, 0x02,			// LDH A, (0x02)
+	0xCB, 0x7F,			// BIT 7, A
+	0x20, 0xFA,			// JR NZ, -6
+	0xF0, 0x01,			// LDH A, (0x01)
+	0x57,				// LD D, A
+	0x0C,				// INC C
+	0x06, 0x00,			// LD B, 0
+	0x05,				// DEC B
+	0x20, 0xFD,			// JR NZ, -3
+	0x18, 0xE8,			// JR -24
+];
+
+// waits on the external clock and replies with whatever it received plus one
+const ECHO_SLAVE: [u8; 17] = [
+	0x3E, 0x80,			// LD A, 0x80
+	0xE0, 0x02,			// LDH (0x02), A
+	0xF0, 0x02,			// LDH A, (0x02)
+	0xCB, 0x7F,			// BIT 7, A
+	0x20, 0xFA,			// JR NZ, -6
+	0xF0, 0x01,			// LDH A, (0x01)
+	0x3C,				// INC A
+	0xE0, 0x01,			// LDH (0x01), A
+	0x18, 0xEF,			// JR -17
+];
+
+const SESSION_CYCLES: u64 = 2_000_000;
+
+// builds a 32KiB ROM-only cart with `code` at `addr`. Everything before it is NOPs, which delays when the code runs.
+fn build_rom(addr: usize, code: &[u8]) -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[addr..addr + code.len()].copy_from_slice(code);
+
+	rom
+}
+
+// LD A, sb; LDH (0x01), A; LD A, sc; LDH (0x02), A; JR -2
+fn transfer_code(sb: u8, sc: u8) -> Vec<u8> {
+	vec![0x3E, sb, 0xE0, 0x01, 0x3E, sc, 0xE0, 0x02, 0x18, 0xFE]
+}
+
+#[test]
+fn link_cable_exchanges_bytes() {
+
+	// the slave starts listening first, and the master starts its transfer a while later
+	let mut master = Gameboy::new(build_rom(0x400, &transfer_code(0x42, 0x81)), Box::new(|_| {})).unwrap();
+	let mut slave = Gameboy::new(build_rom(0x100, &transfer_code(0x24, 0x80)), Box::new(|_| {})).unwrap();
+
+	master.link_with(&mut slave);
+
+	while master.cycles < 20_000 {
+		master.tick_linked(&mut slave);
+	}
+
+	assert!(master.cycles.abs_diff(slave.cycles) <= 24, "linked gameboys drifted apart");
+
+	for (gb, expected) in [(&master, 0x24), (&slave, 0x42)] {
+		let bus = gb.bus();
+
+		assert_eq!(bus.read_byte(0xFF01), expected);
+		assert_eq!(bus.read_byte(0xFF02) & 0x80, 0, "transfer didn't finish");
+		assert_ne!(bus.read_byte(0xFF0F) & 0x8, 0, "serial interrupt wasn't raised");
+	}
+
+}
+
+#[test]
+fn unlinked_transfer_reads_ones() {
+
+	let mut gb = Gameboy::new(build_rom(0x100, &transfer_code(0x42, 0x81)), Box::new(|_| {})).unwrap();
+
+	while gb.cycles < 10_000 {
+		gb.tick();
+	}
+
+	assert_eq!(gb.bus().read_byte(0xFF01), 0xFF);
+	assert_eq!(gb.bus().read_byte(0xFF02) & 0x80, 0);
+
+}
+// runs a master and a slave linked over loopback, returning their final states. The master either ticks or runs the whole
+// session in one go
+fn run_tcp_session(slow_client: bool, host_runs: bool) -> (Vec<u8>, Vec<u8>) {
+
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+
+	let client = thread::spawn(move || {
+		let mut gb = Gameboy::new(build_rom(0x100, &ECHO_SLAVE), Box::new(|_| {})).unwrap();
+		let mut link = TcpLink::connect(addr).expect("unable to connect");
+		link.attach(&mut gb);
+
+		let mut ticks: u64 = 0;
+
+		while gb.cycles < SESSION_CYCLES {
+			link.tick(&mut gb).expect("link error");
+
+			// stall now and then, like a laggy connection would
+			ticks += 1;
+			if slow_client && ticks.is_multiple_of(25_000) {
+				thread::sleep(Duration::from_millis(2));
+			}
+		}
+
+		assert!(gb.bus().read_byte(0xFF01) > 1, "the slave never received anything");
+
+		gb.save_state()
+	});
+
+	let mut gb = Gameboy::new(build_rom(0x100, &COUNTER_MASTER), Box::new(|_| {})).unwrap();
+	let mut link = TcpLink::host(&listener, DEFAULT_WINDOW, DEFAULT_DELAY).expect("unable to host");
+	link.attach(&mut gb);
+
+	if host_runs {
+		link.run_cycles(&mut gb, SESSION_CYCLES).expect("link error");
+	}
+
+	while gb.cycles < SESSION_CYCLES {
+		link.tick(&mut gb).expect("link error");
+	}
+
+	// transfers started while the slave's reply is still in flight read 0xFF, so only some of them get through
+	assert!(gb.cpu.registers.get_8bit_reg(Register8Bit::C) > 100, "the master stalled");
+
+	let host_state = gb.save_state();
+	drop(link);
+
+	(host_state, client.join().unwrap())
+
+}
+
+#[test]
+fn tcp_link_is_deterministic() {
+
+	// latency must never change what either gameboy sees
+	assert!(run_tcp_session(false, false) == run_tcp_session(true, false), "tcp link sessions diverged");
+
+}
+
+#[test]
+fn running_a_tcp_link_matches_ticking_it() {
+	assert!(run_tcp_session(false, false) == run_tcp_session(false, true), "running the link diverged from ticking it");
+}
\ No newline at end of file
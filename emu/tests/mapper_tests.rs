@@ -0,0 +1,450 @@
+use emu::cart::{create_cart, MBC, CAMERA_WIDTH, CAMERA_HEIGHT};
+
+// a cart where the first byte of every 8KiB is its index, so 16KiB bank n starts with 2n
+fn rom(cart_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+	let mut rom = vec![0; 0x8000 << rom_size];
+
+	for (i, chunk) in rom.chunks_mut(0x2000).enumerate() {
+		chunk[0] = i as u8;
+	}
+
+	rom[0x147] = cart_type;
+	rom[0x148] = rom_size;
+	rom[0x149] = ram_size;
+
+	rom
+}
+
+fn cart(cart_type: u8, rom_size: u8, ram_size: u8) -> Box<dyn MBC> {
+	create_cart(rom(cart_type, rom_size, ram_size)).unwrap()
+}
+
+fn assert_sram_round_trips(cart: &mut Box<dyn MBC>, len: usize) {
+	let sram = cart.dump_sram();
+
+	assert_eq!(sram.len(), len);
+	assert!(cart.load_sram(sram).is_ok());
+	assert!(cart.load_sram(vec![0; 3]).is_err());
+}
+
+#[test]
+fn rom_ram_carts_map_ram_without_an_mbc() {
+
+	let mut battery = cart(0x09, 0x00, 0x02);
+	assert!(battery.is_battery_backed());
+
+	// no enable needed, and no banking
+	battery.write(0x2000, 0x01);
+	assert_eq!(battery.read(0x4000), 2);
+
+	battery.write(0xBFFF, 0x42);
+	assert_eq!(battery.read(0xBFFF), 0x42);
+	assert_eq!(battery.dump_sram()[0x1FFF], 0x42);
+
+	assert_sram_round_trips(&mut battery, 0x2000);
+
+	// without a battery there's nothing to save, but the RAM is still there
+	let mut no_battery = cart(0x08, 0x00, 0x00);
+	assert!(!no_battery.is_battery_backed());
+
+	no_battery.write(0xA000, 0x42);
+	assert_eq!(no_battery.read(0xA000), 0x42);
+
+	// plain ROM carts have nothing mapped there
+	let mut rom_only = cart(0x00, 0x00, 0x00);
+	rom_only.write(0xA000, 0x42);
+	assert_eq!(rom_only.read(0xA000), 0xFF);
+	assert!(rom_only.dump_sram().is_empty());
+
+}
+
+#[test]
+fn mbc5_rumble_bit_drives_the_motor() {
+
+	let mut rumble = cart(0x1E, 0x02, 0x03);
+	assert!(!rumble.rumble());
+
+	rumble.write(0x0000, 0x0A);
+	rumble.write(0x4000, 0x09);
+	assert!(rumble.rumble());
+
+	// the motor bit doesn't select a bank
+	rumble.write(0xA000, 0x42);
+	rumble.write(0x4000, 0x01);
+	assert!(!rumble.rumble());
+	assert_eq!(rumble.read(0xA000), 0x42);
+
+	// on other MBC5 carts it's a bank bit
+	let mut no_rumble = cart(0x1B, 0x02, 0x04);
+	no_rumble.write(0x4000, 0x08);
+	assert!(!no_rumble.rumble());
+
+}
+
+#[test]
+fn huc1_banks_and_ir() {
+
+	let mut cart = cart(0xFF, 0x03, 0x03);
+	assert!(cart.is_battery_backed());
+
+	cart.write(0x2000, 0x05);
+	assert_eq!(cart.read(0x4000), 10);
+
+	// RAM is there without enabling it
+	cart.write(0x4000, 0x02);
+	cart.write(0xA000, 0x42);
+	assert_eq!(cart.read(0xA000), 0x42);
+
+	// the IR port sees no light, and ignores writes
+	cart.write(0x0000, 0x0E);
+	assert_eq!(cart.read(0xA000), 0xC0);
+	cart.write(0xA000, 0x01);
+
+	cart.write(0x0000, 0x00);
+	assert_eq!(cart.read(0xA000), 0x42);
+	assert_eq!(cart.dump_sram()[2 * 0x2000], 0x42);
+
+	assert_sram_round_trips(&mut cart, 0x8000);
+
+}
+
+// runs an RTC command, and returns the response
+fn huc3_command(cart: &mut Box<dyn MBC>, command: u8) -> u8 {
+	cart.write(0x0000, 0x0B);
+	cart.write(0xA000, command);
+	cart.write(0x0000, 0x0C);
+
+	cart.read(0xA000)
+}
+
+#[test]
+fn huc3_rtc_counts_minutes_and_days() {
+
+	let mut cart = cart(0xFE, 0x03, 0x02);
+
+	// set the clock to 23:59 on day 0x123, a nibble at a time
+	huc3_command(&mut cart, 0x40);
+	huc3_command(&mut cart, 0x50);
+
+	for nibble in [0xF, 0x9, 0x5, 0x3, 0x2, 0x1] {
+		huc3_command(&mut cart, 0x30 | nibble);
+	}
+
+	huc3_command(&mut cart, 0x61);
+
+	// a minute later it's the next day
+	cart.tick(60 * 4194304);
+	huc3_command(&mut cart, 0x60);
+	huc3_command(&mut cart, 0x40);
+
+	let nibbles: Vec<u8> = (0..6).map(|_| huc3_command(&mut cart, 0x10) & 0xF).collect();
+	assert_eq!(nibbles, [0, 0, 0, 4, 2, 1]);
+
+	// responses echo the command in the high nibble
+	assert_eq!(huc3_command(&mut cart, 0x10) & 0xF0, 0x10);
+
+	// the clock is saved after the RAM
+	assert_sram_round_trips(&mut cart, 0x2000 + 12);
+	assert!(cart.load_sram(vec![0; 0x2000]).is_ok());
+
+}
+
+// bit bangs the MBC7's EEPROM
+struct Eeprom<'a>(&'a mut Box<dyn MBC>);
+
+impl Eeprom<'_> {
+	fn clock(&mut self, bit: bool) {
+		let di = if bit { 0x02 } else { 0x00 };
+
+		self.0.write(0xA080, 0x80 | di);
+		self.0.write(0xA080, 0xC0 | di);
+	}
+
+	fn send(&mut self, value: u32, bits: u32) {
+		(0..bits).rev().for_each(|bit| self.clock(value >> bit & 1 != 0));
+	}
+
+	// start bit, 2 opcode bits and 8 address bits
+	fn command(&mut self, opcode: u32, address: u32) {
+		self.0.write(0xA080, 0x00);
+		self.send(1 << 10 | opcode << 8 | address, 11);
+	}
+
+	fn read_word(&mut self, address: u32) -> u16 {
+		self.command(0b10, address);
+		assert_eq!(self.0.read(0xA080) & 1, 0);
+
+		(0..16).fold(0, |word, _| {
+			self.clock(false);
+			word << 1 | (self.0.read(0xA080) & 1) as u16
+		})
+	}
+}
+
+#[test]
+fn mbc7_eeprom_and_accelerometer() {
+
+	let mut cart = cart(0x22, 0x05, 0x00);
+
+	// nothing is mapped until both enables are set
+	cart.set_accelerometer(1.0, -1.0);
+	cart.write(0xA000, 0x55);
+	cart.write(0xA010, 0xAA);
+	assert_eq!(cart.read(0xA020), 0xFF);
+
+	cart.write(0x0000, 0x0A);
+	cart.write(0x4000, 0x40);
+
+	cart.write(0xA000, 0x55);
+	assert_eq!(cart.read(0xA030), 0x80);
+	cart.write(0xA010, 0xAA);
+
+	let x = u16::from_le_bytes([cart.read(0xA020), cart.read(0xA030)]);
+	let y = u16::from_le_bytes([cart.read(0xA040), cart.read(0xA050)]);
+	assert_eq!((x, y), (0x81D0 + 0x70, 0x81D0 - 0x70));
+
+	// a reading is only taken once per erase
+	cart.set_accelerometer(0.0, 0.0);
+	cart.write(0xA010, 0xAA);
+	assert_eq!(u16::from_le_bytes([cart.read(0xA020), cart.read(0xA030)]), 0x81D0 + 0x70);
+
+	let mut eeprom = Eeprom(&mut cart);
+	assert_eq!(eeprom.read_word(0x12), 0xFFFF);
+
+	// writes are ignored until enabled
+	eeprom.command(0b01, 0x12);
+	eeprom.send(0x1234, 16);
+	assert_eq!(eeprom.read_word(0x12), 0xFFFF);
+
+	eeprom.command(0b00, 0xC0);
+	eeprom.command(0b01, 0x12);
+	eeprom.send(0xBEEF, 16);
+	assert_eq!(eeprom.0.read(0xA080) & 1, 1);
+	assert_eq!(eeprom.read_word(0x12), 0xBEEF);
+
+	eeprom.command(0b11, 0x12);
+	assert_eq!(eeprom.read_word(0x12), 0xFFFF);
+
+	eeprom.command(0b01, 0x7F);
+	eeprom.send(0xCAFE, 16);
+	assert_eq!(cart.dump_sram()[0xFE..0x100], [0xFE, 0xCA]);
+
+	assert_sram_round_trips(&mut cart, 256);
+	assert!(cart.load_sram(vec![0xFF; 512]).is_ok());
+
+}
+
+// the flash unlock sequence writes 0x5555 and 0x2AAA in flash, which are in banks 2 and 1
+fn mbc6_unlock(cart: &mut Box<dyn MBC>) {
+	cart.write(0x2000, 0x02);
+	cart.write(0x5555, 0xAA);
+	cart.write(0x2000, 0x01);
+	cart.write(0x4AAA, 0x55);
+	cart.write(0x2000, 0x02);
+}
+
+fn mbc6_command(cart: &mut Box<dyn MBC>, command: u8) {
+	mbc6_unlock(cart);
+	cart.write(0x5555, command);
+}
+
+#[test]
+fn mbc6_banks_rom_and_flash() {
+
+	let mut cart = cart(0x20, 0x05, 0x03);
+
+	// two 8KiB windows
+	cart.write(0x2000, 0x07);
+	cart.write(0x3000, 0x0A);
+	assert_eq!(cart.read(0x4000), 0x07);
+	assert_eq!(cart.read(0x6000), 0x0A);
+
+	// 4KiB RAM windows
+	cart.write(0x0000, 0x0A);
+	cart.write(0x0400, 0x01);
+	cart.write(0x0800, 0x01);
+	cart.write(0xA123, 0x42);
+	assert_eq!(cart.read(0xB123), 0x42);
+
+	// flash in the first window, bank 2
+	cart.write(0x0C00, 0x01);
+	cart.write(0x1000, 0x01);
+	cart.write(0x2800, 0x08);
+	cart.write(0x2000, 0x02);
+	assert_eq!(cart.read(0x4000), 0xFF);
+
+	mbc6_command(&mut cart, 0x90);
+	assert_eq!([cart.read(0x4000), cart.read(0x4001)], [0xC2, 0x81]);
+	cart.write(0x4000, 0xF0);
+	assert_eq!(cart.read(0x4000), 0xFF);
+
+	// programming can only clear bits
+	mbc6_command(&mut cart, 0xA0);
+	cart.write(0x4010, 0x5A);
+	mbc6_command(&mut cart, 0xA0);
+	cart.write(0x4010, 0xF3);
+	assert_eq!(cart.read(0x4010), 0x52);
+
+	let sram = cart.dump_sram();
+	assert_eq!(sram[0x1123], 0x42);
+	assert_eq!(sram[0x8000 + 0x4010], 0x52);
+
+	// and at the end of the same 128KiB sector, and the start of the next one
+	mbc6_command(&mut cart, 0xA0);
+	cart.write(0x2000, 0x0F);
+	cart.write(0x5FFF, 0x00);
+	mbc6_command(&mut cart, 0xA0);
+	cart.write(0x2000, 0x10);
+	cart.write(0x4000, 0x00);
+
+	// erasing the sector sets everything in it again
+	mbc6_command(&mut cart, 0x80);
+	mbc6_unlock(&mut cart);
+	cart.write(0x4000, 0x30);
+	assert_eq!(cart.read(0x4010), 0xFF);
+
+	cart.write(0x2000, 0x0F);
+	assert_eq!(cart.read(0x5FFF), 0xFF);
+	cart.write(0x2000, 0x10);
+	assert_eq!(cart.read(0x4000), 0x00);
+
+	assert_sram_round_trips(&mut cart, 0x8000 + 0x100000);
+
+}
+
+#[test]
+fn mmm01_boots_the_menu_then_locks_to_a_game() {
+
+	// 512KiB of games, with the menu in the last 32KiB
+	let mut rom = rom(0x01, 0x04, 0x00);
+	let menu = rom.len() - 0x8000;
+	rom[menu + 0x147] = 0x0D;
+	rom[menu + 0x148] = 0x04;
+	rom[menu + 0x149] = 0x02;
+
+	let mut cart = create_cart(rom).unwrap();
+	assert!(cart.is_battery_backed());
+	assert_eq!(cart.read(0x0000), 60);
+	assert_eq!(cart.read(0x4000), 62);
+
+	// pick the 128KiB game starting at bank 8: bits 3-4 of the bank are fixed, leaving 8 banks to switch between
+	cart.write(0x2000, 0x08);
+	cart.write(0x6000, 0x30);
+	cart.write(0x0000, 0x40);
+
+	assert_eq!(cart.read(0x0000), 16);
+	assert_eq!(cart.read(0x4000), 18);
+
+	cart.write(0x2000, 0x03);
+	assert_eq!(cart.read(0x4000), 22);
+
+	// the game can't leave its part of the ROM
+	cart.write(0x2000, 0x1F);
+	assert_eq!(cart.read(0x4000), 30);
+	cart.write(0x2000, 0x10);
+	assert_eq!(cart.read(0x4000), 18);
+
+	cart.write(0x0000, 0x0A);
+	cart.write(0xA000, 0x42);
+	assert_eq!(cart.read(0xA000), 0x42);
+
+	assert_sram_round_trips(&mut cart, 0x2000);
+
+}
+
+#[test]
+fn camera_captures_dithered_tiles() {
+
+	let mut cart = cart(0xFC, 0x05, 0x04);
+
+	// left half black, right half white
+	let image: Vec<u8> = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+		.map(|i| if i % CAMERA_WIDTH < CAMERA_WIDTH / 2 { 0x00 } else { 0xFF })
+		.collect();
+	cart.set_camera_image(&image);
+
+	cart.write(0x0000, 0x0A);
+	cart.write(0x4000, 0x10);
+
+	for reg in 0..48 {
+		cart.write(0xA006 + reg, [0x40, 0x80, 0xC0][reg as usize % 3]);
+	}
+
+	cart.write(0xA000, 0x01);
+	assert_eq!(cart.read(0xA000), 0x01);
+
+	cart.tick(4 * (32446 + 512));
+	assert_eq!(cart.read(0xA000), 0x00);
+
+	// the first tile is black, the last tile of the first row is white
+	cart.write(0x4000, 0x00);
+	assert_eq!(cart.read(0xA100), 0xFF);
+	assert_eq!(cart.read(0xA101), 0xFF);
+	assert_eq!(cart.read(0xA100 + 15 * 16), 0x00);
+	assert_eq!(cart.read(0xA101 + 15 * 16), 0x00);
+
+	assert_sram_round_trips(&mut cart, 0x20000);
+
+}
+
+fn tama5_set(cart: &mut Box<dyn MBC>, reg: u8, value: u8) {
+	cart.write(0xA001, reg);
+	cart.write(0xA000, value);
+}
+
+#[test]
+fn tama5_ram_goes_through_the_registers() {
+
+	let mut cart = cart(0xFD, 0x04, 0x00);
+
+	tama5_set(&mut cart, 0x0, 0x3);
+	tama5_set(&mut cart, 0x1, 0x1);
+	assert_eq!(cart.read(0x4000), 2 * 0x13);
+
+	// write 0xA5 to 0x12
+	tama5_set(&mut cart, 0x4, 0x5);
+	tama5_set(&mut cart, 0x5, 0xA);
+	tama5_set(&mut cart, 0x6, 0x1);
+	tama5_set(&mut cart, 0x7, 0x2);
+
+	// and read it back
+	tama5_set(&mut cart, 0x6, 0x3);
+	tama5_set(&mut cart, 0x7, 0x2);
+
+	cart.write(0xA001, 0xA);
+	assert_eq!(cart.read(0xA000), 0xF1);
+	cart.write(0xA001, 0xC);
+	assert_eq!(cart.read(0xA000), 0xF5);
+	cart.write(0xA001, 0xD);
+	assert_eq!(cart.read(0xA000), 0xFA);
+
+	assert_eq!(cart.dump_sram()[0x12], 0xA5);
+	assert_sram_round_trips(&mut cart, 32);
+
+}
+
+#[test]
+fn tama5_rtc_isnt_emulated() {
+
+	let mut cart = cart(0xFD, 0x04, 0x00);
+
+	// write 0x3C to 0x00 and read it back
+	tama5_set(&mut cart, 0x4, 0xC);
+	tama5_set(&mut cart, 0x5, 0x3);
+	tama5_set(&mut cart, 0x6, 0x0);
+	tama5_set(&mut cart, 0x7, 0x0);
+	tama5_set(&mut cart, 0x6, 0x2);
+	tama5_set(&mut cart, 0x7, 0x0);
+
+	// the RTC is in the TAMA6, so its commands are ignored and the last RAM read stays put
+	tama5_set(&mut cart, 0x6, 0x4);
+	tama5_set(&mut cart, 0x7, 0x0);
+
+	cart.write(0xA001, 0xC);
+	assert_eq!(cart.read(0xA000), 0xFC);
+	cart.write(0xA001, 0xD);
+	assert_eq!(cart.read(0xA000), 0xF3);
+
+	assert_eq!(cart.dump_sram()[0x00], 0x3C);
+
+}
\ No newline at end of file
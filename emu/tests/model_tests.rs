@@ -0,0 +1,47 @@
+use emu::cpu::registers::Register16Bit;
+use emu::{Gameboy, Mode, Model};
+
+fn dmg_rom() -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+
+	rom
+}
+
+fn af(model: Model) -> u16 {
+	let gb = Gameboy::with_model(dmg_rom(), Box::new(|_| {}), model).unwrap();
+
+	assert_eq!(gb.model(), model);
+
+	gb.cpu.registers.get_16bit_reg(Register16Bit::AF)
+}
+
+#[test]
+fn models_are_told_apart_by_the_registers_the_bootrom_leaves() {
+
+	// games check A for the family, and the MGB and SGB2 set it to 0xFF
+	assert_eq!(af(Model::Dmg0) >> 8, 0x01);
+	assert_eq!(af(Model::Dmg) >> 8, 0x01);
+	assert_eq!(af(Model::Mgb) >> 8, 0xFF);
+	assert_eq!(af(Model::Sgb) >> 8, 0x01);
+	assert_eq!(af(Model::Sgb2) >> 8, 0xFF);
+	assert_eq!(af(Model::Cgb) >> 8, 0x11);
+
+	// the DMG bootrom leaves its header checksum in the flags, which is zero for this rom
+	assert_eq!(af(Model::Dmg) & 0xFF, 0x80);
+
+}
+
+#[test]
+fn only_the_cgb_runs_in_colour() {
+
+	for model in Model::ALL {
+		let gb = Gameboy::with_model(dmg_rom(), Box::new(|_| {}), model).unwrap();
+		let expected = if model == Model::Cgb { Mode::Cgb } else { Mode::Dmg };
+
+		assert_eq!(gb.mode(), expected, "{}", model.name());
+	}
+
+	assert_eq!(Gameboy::new(dmg_rom(), Box::new(|_| {})).unwrap().model(), Model::Dmg);
+
+}
\ No newline at end of file
@@ -0,0 +1,190 @@
+use emu::joypad::GBInput;
+use emu::movie::{Movie, MovieError, MovieStart};
+use emu::{Gameboy, FRAME_CYCLES};
+
+mod common;
+use common::gameboy;
+
+// a 32KiB ROM-only cart that keeps adding JOYP's button row into WRAM, so when a button changes shows up in memory
+fn joypad_rom() -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+
+	// ld a, 0x10; ldh (0x00), a; ld hl, 0xC000
+	// loop: ldh a, (0x00); ld b, a; ld a, (hl); add a, b; ld (hl), a; inc l; jr loop
+	rom[0x100..0x110].copy_from_slice(&[
+		0x3E, 0x10, 0xE0, 0x00, 0x21, 0x00, 0xC0,
+		0xF0, 0x00, 0x47, 0x7E, 0x80, 0x77, 0x2C, 0x18, 0xF7,
+	]);
+
+	rom
+}
+
+// a cart that never reads the joypad
+fn idle_rom() -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+
+	rom
+}
+
+fn run_cycles(gb: &mut Gameboy, cycles: u64) {
+	let end = gb.cycles + cycles;
+
+	while gb.cycles < end {
+		gb.tick();
+	}
+}
+
+// presses and releases buttons partway through frames, the way a frontend polling input at its own rate would
+fn record(gb: &mut Gameboy) {
+	run_cycles(gb, FRAME_CYCLES * 3 + 1234);
+	gb.btn_down(GBInput::BtnA);
+
+	run_cycles(gb, FRAME_CYCLES * 2 + 5678);
+	gb.btn_down(GBInput::BtnStart);
+	gb.btn_up(GBInput::BtnA);
+
+	run_cycles(gb, FRAME_CYCLES + 999);
+	gb.btn_up(GBInput::BtnStart);
+
+	run_cycles(gb, FRAME_CYCLES * 4);
+}
+
+#[test]
+fn movie_files_round_trip() {
+
+	let mut gb = gameboy(joypad_rom());
+
+	gb.record_movie(false).unwrap();
+	record(&mut gb);
+
+	let movie = gb.stop_movie().unwrap();
+	assert!(matches!(movie.start, MovieStart::Snapshot(_)));
+
+	assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie.clone()));
+
+	let bytes = movie.to_bytes();
+	assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated));
+	assert_eq!(Movie::from_bytes(b"not a movie"), Err(MovieError::InvalidMagic));
+
+}
+
+#[test]
+fn power_on_movies_play_back_exactly() {
+
+	let mut gb = gameboy(joypad_rom());
+
+	gb.record_movie(true).unwrap();
+	record(&mut gb);
+
+	let (cycles, state) = (gb.cycles, gb.save_state());
+	let movie = gb.stop_movie().unwrap();
+	assert!(movie.frames.contains(&0b0001) && movie.frames.contains(&0b1000));
+
+	let mut replay = gameboy(joypad_rom());
+	replay.play_movie(movie).unwrap();
+
+	while replay.cycles < cycles {
+		replay.tick();
+	}
+
+	assert_eq!(replay.cycles, cycles);
+	assert!(replay.save_state() == state, "playback desynced");
+
+}
+
+#[test]
+fn snapshot_movies_play_back_exactly() {
+
+	let mut gb = gameboy(joypad_rom());
+	run_cycles(&mut gb, FRAME_CYCLES * 5 + 321);
+
+	gb.record_movie(false).unwrap();
+	record(&mut gb);
+
+	let (cycles, state) = (gb.cycles, gb.save_state());
+	let movie = gb.stop_movie().unwrap();
+
+	// the snapshot puts it back where recording started, however far it's run since
+	run_cycles(&mut gb, FRAME_CYCLES * 2);
+	gb.play_movie(movie).unwrap();
+
+	while gb.cycles < cycles {
+		gb.tick();
+	}
+
+	assert_eq!(gb.cycles, cycles);
+	assert!(gb.save_state() == state, "playback desynced");
+
+}
+
+#[test]
+fn playback_ignores_input_and_ends_with_the_movie() {
+
+	let mut gb = gameboy(joypad_rom());
+
+	gb.record_movie(true).unwrap();
+	run_cycles(&mut gb, FRAME_CYCLES * 10);
+
+	let movie = gb.stop_movie().unwrap();
+	let length = movie.frames.len();
+
+	let mut replay = gameboy(joypad_rom());
+	replay.play_movie(movie).unwrap();
+
+	replay.btn_down(GBInput::BtnA);
+	assert_eq!(replay.bus().joypad.held(), 0);
+
+	while replay.movie_status().is_some() {
+		assert!(replay.movie_status().unwrap().playing);
+		replay.tick();
+	}
+
+	// one frame per VBlank, give or take the frame in progress when recording stopped
+	assert!(replay.cycles >= (length as u64 - 1) * FRAME_CYCLES);
+
+	replay.btn_down(GBInput::BtnA);
+	assert_eq!(replay.bus().joypad.held(), 1, "input goes back to the frontend once the movie ends");
+
+}
+
+#[test]
+fn movies_only_play_on_the_rom_and_start_they_were_made_for() {
+
+	let mut gb = gameboy(joypad_rom());
+
+	gb.record_movie(true).unwrap();
+	run_cycles(&mut gb, FRAME_CYCLES);
+
+	let movie = gb.stop_movie().unwrap();
+
+	assert_eq!(gameboy(idle_rom()).play_movie(movie.clone()), Err(MovieError::RomMismatch));
+	assert_eq!(gb.play_movie(movie.clone()), Err(MovieError::NotAtPowerOn));
+	assert_eq!(gb.record_movie(true), Err(MovieError::NotAtPowerOn));
+
+	assert_eq!(gameboy(joypad_rom()).play_movie(movie), Ok(()));
+
+}
+
+#[test]
+fn frames_where_the_joypad_isnt_read_are_lag_frames() {
+
+	let mut polling = gameboy(joypad_rom());
+	polling.record_movie(true).unwrap();
+	run_cycles(&mut polling, FRAME_CYCLES * 10);
+
+	let status = polling.movie_status().unwrap();
+	assert!(status.frame >= 9);
+	assert_eq!(status.length, status.frame + 1);
+	assert_eq!(status.lag_frames, 0);
+	assert!(!status.lagged);
+
+	let mut idle = gameboy(idle_rom());
+	idle.record_movie(true).unwrap();
+	run_cycles(&mut idle, FRAME_CYCLES * 10);
+
+	let status = idle.movie_status().unwrap();
+	assert_eq!(status.lag_frames, status.frame);
+	assert!(status.lagged);
+
+}
\ No newline at end of file
@@ -0,0 +1,126 @@
+use std::fs;
+
+use emu::{ppu::{RenderingMode, Renderer}, Gameboy};
+
+// an empty cart whose PPU is driven directly, one dot at a time
+fn fifo_gameboy() -> Gameboy {
+	let mut gb = Gameboy::new(vec![0; 0x8000], Box::new(|_| {})).unwrap();
+	gb.set_renderer(Renderer::Fifo);
+
+	gb
+}
+
+fn write(gb: &mut Gameboy, addr: u16, write: u8) {
+	gb.bus_mut().ppu.write(addr, write);
+}
+
+fn tick_until(gb: &mut Gameboy, mode: RenderingMode) -> u32 {
+	let mut dots = 0;
+
+	while gb.bus().ppu.rendering_mode != mode {
+		gb.bus_mut().ppu.tick(1);
+		dots += 1;
+	}
+
+	dots
+}
+
+// length of mode 3 on the next line
+fn mode_3_length(gb: &mut Gameboy) -> u32 {
+	tick_until(gb, RenderingMode::OAMscan);
+	tick_until(gb, RenderingMode::Draw);
+
+	tick_until(gb, RenderingMode::HBlank)
+}
+
+#[test]
+fn fifo_mode_3_length_varies() {
+
+	let mut gb = fifo_gameboy();
+
+	assert_eq!(mode_3_length(&mut gb), 172);
+
+	// fine scrolling throws away pixels at the start of the line
+	write(&mut gb, 0xFF43, 3);
+	assert_eq!(mode_3_length(&mut gb), 175);
+	write(&mut gb, 0xFF43, 0);
+
+	// an object lined up with a background tile in the middle of the next line, which costs 11 dots
+	let ly = gb.bus().ppu.read(0xFF44);
+
+	write(&mut gb, 0xFF40, 0x93);
+	write(&mut gb, 0xFE00, ly + 1 + 16);
+	write(&mut gb, 0xFE01, 80);
+
+	assert_eq!(mode_3_length(&mut gb), 172 + 11);
+
+	// the scanline renderer always takes 172 dots
+	gb.bus_mut().ppu.set_renderer(Renderer::Scanline);
+	assert_eq!(mode_3_length(&mut gb), 172);
+
+}
+
+#[test]
+fn fifo_mid_line_palette_write() {
+
+	let mut gb = fifo_gameboy();
+
+	// tile 0 is solid colour 3 and the background is made of it
+	for addr in 0x8000..0x8010 {
+		write(&mut gb, addr, 0xFF);
+	}
+
+	// colour 3 is black
+	write(&mut gb, 0xFF47, 0xFC);
+
+	tick_until(&mut gb, RenderingMode::OAMscan);
+	while gb.bus().ppu.read(0xFF44) != 10 {
+		gb.bus_mut().ppu.tick(1);
+	}
+
+	// half way through mode 3 colour 3 becomes white
+	tick_until(&mut gb, RenderingMode::Draw);
+	for _ in 0..12 + 80 {
+		gb.bus_mut().ppu.tick(1);
+	}
+	write(&mut gb, 0xFF47, 0x00);
+
+	tick_until(&mut gb, RenderingMode::VBlank);
+	tick_until(&mut gb, RenderingMode::OAMscan);
+
+	let frame = gb.bus().ppu.get_frame();
+	let line = &frame[160 * 10..160 * 11];
+
+	assert_eq!(line[0], 0x0000);
+	assert_eq!(line[159], 0x7FFF);
+
+	// the change lands on one pixel, and stays for the rest of the line
+	let split = line.iter().position(|colour| *colour == 0x7FFF).unwrap();
+	assert!((70..=90).contains(&split), "palette change landed on pixel {}", split);
+	assert!(line[split..].iter().all(|colour| *colour == 0x7FFF));
+
+}
+
+#[test]
+fn renderers_agree_on_dmg_acid2() {
+
+	let rom = fs::read("../roms/dmg-acid2.gb").expect("TEST ERROR: unable to read dmg-acid2.gb");
+
+	let mut frames = Vec::new();
+
+	for renderer in [Renderer::Scanline, Renderer::Fifo] {
+		let mut gb = Gameboy::new(rom.clone(), Box::new(|_| {})).unwrap();
+		gb.set_renderer(renderer);
+
+		while gb.cycles < 70224 * 30 {
+			gb.tick();
+		}
+
+		frames.push(gb.bus().ppu.get_frame());
+	}
+
+	let differences = frames[0].iter().zip(frames[1].iter()).filter(|(a, b)| a != b).count();
+
+	assert_eq!(differences, 0, "the renderers drew {} pixels differently", differences);
+
+}
\ No newline at end of file
@@ -0,0 +1,90 @@
+use emu::FRAME_CYCLES;
+
+mod common;
+use common::{gameboy, loop_rom, run_frames};
+
+#[test]
+fn rewinding_is_off_by_default() {
+
+	let mut gb = gameboy(loop_rom());
+
+	run_frames(&mut gb, 10);
+
+	assert_eq!(gb.rewind_usage(), (0, 0));
+	assert!(!gb.rewind_step());
+
+}
+
+#[test]
+fn rewind_steps_back_a_frame_at_a_time() {
+
+	let mut gb = gameboy(loop_rom());
+	gb.set_rewind_length(1);
+
+	run_frames(&mut gb, 10);
+
+	let mut last_cycles = gb.cycles;
+
+	for _ in 0..5 {
+		assert!(gb.rewind_step());
+		assert!(gb.cycles < last_cycles);
+		assert!(last_cycles - gb.cycles <= FRAME_CYCLES);
+
+		last_cycles = gb.cycles;
+	}
+
+}
+
+#[test]
+fn rewinding_restores_memory() {
+
+	let mut gb = gameboy(loop_rom());
+	gb.set_rewind_length(1);
+
+	run_frames(&mut gb, 5);
+
+	let (cycles, counter) = (gb.cycles, gb.bus().read_byte(0xC000));
+
+	run_frames(&mut gb, 5);
+	assert_ne!(gb.bus().read_byte(0xC000), counter);
+
+	// back to the first snapshot at or before that point
+	while gb.cycles > cycles {
+		assert!(gb.rewind_step());
+	}
+
+	let rewound = gb.cycles;
+
+	// running forward again from a snapshot gets the same results it did the first time
+	let mut replay = gameboy(loop_rom());
+	while replay.cycles < rewound {
+		replay.tick();
+	}
+
+	assert_eq!(replay.cycles, rewound);
+	assert_eq!(gb.bus().read_byte(0xC000), replay.bus().read_byte(0xC000));
+
+}
+
+#[test]
+fn rewind_buffer_is_bounded() {
+
+	let mut gb = gameboy(loop_rom());
+	gb.set_rewind_length(1);
+
+	run_frames(&mut gb, 120);
+
+	let (frames, size) = gb.rewind_usage();
+	assert_eq!(frames, 60);
+
+	// a whole state is tens of KB, and a game that barely changes memory shouldn't need anywhere near 60 of them
+	let state_size = gb.save_state().len();
+	assert!(size < state_size * 4, "{size} bytes for {frames} frames, with {state_size} byte states");
+
+	for _ in 0..60 {
+		assert!(gb.rewind_step());
+	}
+
+	assert!(!gb.rewind_step(), "only a second's worth should be kept");
+
+}
\ No newline at end of file
@@ -0,0 +1,219 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use emu::{cpu::registers::Register8Bit, serial::CapturePort, Gameboy, Model};
+
+// Mooneye test roms: https://github.com/Gekkio/mooneye-test-suite
+// Blargg's test roms: https://github.com/retrio/gb-test-roms
+
+const CPU_CLOCK: u64 = 4194304;
+
+// emulated time before a rom is considered hung. Some of blargg's cpu_instrs roms take over 15 seconds.
+const MOONEYE_TIMEOUT: u64 = 10 * CPU_CLOCK;
+const BLARGG_TIMEOUT: u64 = 30 * CPU_CLOCK;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum TestResult {
+	Pass,
+	Fail,
+	Timeout,
+}
+
+// roms that are known to fail. A failure in any rom not listed here is treated as a regression.
+const KNOWN_FAILURES: &[&str] = &[
+	"blargg/halt_bug.gb",
+	"blargg/interrupt_time.gb",
+	"mooneye/acceptance/bits/unused_hwio-GS.gb",
+	"mooneye/acceptance/ei_sequence.gb",
+	"mooneye/acceptance/interrupts/ie_push.gb",
+	"mooneye/acceptance/ppu/hblank_ly_scx_timing-GS.gb",
+	"mooneye/acceptance/ppu/intr_1_2_timing-GS.gb",
+	"mooneye/acceptance/ppu/intr_2_0_timing.gb",
+	"mooneye/acceptance/ppu/intr_2_mode0_timing.gb",
+	"mooneye/acceptance/ppu/intr_2_mode0_timing_sprites.gb",
+	"mooneye/acceptance/ppu/intr_2_mode3_timing.gb",
+	"mooneye/acceptance/ppu/intr_2_oam_ok_timing.gb",
+	"mooneye/acceptance/ppu/lcdon_timing-GS.gb",
+	"mooneye/acceptance/ppu/lcdon_write_timing-GS.gb",
+	"mooneye/acceptance/ppu/stat_irq_blocking.gb",
+	"mooneye/acceptance/ppu/stat_lyc_onoff.gb",
+	"mooneye/acceptance/ppu/vblank_stat_intr-GS.gb",
+	"mooneye/acceptance/rapid_di_ei.gb",
+	"mooneye/acceptance/serial/boot_sclk_align-dmgABCmgb.gb",
+];
+
+fn run_mooneye(path: &Path) -> TestResult {
+
+	let model = test_model(path).expect("TEST ERROR: rom doesn't run on an emulated model");
+	let mut gb = Gameboy::with_model(fs::read(path).unwrap(), Box::new(|_| {}), model).unwrap();
+
+	while gb.cycles < MOONEYE_TIMEOUT {
+
+		// mooneye tests signal completion by executing LD B, B
+		if !gb.cpu.halted && gb.bus().read_byte(gb.cpu.pc) == 0x40 {
+
+			let regs = [Register8Bit::B, Register8Bit::C, Register8Bit::D, Register8Bit::E, Register8Bit::H, Register8Bit::L]
+				.map(|reg| gb.cpu.registers.get_8bit_reg(reg));
+
+			// passing tests load the fibonacci sequence into the registers
+			return match regs {
+				[3, 5, 8, 13, 21, 34] => TestResult::Pass,
+				_ => TestResult::Fail
+			};
+
+		}
+
+		gb.tick();
+
+	}
+
+	TestResult::Timeout
+
+}
+
+// blargg's tests print their results through the serial port, and newer ones also write them to cart RAM
+fn run_blargg(path: &Path) -> TestResult {
+
+	let mut gb = Gameboy::new(fs::read(path).unwrap(), Box::new(|_| {})).unwrap();
+
+	let serial_output = Arc::new(Mutex::new(String::new()));
+	let sink = Arc::clone(&serial_output);
+
+	gb.set_link_port(Box::new(CapturePort::new(Box::new(move |byte| sink.lock().unwrap().push(byte as char)))));
+
+	while gb.cycles < BLARGG_TIMEOUT {
+
+		gb.tick();
+
+		if serial_output.lock().unwrap().contains("Passed") {
+			return TestResult::Pass;
+		} else if serial_output.lock().unwrap().contains("Failed") {
+			return TestResult::Fail;
+		}
+
+		// 0xA001-0xA003 holds a signature once the rom has started writing its results, 0xA000 holds the status
+		let bus = gb.bus();
+		if bus.read_byte(0xA001) == 0xDE && bus.read_byte(0xA002) == 0xB0 && bus.read_byte(0xA003) == 0x61 {
+			match bus.read_byte(0xA000) {
+				0x80 => {},
+				0x00 => return TestResult::Pass,
+				_ => return TestResult::Fail,
+			}
+		}
+
+	}
+
+	TestResult::Timeout
+
+}
+
+// mooneye tests with a model suffix (e.g. `-dmg0` or `-GS`) only pass on those models, so they're run on the first one
+// listed. The GBA runs GB games as a CGB does, so its tests run on the CGB. Tests for models that aren't emulated are
+// skipped
+fn test_model(path: &Path) -> Option<Model> {
+
+	let stem = path.file_stem().unwrap().to_str().unwrap();
+
+	match stem.rsplit_once('-') {
+		Some((_, models)) => match models {
+			"dmg0" => Some(Model::Dmg0),
+			"dmgABC" | "dmgABCmgb" | "GS" => Some(Model::Dmg),
+			"mgb" => Some(Model::Mgb),
+			"sgb" | "S" => Some(Model::Sgb),
+			"sgb2" => Some(Model::Sgb2),
+			"C" | "cgb" | "cgbABCDE" | "A" => Some(Model::Cgb),
+			_ => None,
+		},
+		None => Some(Model::Dmg),
+	}
+
+}
+
+fn find_roms(dir: &str) -> Vec<PathBuf> {
+
+	let mut roms = Vec::new();
+	let mut dirs = vec![PathBuf::from(dir)];
+
+	while let Some(dir) = dirs.pop() {
+		for entry in fs::read_dir(&dir).expect("TEST ERROR: unable to read test roms") {
+			let path = entry.unwrap().path();
+
+			if path.is_dir() {
+				dirs.push(path);
+			} else if path.extension().is_some_and(|ext| ext == "gb") {
+				roms.push(path);
+			}
+		}
+	}
+
+	roms.sort();
+
+	roms
+
+}
+
+fn run_suite(roms: Vec<PathBuf>, runner: fn(&Path) -> TestResult) {
+
+	let mut regressions = Vec::new();
+
+	println!("{:<60} RESULT", "ROM");
+
+	for rom in roms.iter() {
+
+		let name = rom.strip_prefix("../tests/").unwrap().to_str().unwrap().replace('\\', "/");
+		let result = runner(rom);
+		let known_failure = KNOWN_FAILURES.contains(&name.as_str());
+
+		let note = match (result, known_failure) {
+			(TestResult::Pass, true) => "(known failure now passes, remove it from KNOWN_FAILURES)",
+			(TestResult::Pass, false) | (_, true) => "",
+			(_, false) => {
+				regressions.push(name.clone());
+				"(REGRESSION)"
+			},
+		};
+
+		println!("{:<60} {:?} {}", name, result, note);
+
+	}
+
+	assert!(regressions.is_empty(), "{} rom(s) regressed: {:?}", regressions.len(), regressions);
+
+}
+
+#[test]
+fn mooneye_acceptance() {
+	run_suite(find_roms("../tests/mooneye/acceptance").into_iter().filter(|rom| test_model(rom).is_some()).collect(), run_mooneye);
+}
+
+#[test]
+fn mooneye_mbc1() {
+	run_suite(find_roms("../tests/mooneye/mbc1"), run_mooneye);
+}
+
+#[test]
+fn mooneye_mbc2() {
+	run_suite(find_roms("../tests/mooneye/mbc2"), run_mooneye);
+}
+
+#[test]
+fn mooneye_mbc5() {
+	run_suite(find_roms("../tests/mooneye/mbc5"), run_mooneye);
+}
+
+// the combined cpu_instrs.gb and dmg_sound.gb roms are skipped since they just run the individual roms back to back
+#[test]
+fn blargg_cpu_instrs() {
+	run_suite(find_roms("../tests/blargg/cpu_instrs"), run_blargg);
+}
+
+#[test]
+fn blargg_dmg_sound() {
+	run_suite(find_roms("../tests/blargg/dmg_sound"), run_blargg);
+}
+
+#[test]
+fn blargg_misc() {
+	run_suite(["halt_bug.gb", "instr_timing.gb", "interrupt_time.gb"].iter().map(|rom| Path::new("../tests/blargg").join(rom)).collect(), run_blargg);
+}
\ No newline at end of file
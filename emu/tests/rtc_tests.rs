@@ -0,0 +1,106 @@
+use emu::Gameboy;
+
+const CYCLES_PER_SECOND: u64 = 4194304;
+
+// an MBC3+TIMER+RAM+BATTERY cart with 8KiB of RAM
+fn mbc3_rtc_cart() -> Gameboy {
+	let mut rom = vec![0; 0x8000];
+	rom[0x147] = 0x10;
+	rom[0x149] = 0x02;
+
+	let mut gb = Gameboy::new(rom, Box::new(|_| {})).unwrap();
+
+	// enable RAM and the RTC
+	gb.bus_mut().write_byte(0x0000, 0x0A);
+
+	gb
+}
+
+fn latch(gb: &mut Gameboy) {
+	gb.bus_mut().write_byte(0x6000, 0x00);
+	gb.bus_mut().write_byte(0x6000, 0x01);
+}
+
+fn write_rtc(gb: &mut Gameboy, reg: u8, value: u8) {
+	gb.bus_mut().write_byte(0x4000, reg);
+	gb.bus_mut().write_byte(0xA000, value);
+}
+
+fn read_rtc(gb: &mut Gameboy, reg: u8) -> u8 {
+	gb.bus_mut().write_byte(0x4000, reg);
+	gb.bus().read_byte(0xA000)
+}
+
+// seconds, minutes, hours, days low, days high
+fn read_latched(gb: &mut Gameboy) -> [u8; 5] {
+	[0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|reg| read_rtc(gb, reg))
+}
+
+#[test]
+fn rtc_counts_emulated_time() {
+
+	let mut gb = mbc3_rtc_cart();
+
+	gb.bus_mut().cart.tick(CYCLES_PER_SECOND * 61);
+
+	// the registers only change when latched
+	assert_eq!(read_latched(&mut gb), [0, 0, 0, 0, 0]);
+
+	latch(&mut gb);
+	assert_eq!(read_latched(&mut gb), [1, 1, 0, 0, 0]);
+
+	// halting the clock stops it
+	write_rtc(&mut gb, 0x0C, 0x40);
+	gb.bus_mut().cart.tick(CYCLES_PER_SECOND * 10);
+	latch(&mut gb);
+	assert_eq!(read_latched(&mut gb), [1, 1, 0, 0, 0x40]);
+
+	// RAM banks still work alongside the RTC
+	gb.bus_mut().write_byte(0x4000, 0x00);
+	gb.bus_mut().write_byte(0xA123, 0x42);
+	assert_eq!(gb.bus().read_byte(0xA123), 0x42);
+
+}
+
+#[test]
+fn rtc_day_counter_overflows() {
+
+	let mut gb = mbc3_rtc_cart();
+
+	write_rtc(&mut gb, 0x08, 59);
+	write_rtc(&mut gb, 0x09, 59);
+	write_rtc(&mut gb, 0x0A, 23);
+	write_rtc(&mut gb, 0x0B, 0xFF);
+	write_rtc(&mut gb, 0x0C, 0x01);
+
+	gb.bus_mut().cart.tick(CYCLES_PER_SECOND);
+	latch(&mut gb);
+
+	// day 511 rolls over to day 0 and sets the carry bit
+	assert_eq!(read_latched(&mut gb), [0, 0, 0, 0, 0x80]);
+
+}
+
+#[test]
+fn rtc_persists_in_save_trailer() {
+
+	let mut gb = mbc3_rtc_cart();
+
+	write_rtc(&mut gb, 0x08, 12);
+	write_rtc(&mut gb, 0x09, 34);
+	write_rtc(&mut gb, 0x0A, 5);
+	write_rtc(&mut gb, 0x0B, 0x2A);
+	// halted, so the time between saving and loading doesn't matter
+	write_rtc(&mut gb, 0x0C, 0x41);
+	latch(&mut gb);
+
+	let sram = gb.bus().cart.dump_sram();
+	assert_eq!(sram.len(), 8 * 1024 + 48);
+
+	let mut loaded = mbc3_rtc_cart();
+	loaded.bus_mut().cart.load_sram(sram).unwrap();
+	latch(&mut loaded);
+
+	assert_eq!(read_latched(&mut loaded), [12, 34, 5, 0x2A, 0x41]);
+
+}
\ No newline at end of file
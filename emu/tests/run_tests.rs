@@ -0,0 +1,155 @@
+use emu::bus::MemRegister;
+use emu::ppu::RenderingMode;
+use emu::FRAME_CYCLES;
+
+mod common;
+use common::{gameboy, loop_rom};
+
+#[test]
+fn run_frame_stops_when_vblank_starts() {
+
+	let mut gb = gameboy(loop_rom());
+
+	// the bootrom hands over partway through a frame, so the first VBlank comes early
+	assert!(gb.run_frame().frame);
+
+	for _ in 0..5 {
+		let result = gb.run_frame();
+
+		assert!(result.frame);
+		assert_eq!(result.breakpoint, None);
+		assert_eq!(gb.bus().ppu.rendering_mode, RenderingMode::VBlank);
+		assert_eq!(gb.bus().read_register(MemRegister::LY), 144);
+	}
+
+	// from one VBlank to the next is a whole frame, give or take the instruction that crossed it
+	let result = gb.run_frame();
+	assert!(result.cycles.abs_diff(FRAME_CYCLES) <= 24, "{} cycles", result.cycles);
+
+}
+
+#[test]
+fn run_frame_times_out_with_the_lcd_off() {
+
+	// ld a, 0; ldh (0x40), a; jr @
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x106].copy_from_slice(&[0x3E, 0x00, 0xE0, 0x40, 0x18, 0xFE]);
+
+	let mut gb = gameboy(rom);
+	gb.run_cycles(16);
+
+	let result = gb.run_frame();
+
+	assert!(result.frame);
+	assert!(result.cycles >= FRAME_CYCLES && result.cycles < FRAME_CYCLES + 24, "{} cycles", result.cycles);
+
+}
+
+#[test]
+fn run_cycles_runs_at_least_as_long_as_asked() {
+
+	let mut gb = gameboy(loop_rom());
+
+	let result = gb.run_cycles(1000);
+
+	assert!(result.cycles >= 1000 && result.cycles < 1024, "{} cycles", result.cycles);
+	assert_eq!(gb.cycles, result.cycles);
+	assert!(!result.frame);
+
+	gb.run_frame();
+
+	let result = gb.run_cycles(FRAME_CYCLES);
+	assert!(result.frame, "a frame's worth of cycles always crosses a VBlank");
+
+}
+
+#[test]
+fn run_results_count_audio_samples() {
+
+	let mut gb = gameboy(loop_rom());
+
+	// 48kHz at just under 60 frames a second
+	let result = gb.run_cycles(FRAME_CYCLES * 10);
+	let per_frame = result.audio_samples / 10;
+
+	assert!((780..830).contains(&per_frame), "{} samples per frame", per_frame);
+
+}
+
+#[test]
+fn breakpoints_stop_a_run_early() {
+
+	let mut gb = gameboy(loop_rom());
+	gb.set_breakpoints(vec![0x104]);
+
+	let result = gb.run_frame();
+	assert_eq!(result.breakpoint, Some(0x104));
+	assert_eq!(gb.cpu.pc, 0x104);
+	assert!(!result.frame);
+
+	// carrying on runs the loop once more before stopping again
+	let result = gb.run_frame();
+	assert_eq!(result.breakpoint, Some(0x104));
+	assert_eq!(result.cycles, 24);
+
+	gb.set_breakpoints(Vec::new());
+	assert_eq!(gb.run_frame().breakpoint, None);
+
+}
+
+#[test]
+fn run_results_say_when_the_rumble_motor_changed() {
+
+	// an MBC5 rumble cart: ld a, 0x08; ld (0x4000), a; xor a; ld (0x4000), a; jr @
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x10B].copy_from_slice(&[0x3E, 0x08, 0xEA, 0x00, 0x40, 0xAF, 0xEA, 0x00, 0x40, 0x18, 0xFE]);
+	rom[0x147] = 0x1C;
+
+	let mut gb = gameboy(rom);
+	let result = gb.run_cycles(100);
+
+	assert!(result.rumble);
+
+	// a buzz shorter than the run still turns on and off at the cycles it happened
+	match result.rumble_changes[..] {
+		[(on, true), (off, false)] => assert_eq!(off - on, 20),
+		_ => panic!("{:?}", result.rumble_changes),
+	}
+
+	assert!(gb.run_frame().rumble_changes.is_empty());
+
+}
+
+#[test]
+fn run_frame_linked_runs_both_gameboys() {
+
+	let mut gb = gameboy(loop_rom());
+	let mut other = gameboy(loop_rom());
+	gb.link_with(&mut other);
+
+	for _ in 0..3 {
+		let (result, other_result) = gb.run_frame_linked(&mut other);
+
+		assert!(result.frame);
+		assert!(gb.cycles.abs_diff(other.cycles) <= 24);
+		assert!(other_result.cycles.abs_diff(result.cycles) <= 24);
+	}
+
+}
+
+#[test]
+fn gameboy_can_run_on_another_thread() {
+
+	let mut gb = gameboy(loop_rom());
+	gb.run_frame();
+
+	// the frontend's emulation thread hands the Gameboy back and forth like this
+	let mut gb = std::thread::spawn(move || {
+		gb.run_frame();
+		gb
+	}).join().unwrap();
+
+	assert!(gb.run_frame().frame);
+	assert!(gb.bus().read_byte(0xC000) > 0);
+
+}
\ No newline at end of file
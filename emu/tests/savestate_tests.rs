@@ -0,0 +1,75 @@
+use std::fs;
+
+use emu::{cpu::registers::Register16Bit, savestate::StateError, Gameboy};
+
+fn boot_acid2() -> Gameboy {
+	let rom = fs::read("../roms/dmg-acid2.gb").expect("TEST ERROR: unable to read dmg-acid2.gb");
+
+	Gameboy::new(rom, Box::new(|_| {})).unwrap()
+}
+
+fn machine_snapshot(gb: &Gameboy) -> (u16, u16, u16, u64, Vec<u16>) {
+	let frame = gb.bus().ppu.get_frame().to_vec();
+
+	(gb.cpu.pc, gb.cpu.registers.get_16bit_reg(Register16Bit::AF), gb.cpu.registers.get_16bit_reg(Register16Bit::HL), gb.cycles, frame)
+}
+
+#[test]
+fn savestate_round_trip() {
+
+	let mut gb = boot_acid2();
+
+	for _ in 0..100_000 {
+		gb.tick();
+	}
+
+	let state = gb.save_state();
+
+	for _ in 0..50_000 {
+		gb.tick();
+	}
+
+	let expected = machine_snapshot(&gb);
+
+	gb.load_state(&state).expect("unable to load save state");
+
+	for _ in 0..50_000 {
+		gb.tick();
+	}
+
+	assert!(machine_snapshot(&gb) == expected, "emulation diverged after loading a save state");
+
+	// a state should load into a freshly booted instance of the same ROM
+	let mut fresh = boot_acid2();
+	fresh.load_state(&gb.save_state()).expect("unable to load save state into a new instance");
+	assert!(machine_snapshot(&fresh) == machine_snapshot(&gb));
+
+}
+
+#[test]
+fn savestate_rejects_bad_states() {
+
+	let mut gb = boot_acid2();
+
+	for _ in 0..10_000 {
+		gb.tick();
+	}
+
+	let state = gb.save_state();
+
+	let mut old_version = state.clone();
+	old_version[4..8].copy_from_slice(&0u32.to_le_bytes());
+	assert_eq!(gb.load_state(&old_version), Err(StateError::UnsupportedVersion(0)));
+
+	assert_eq!(gb.load_state(b"not a state"), Err(StateError::InvalidMagic));
+
+	// a truncated state must leave the emulator as it was
+	for _ in 0..10_000 {
+		gb.tick();
+	}
+
+	let before = gb.save_state();
+	assert_eq!(gb.load_state(&state[..state.len() / 2]), Err(StateError::UnexpectedEof));
+	assert_eq!(gb.save_state(), before);
+
+}
\ No newline at end of file
@@ -0,0 +1,189 @@
+use emu::sgb::SGB_WIDTH;
+use emu::{Gameboy, Model};
+
+mod common;
+use common::run_frames;
+
+// a 32KiB ROM-only cart that loops forever, with the header flags that turn on SGB commands
+fn sgb_rom() -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x146] = 0x03;
+	rom[0x14B] = 0x33;
+	rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+
+	rom
+}
+
+fn sgb() -> Gameboy {
+	Gameboy::with_model(sgb_rom(), Box::new(|_| {}), Model::Sgb).unwrap()
+}
+
+// sends a packet the way games do: a reset pulse, 128 bits LSB first, then a 0 stop bit
+fn send_packet(gb: &mut Gameboy, packet: [u8; 16]) {
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0xFF00, 0x00);
+	bus.write_byte(0xFF00, 0x30);
+
+	for bit in (0..128).map(|i| (packet[i / 8] >> (i % 8)) & 1).chain([0]) {
+		bus.write_byte(0xFF00, if bit == 1 { 0x10 } else { 0x20 });
+		bus.write_byte(0xFF00, 0x30);
+	}
+}
+
+// the colour of a pixel on the Game Boy's screen, which sits at (48, 40) inside the border
+fn screen_pixel(gb: &Gameboy, x: usize, y: usize) -> u16 {
+	gb.sgb_frame().unwrap()[(40 + y) * SGB_WIDTH + 48 + x]
+}
+
+// PAL01 with colour 0, then colours 1-3 of palettes 0 and 1
+fn pal01(colours: [u16; 7]) -> [u8; 16] {
+	let mut packet = [0; 16];
+	packet[0] = 1;	// PAL01 is command 0, and 1 packet long
+
+	for (i, colour) in colours.iter().enumerate() {
+		packet[1 + i * 2..3 + i * 2].copy_from_slice(&colour.to_le_bytes());
+	}
+
+	packet
+}
+
+#[test]
+fn only_sgb_games_on_an_sgb_get_a_border() {
+
+	assert!(sgb().sgb_frame().is_some());
+
+	let dmg = Gameboy::with_model(sgb_rom(), Box::new(|_| {}), Model::Dmg).unwrap();
+	assert!(dmg.sgb_frame().is_none());
+
+	let mut rom = sgb_rom();
+	rom[0x146] = 0x00;
+	let unflagged = Gameboy::with_model(rom, Box::new(|_| {}), Model::Sgb).unwrap();
+	assert!(unflagged.sgb_frame().is_none());
+
+}
+
+#[test]
+fn palettes_colour_the_screen_and_border() {
+
+	let mut gb = sgb();
+
+	send_packet(&mut gb, pal01([0x001F, 0x0001, 0x0002, 0x7C00, 0x0004, 0x0005, 0x0006]));
+	run_frames(&mut gb, 2);
+
+	// the screen is blank, and with no border loaded everything is colour 0
+	assert_eq!(screen_pixel(&gb, 0, 0), 0x001F);
+	assert_eq!(gb.sgb_frame().unwrap()[0], 0x001F);
+
+	// mapping every colour to shade 3 shows colour 3 of palette 0
+	gb.bus_mut().write_byte(0xFF47, 0xFF);
+	run_frames(&mut gb, 2);
+
+	assert_eq!(screen_pixel(&gb, 0, 0), 0x7C00);
+
+}
+
+#[test]
+fn attr_blk_picks_palettes_for_cells() {
+
+	let mut gb = sgb();
+
+	send_packet(&mut gb, pal01([0x0000, 0x0001, 0x0002, 0x7C00, 0x0004, 0x0005, 0x03E0]));
+
+	// one block covering cells (0, 0) to (1, 1), with only the inside set to palette 1
+	let mut attr_blk = [0; 16];
+	attr_blk[..8].copy_from_slice(&[0x04 << 3 | 1, 1, 0b001, 0b01, 0, 0, 1, 1]);
+	send_packet(&mut gb, attr_blk);
+
+	gb.bus_mut().write_byte(0xFF47, 0xFF);
+	run_frames(&mut gb, 2);
+
+	assert_eq!(screen_pixel(&gb, 0, 0), 0x03E0);
+	assert_eq!(screen_pixel(&gb, 15, 15), 0x03E0, "the edges get the inside palette too");
+	assert_eq!(screen_pixel(&gb, 16, 16), 0x7C00);
+
+}
+
+#[test]
+fn mlt_req_cycles_through_controllers() {
+
+	let mut gb = sgb();
+
+	let next_player = |gb: &mut Gameboy| {
+		let bus = gb.bus_mut();
+
+		bus.write_byte(0xFF00, 0x10);
+		bus.write_byte(0xFF00, 0x30);
+		bus.read_byte(0xFF00) & 0xF
+	};
+
+	assert_eq!(gb.bus().read_byte(0xFF00) & 0xF, 0xF);
+	assert_eq!(next_player(&mut gb), 0xF, "there's only one controller until MLT_REQ");
+
+	let mut mlt_req = [0; 16];
+	mlt_req[..2].copy_from_slice(&[0x11 << 3 | 1, 0x01]);
+	send_packet(&mut gb, mlt_req);
+
+	assert_eq!(next_player(&mut gb), 0xE);
+	assert_eq!(next_player(&mut gb), 0xF);
+
+}
+
+#[test]
+fn pal_trn_reads_system_palettes_off_the_screen() {
+
+	let mut gb = sgb();
+
+	// show 256 tiles in order, 20 to a row. The first 8 bytes are system palette 0
+	{
+		let bus = gb.bus_mut();
+		let colours: [u16; 4] = [0x1234, 0x2345, 0x3456, 0x4567];
+
+		for (i, colour) in colours.iter().enumerate() {
+			bus.write_byte(0x8000 + i as u16 * 2, colour.to_le_bytes()[0]);
+			bus.write_byte(0x8001 + i as u16 * 2, colour.to_le_bytes()[1]);
+		}
+
+		for tile in 0..20 * 13u16 {
+			bus.write_byte(0x9800 + (tile / 20) * 32 + tile % 20, tile as u8);
+		}
+
+		bus.write_byte(0xFF47, 0xE4);
+	}
+
+	run_frames(&mut gb, 1);
+
+	let mut pal_trn = [0; 16];
+	pal_trn[0] = 0x0B << 3 | 1;
+	send_packet(&mut gb, pal_trn);
+
+	run_frames(&mut gb, 1);
+
+	// PAL_SET with system palette 0 for all 4 palettes
+	let mut pal_set = [0; 16];
+	pal_set[0] = 0x0A << 3 | 1;
+	send_packet(&mut gb, pal_set);
+
+	run_frames(&mut gb, 1);
+
+	assert_eq!(gb.sgb_frame().unwrap()[0], 0x1234);
+
+}
+
+#[test]
+fn sgb_state_is_saved() {
+
+	let mut gb = sgb();
+
+	send_packet(&mut gb, pal01([0x001F, 0, 0, 0, 0, 0, 0]));
+	run_frames(&mut gb, 2);
+
+	let state = gb.save_state();
+
+	send_packet(&mut gb, pal01([0x03E0, 0, 0, 0, 0, 0, 0]));
+	assert_eq!(gb.sgb_frame().unwrap()[0], 0x03E0);
+
+	gb.load_state(&state).unwrap();
+	assert_eq!(gb.sgb_frame().unwrap()[0], 0x001F);
+
+}
\ No newline at end of file
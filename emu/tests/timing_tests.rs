@@ -0,0 +1,176 @@
+use emu::{cpu::registers::Register8Bit, Gameboy};
+
+// `nops` NOPs followed by LDH A, (LY); JR -2
+fn ly_read_rom(nops: usize) -> Vec<u8> {
+	let mut rom = vec![0; 0x8000];
+	rom[0x100 + nops..0x100 + nops + 4].copy_from_slice(&[0xF0, 0x44, 0x18, 0xFE]);
+
+	rom
+}
+
+// runs up to the JR, and returns what the LDH read from LY
+fn read_ly_after(nops: usize) -> u8 {
+	let mut gb = Gameboy::new(ly_read_rom(nops), Box::new(|_| {})).unwrap();
+
+	while gb.cpu.pc != 0x100 + nops as u16 + 2 {
+		gb.tick();
+	}
+
+	gb.cpu.registers.get_8bit_reg(Register8Bit::A)
+}
+
+#[test]
+fn reads_happen_on_their_m_cycle() {
+
+	// LY starts at 0 and increments after 452 T-states, 4 before the line ends. LDH A, (a8) reads on its 3rd M-cycle,
+	// so starting it 12 T-states before the increment reads the new LY, while starting it 16 T-states before reads the old one
+	assert_eq!(read_ly_after(109), 0);
+	assert_eq!(read_ly_after(110), 1);
+
+}
+
+#[test]
+fn bus_is_ticked_once_per_m_cycle() {
+
+	// LD A, (0xC000); NOP
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x104].copy_from_slice(&[0xFA, 0x00, 0xC0, 0x00]);
+
+	let mut gb = Gameboy::new(rom, Box::new(|_| {})).unwrap();
+
+	gb.tick();
+	assert_eq!(gb.cpu.instr_cycles, 16);
+	assert_eq!(gb.bus().ppu.line_dots, 16);
+
+	gb.tick();
+	assert_eq!(gb.bus().ppu.line_dots, 20);
+
+}
+
+// fills work RAM at `src` with a pattern, then starts a DMA from it
+fn start_dma(gb: &mut Gameboy, src: u8) {
+	let bus = gb.bus_mut();
+
+	for i in 0..0xA0 {
+		bus.write_byte(((src as u16) << 8) + i, i as u8 ^ 0x5A);
+	}
+
+	bus.write_byte(0xFF46, src);
+}
+
+#[test]
+fn oam_dma_takes_160_m_cycles() {
+
+	let mut gb = Gameboy::new(vec![0; 0x8000], Box::new(|_| {})).unwrap();
+	start_dma(&mut gb, 0xC1);
+
+	let bus = gb.bus_mut();
+
+	// OAM is still usable during the setup M-cycle
+	bus.tick(4);
+	assert!(!bus.dma_running());
+	assert_ne!(bus.cpu_read(0xFE00), 0xFF);
+
+	bus.write_byte(0xFF80, 0x12);
+
+	for i in 0..0xA0 {
+		bus.tick(4);
+
+		assert!(bus.dma_running());
+		assert_eq!(bus.cpu_read(0xFE00), 0xFF);
+		// the cart shares the external bus with the source, so reading it returns the byte being copied. HRAM isn't on it
+		assert_eq!(bus.cpu_read(0x0000), i as u8 ^ 0x5A);
+		assert_eq!(bus.cpu_read(0xFF80), 0x12);
+	}
+
+	bus.tick(4);
+	assert!(!bus.dma_running());
+
+	for i in 0..0xA0 {
+		assert_eq!(bus.cpu_read(0xFE00 + i), i as u8 ^ 0x5A);
+	}
+
+}
+
+
+#[test]
+fn oam_dma_sources_above_0xdf_read_work_ram() {
+
+	let mut gb = Gameboy::new(vec![0; 0x8000], Box::new(|_| {})).unwrap();
+	start_dma(&mut gb, 0xDE);
+	gb.bus_mut().write_byte(0xFF46, 0xFE);
+
+	let bus = gb.bus_mut();
+
+	for _ in 0..162 {
+		bus.tick(4);
+	}
+
+	assert_eq!(bus.read_byte(0xFF46), 0xFE);
+	assert_eq!(bus.read_byte(0xFE10), 0x10 ^ 0x5A);
+
+}
+
+
+// a cart that does nothing, with the timer stopped and the system counter reset
+fn timer_gb() -> Gameboy {
+	let mut rom = vec![0; 0x8000];
+	rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+
+	let mut gb = Gameboy::new(rom, Box::new(|_| {})).unwrap();
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0xFF07, 0x00);
+	bus.write_byte(0xFF04, 0x00);
+	bus.write_byte(0xFF0F, 0x00);
+
+	gb
+}
+
+#[test]
+fn resetting_div_can_increment_tima() {
+
+	let mut gb = timer_gb();
+	let bus = gb.bus_mut();
+
+	// TAC 0x05 clocks TIMA off bit 3 of the system counter, which is set after 8 T-states
+	bus.write_byte(0xFF05, 0x00);
+	bus.write_byte(0xFF07, 0x05);
+	bus.tick(8);
+	assert_eq!(bus.read_byte(0xFF05), 0x00);
+
+	bus.write_byte(0xFF04, 0x12);
+	assert_eq!(bus.read_byte(0xFF05), 0x01);
+	assert_eq!(bus.read_byte(0xFF04), 0x00);
+
+	// so does turning the timer off while the bit is set
+	bus.tick(8);
+	bus.write_byte(0xFF07, 0x01);
+	assert_eq!(bus.read_byte(0xFF05), 0x02);
+
+}
+
+#[test]
+fn tima_reloads_an_m_cycle_after_overflowing() {
+
+	let mut gb = timer_gb();
+	let bus = gb.bus_mut();
+
+	bus.write_byte(0xFF05, 0xFF);
+	bus.write_byte(0xFF06, 0x42);
+	bus.write_byte(0xFF07, 0x05);
+
+	// bit 3 falls after 16 T-states, and TIMA sits at 0 for an M-cycle before the reload
+	bus.tick(16);
+	assert_eq!(bus.read_byte(0xFF05), 0x00);
+	assert_eq!(bus.read_byte(0xFF0F) & 0x04, 0x00);
+
+	bus.tick(4);
+	assert_eq!(bus.read_byte(0xFF05), 0x42);
+	assert_eq!(bus.read_byte(0xFF0F) & 0x04, 0x04);
+
+	// writing TIMA during the reload M-cycle is ignored
+	bus.write_byte(0xFF05, 0x99);
+	assert_eq!(bus.read_byte(0xFF05), 0x42);
+
+}
\ No newline at end of file
@@ -0,0 +1,67 @@
+use emu::joypad::GBInput;
+
+// A scripted input file has one event per line:
+//
+//     <frame> <press|release> <button>
+//
+// e.g. `120 press start`. Buttons are up, down, left, right, a, b, start and select.
+// Blank lines and anything after a '#' are ignored.
+
+pub struct InputEvent {
+	pub frame: u64,
+	pub btn: GBInput,
+	pub pressed: bool,
+}
+
+fn parse_button(name: &str) -> Option<GBInput> {
+	match name.to_ascii_lowercase().as_str() {
+		"up" => Some(GBInput::DPadUp),
+		"down" => Some(GBInput::DPadDown),
+		"left" => Some(GBInput::DPadLeft),
+		"right" => Some(GBInput::DPadRight),
+		"a" => Some(GBInput::BtnA),
+		"b" => Some(GBInput::BtnB),
+		"start" => Some(GBInput::BtnStart),
+		"select" => Some(GBInput::BtnSelect),
+		_ => None
+	}
+}
+
+pub fn parse_script(script: &str) -> Result<Vec<InputEvent>, String> {
+
+	let mut events = Vec::new();
+
+	for (i, line) in script.lines().enumerate() {
+
+		let line = line.split('#').next().unwrap_or("").trim();
+
+		if line.is_empty() {
+			continue;
+		}
+
+		let parts: Vec<&str> = line.split_whitespace().collect();
+
+		if parts.len() != 3 {
+			return Err(format!("line {}: expected `<frame> <press|release> <button>`", i + 1));
+		}
+
+		let frame = parts[0].parse::<u64>().map_err(|_| format!("line {}: invalid frame number `{}`", i + 1, parts[0]))?;
+
+		let pressed = match parts[1] {
+			"press" => true,
+			"release" => false,
+			action => return Err(format!("line {}: unknown action `{}`", i + 1, action))
+		};
+
+		let btn = parse_button(parts[2]).ok_or(format!("line {}: unknown button `{}`", i + 1, parts[2]))?;
+
+		events.push(InputEvent { frame, btn, pressed });
+
+	}
+
+	// events are applied in order, so keep lines for the same frame in the order they were written
+	events.sort_by_key(|event| event.frame);
+
+	Ok(events)
+
+}
\ No newline at end of file
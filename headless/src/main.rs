@@ -0,0 +1,256 @@
+use std::fs;
+use std::net::TcpListener;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use emu::{Gameboy, Model, FRAME_CYCLES};
+use emu::ppu::Renderer;
+use emu::serial::CapturePort;
+use emu::sgb::{SGB_WIDTH, SGB_HEIGHT};
+use emu::link::tcp::{TcpLink, DEFAULT_DELAY, DEFAULT_WINDOW};
+
+mod input;
+mod output;
+
+const CYCLES_PER_SECOND: f64 = 4194304.0;
+
+const USAGE: &str = "Usage: headless <rom> [options]
+
+Options:
+  --frames <n>      run for n frames (default: 60)
+  --cycles <n>      run for n T-states instead of a number of frames
+  --input <file>    apply the button presses in a scripted input file
+  --bootrom <file>  boot through the given bootrom
+  --model <dmg0|dmg|mgb|sgb|sgb2|cgb>  emulate the given hardware instead of the one named in the cart header
+  --renderer <scanline|fifo>  pick the PPU renderer (default: scanline)
+  --serial          print bytes sent over the serial port to stdout
+  --rumble          print when the rumble motor turns on and off, with the emulated time
+  --link-host <addr>     wait for another instance to connect a link cable at addr
+  --link-connect <addr>  connect a link cable to an instance hosting at addr
+  --link-delay <n>       windows of latency hidden by the link when hosting (default: 8)
+  --png <file>      write the final frame to a PNG
+  --wav <file>      write the audio output to a WAV";
+
+struct Args {
+	rom_path: String,
+	frames: u64,
+	cycles: Option<u64>,
+	input_path: Option<String>,
+	bootrom_path: Option<String>,
+	model: Option<Model>,
+	renderer: Renderer,
+	print_serial: bool,
+	log_rumble: bool,
+	link_host: Option<String>,
+	link_connect: Option<String>,
+	link_delay: u32,
+	png_path: Option<String>,
+	wav_path: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+
+	let mut args = std::env::args().skip(1);
+
+	let mut rom_path = None;
+	let mut parsed = Args {
+		rom_path: String::new(),
+		frames: 60,
+		cycles: None,
+		input_path: None,
+		bootrom_path: None,
+		model: None,
+		renderer: Renderer::Scanline,
+		print_serial: false,
+		log_rumble: false,
+		link_host: None,
+		link_connect: None,
+		link_delay: DEFAULT_DELAY,
+		png_path: None,
+		wav_path: None,
+	};
+
+	while let Some(arg) = args.next() {
+
+		let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+
+		match arg.as_str() {
+			"--frames" => parsed.frames = value("--frames")?.parse().map_err(|_| "--frames must be a number".to_string())?,
+			"--cycles" => parsed.cycles = Some(value("--cycles")?.parse().map_err(|_| "--cycles must be a number".to_string())?),
+			"--input" => parsed.input_path = Some(value("--input")?),
+			"--bootrom" => parsed.bootrom_path = Some(value("--bootrom")?),
+			"--model" => {
+				let name = value("--model")?;
+
+				parsed.model = Some(Model::ALL.into_iter().find(|model| model.name().eq_ignore_ascii_case(&name))
+					.ok_or("--model must be dmg0, dmg, mgb, sgb, sgb2 or cgb".to_string())?);
+			},
+			"--renderer" => parsed.renderer = match value("--renderer")?.as_str() {
+				"scanline" => Renderer::Scanline,
+				"fifo" => Renderer::Fifo,
+				_ => return Err("--renderer must be scanline or fifo".to_string()),
+			},
+			"--serial" => parsed.print_serial = true,
+			"--rumble" => parsed.log_rumble = true,
+			"--link-host" => parsed.link_host = Some(value("--link-host")?),
+			"--link-connect" => parsed.link_connect = Some(value("--link-connect")?),
+			"--link-delay" => parsed.link_delay = value("--link-delay")?.parse().map_err(|_| "--link-delay must be a number".to_string())?,
+			"--png" => parsed.png_path = Some(value("--png")?),
+			"--wav" => parsed.wav_path = Some(value("--wav")?),
+			"-h" | "--help" => return Err(String::new()),
+			_ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+			_ if rom_path.is_none() => rom_path = Some(arg),
+			_ => return Err(format!("unexpected argument {}", arg)),
+		}
+
+	}
+
+	parsed.rom_path = rom_path.ok_or("no ROM given".to_string())?;
+
+	if parsed.link_host.is_some() && parsed.link_connect.is_some() {
+		return Err("--link-host and --link-connect can't be used together".to_string());
+	}
+
+	if parsed.print_serial && (parsed.link_host.is_some() || parsed.link_connect.is_some()) {
+		return Err("--serial can't be used with a link cable".to_string());
+	}
+
+	Ok(parsed)
+
+}
+
+fn run(args: Args) -> Result<(), String> {
+
+	let rom = fs::read(&args.rom_path).map_err(|e| format!("unable to read {}: {}", args.rom_path, e))?;
+
+	let events = match &args.input_path {
+		Some(path) => input::parse_script(&fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?)
+			.map_err(|e| format!("{}: {}", path, e))?,
+		None => Vec::new(),
+	};
+
+	let samples = Arc::new(Mutex::new(Vec::new()));
+	let audio_samples = Arc::clone(&samples);
+
+	let model = args.model.unwrap_or(Model::detect(&rom));
+
+	let mut gb = Gameboy::with_model(rom, Box::new(move |buffer| {
+		audio_samples.lock().unwrap().extend_from_slice(buffer);
+	}), model).map_err(|e| format!("unable to load {}: {}", args.rom_path, e))?;
+
+	for warning in gb.header().warnings() {
+		eprintln!("[WARNING] {}: {}", args.rom_path, warning);
+	}
+
+	gb.set_renderer(args.renderer);
+
+	if let Some(path) = &args.bootrom_path {
+		gb.load_bootrom(fs::read(path).map_err(|e| format!("unable to read {}: {}", path, e))?, true)
+			.map_err(|e| format!("unable to load {}: {}", path, e))?;
+	}
+
+	if args.print_serial {
+		gb.set_link_port(Box::new(CapturePort::new(Box::new(|byte| {
+			print!("{}", byte as char);
+			let _ = std::io::stdout().flush();
+		}))));
+	}
+
+	let mut link = if let Some(addr) = &args.link_host {
+		let listener = TcpListener::bind(addr).map_err(|e| format!("unable to listen on {}: {}", addr, e))?;
+
+		println!("waiting for a link cable connection on {}", listener.local_addr().map_err(|e| e.to_string())?);
+
+		Some(TcpLink::host(&listener, DEFAULT_WINDOW, args.link_delay).map_err(|e| format!("link cable handshake failed: {}", e))?)
+	} else if let Some(addr) = &args.link_connect {
+		Some(TcpLink::connect(addr).map_err(|e| format!("unable to connect a link cable to {}: {}", addr, e))?)
+	} else {
+		None
+	};
+
+	if let Some(link) = &mut link {
+		link.attach(&mut gb);
+	}
+
+	let mut events = events.into_iter().peekable();
+
+	let mut frame: u64 = 0;
+
+	loop {
+
+		while let Some(event) = events.next_if(|event| event.frame <= frame) {
+			match event.pressed {
+				true => gb.btn_down(event.btn),
+				false => gb.btn_up(event.btn),
+			}
+		}
+
+		// a --cycles run stops on the cycle count, so the last stretch doesn't run to the end of the frame
+		let cycles_left = args.cycles.map(|cycles| cycles.saturating_sub(gb.cycles)).filter(|&left| left < FRAME_CYCLES);
+
+		let result = match (&mut link, cycles_left) {
+			(Some(link), Some(left)) => link.run_cycles(&mut gb, left),
+			(Some(link), None) => link.run_frame(&mut gb),
+			(None, Some(left)) => Ok(gb.run_cycles(left)),
+			(None, None) => Ok(gb.run_frame()),
+		}.map_err(|e| format!("link cable error: {}", e))?;
+
+		if args.log_rumble {
+			for &(cycle, on) in &result.rumble_changes {
+				println!("[{:.6}s] rumble {} (frame {}, cycle {})", cycle as f64 / CYCLES_PER_SECOND, if on { "on" } else { "off" }, frame, cycle);
+			}
+		}
+
+		frame += result.frame as u64;
+
+		match args.cycles {
+			Some(cycles) if gb.cycles >= cycles => break,
+			None if frame >= args.frames => break,
+			_ => {}
+		}
+
+	}
+
+	println!("ran {} frames ({} cycles)", frame, gb.cycles);
+
+	if let Some(path) = &args.png_path {
+		// SGB games are saved with their border
+		match gb.sgb_frame() {
+			Some(frame) => output::write_png(path, &frame, SGB_WIDTH as u32, SGB_HEIGHT as u32)?,
+			None => output::write_png(path, &gb.bus().ppu.get_frame(), 160, 144)?,
+		}
+	}
+
+	if let Some(path) = &args.wav_path {
+		output::write_wav(path, &samples.lock().unwrap())?;
+	}
+
+	Ok(())
+
+}
+
+fn main() -> ExitCode {
+
+	let args = match parse_args() {
+		Ok(args) => args,
+		Err(e) => {
+			if !e.is_empty() {
+				eprintln!("[ERROR] {}", e);
+			}
+
+			eprintln!("{}", USAGE);
+
+			return ExitCode::FAILURE;
+		}
+	};
+
+	if let Err(e) = run(args) {
+		eprintln!("[ERROR] {}", e);
+
+		return ExitCode::FAILURE;
+	}
+
+	ExitCode::SUCCESS
+
+}
\ No newline at end of file
@@ -0,0 +1,43 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use emu::apu::SAMPLE_RATE;
+use emu::ppu::rgb555_to_rgb888;
+
+// frame pixels are 15-bit RGB, as produced by the PPU and SGB
+pub fn write_png(path: &str, frame: &[u16], width: u32, height: u32) -> Result<(), String> {
+
+	let file = File::create(path).map_err(|e| format!("unable to create {}: {}", path, e))?;
+
+	let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+
+	let data: Vec<u8> = frame.iter().flat_map(|pixel| rgb555_to_rgb888(*pixel)).collect();
+
+	let mut writer = encoder.write_header().map_err(|e| format!("unable to write {}: {}", path, e))?;
+	writer.write_image_data(&data).map_err(|e| format!("unable to write {}: {}", path, e))?;
+
+	Ok(())
+
+}
+
+// samples are interleaved stereo, as produced by the APU
+pub fn write_wav(path: &str, samples: &[f32]) -> Result<(), String> {
+
+	let spec = hound::WavSpec {
+		channels: 2,
+		sample_rate: SAMPLE_RATE as u32,
+		bits_per_sample: 16,
+		sample_format: hound::SampleFormat::Int,
+	};
+
+	let mut writer = hound::WavWriter::create(path, spec).map_err(|e| format!("unable to create {}: {}", path, e))?;
+
+	for sample in samples {
+		writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).map_err(|e| format!("unable to write {}: {}", path, e))?;
+	}
+
+	writer.finalize().map_err(|e| format!("unable to write {}: {}", path, e))
+
+}
\ No newline at end of file